@@ -0,0 +1,2632 @@
+//! The commands of the command line tool, built on the `fab_scrapper` library.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Local, NaiveDate};
+use clap::{CommandFactory, FromArgMatches};
+use tracing::{debug, error, info, info_span, warn};
+use fab_scrapper::FabClient;
+use fab_scrapper::api::{self, ApiSource, ApiUnreachable, SetTiming};
+use fab_scrapper::completeness::{self, Report};
+use fab_scrapper::diff::{self, Changelog, SetChanges};
+use fab_scrapper::events::{Event, EventLog};
+use fab_scrapper::export::card_pool::{self, PoolHero};
+use fab_scrapper::export::features::{self, WordOptions};
+use fab_scrapper::export::{self, ExportFormat};
+use fab_scrapper::filter::CardFilter;
+use fab_scrapper::images::{self, ImageSummary};
+use fab_scrapper::mirror::{self, Endpoint};
+use fab_scrapper::model::{Card, SetInfo, SetResponse};
+use fab_scrapper::notify::{Notifier, Webhook, WebhookKind};
+use fab_scrapper::plan::{FetchPlan, PlannedAction, SetPlan, SetState};
+use fab_scrapper::policy::{FailurePolicy, FailureStage};
+use fab_scrapper::report::{RUN_REPORT_FILENAME, RunReport, SetReport, SetStatus};
+use fab_scrapper::prices::{self, PriceClient, PriceStorage};
+use fab_scrapper::promo::{self, PromoSelection};
+use fab_scrapper::reminder;
+use fab_scrapper::retry::RetryPolicy;
+use fab_scrapper::storage::archive::{self, ARCHIVE_DIR};
+use fab_scrapper::storage::backend::{self, Storage};
+use fab_scrapper::storage::compression::{self, Compression};
+use fab_scrapper::storage::http_cache::{HTTP_CACHE_DIR, HttpCache};
+use fab_scrapper::storage::lock::OutputLock;
+use fab_scrapper::storage::manifest::Manifest;
+use fab_scrapper::storage::master_index;
+use fab_scrapper::storage::name_index::{self, NameIndex};
+use fab_scrapper::storage::run_state::RunState;
+use fab_scrapper::storage::search_index::SearchIndex;
+use fab_scrapper::storage::snapshot;
+use fab_scrapper::stats;
+use fab_scrapper::storage::{self, OutputFormat, PREVIEWS_DIR};
+use fab_scrapper::suggest;
+use fab_scrapper::template::CardTemplate;
+use fab_scrapper::validate::{self, ValidationReport};
+
+use crate::cli::{
+    BatchArgs, CardCommand, CardPoolArgs, CardShowArgs, CatalogExportArgs, CleanArgs, Cli,
+    CombineArgs, Command, CompletenessArgs, DiffArgs, ExportCommand, FeaturesArgs, FetchArgs,
+    FilterArgs, ImagesArgs, MatchupsArgs, MirrorArgs, OutputArgs, ReprintsArgs, SET_CODES_FILENAME,
+    SearchArgs, SetsCommand, SetsListArgs, StatsArgs, ValidateArgs, WatchArgs,
+};
+use crate::config::{CONFIG_FILENAME, Config};
+use crate::display;
+use crate::logging::{self, DownloadProgress};
+use crate::memory::MemoryProfiler;
+use crate::pipeline::{
+    FetchContext, Pipeline, PipelineResults, ProcessedSet, SetOutcome, outputs_exist,
+};
+
+// Number of slowest sets listed in the timing summary
+const SLOWEST_SETS_REPORTED: usize = 3;
+
+// Name of the JSON changelog written by `diff`, inside the output directory
+const CHANGELOG_FILENAME: &str = "changelog.json";
+
+// Environment variable holding the pricing API key when --prices-api-key is not given
+const PRICES_API_KEY_VAR: &str = "FAB_PRICES_API_KEY";
+
+/// Builds a summary of where the run spent its time, listing the slowest sets first.
+///
+/// # Arguments
+/// * `timings` - The timings recorded for each processed set.
+///
+/// # Returns
+/// A multi-line summary suitable for the console and the metadata file.
+fn summarize_timings(timings: &[SetTiming]) -> String {
+    let request: Duration = timings.iter().map(|t| t.request).sum();
+    let body: Duration = timings.iter().map(|t| t.body).sum();
+    let parse: Duration = timings.iter().map(|t| t.parse).sum();
+    let write: Duration = timings.iter().map(|t| t.write).sum();
+
+    let mut summary = format!(
+        "Time in requests (network/API): {:.2}s\n\
+        Time reading bodies (transfer): {:.2}s\n\
+        Time parsing responses (JSON): {:.2}s\n\
+        Time writing files (disk): {:.2}s\n\
+        Slowest Sets:\n",
+        request.as_secs_f64(),
+        body.as_secs_f64(),
+        parse.as_secs_f64(),
+        write.as_secs_f64()
+    );
+
+    let mut slowest: Vec<&SetTiming> = timings.iter().collect();
+    slowest.sort_by_key(|t| std::cmp::Reverse(t.total()));
+    for timing in slowest.iter().take(SLOWEST_SETS_REPORTED) {
+        summary.push_str(&format!("- {}\n", timing.describe()));
+    }
+    summary
+}
+
+/// Whether every combined file of the requested formats exists in an output directory.
+fn combined_files_exist(output: &OutputArgs) -> bool {
+    output.formats.iter().all(|format| {
+        compression::find(&storage::combined_filename(&output.out, *format)).is_some()
+    })
+}
+
+/// Fetches every requested set and writes the per-set, combined and metadata files, then
+/// the run report, also when the run fails.
+///
+/// # Arguments
+/// * `args` - The options of the `fetch` command.
+/// * `http` - The HTTP client to send the requests with.
+/// * `source` - Where the API responses come from: the API or recorded fixtures.
+/// * `memory_profiler` - Records allocations of each stage when profiling is enabled.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn run_fetch(
+    args: &FetchArgs,
+    http: &reqwest::blocking::Client,
+    source: &ApiSource,
+    memory_profiler: &MemoryProfiler,
+) -> Result<(), Box<dyn Error>> {
+    if args.dry_run {
+        return plan_fetch(args, http, source);
+    }
+    let started_at = Local::now();
+    let output = &args.output;
+    let policy = FailurePolicy::new(&args.failures.fail_closed, args.failures.strict);
+    let _lock = OutputLock::acquire(&output.out)?;
+
+    let mut set_reports = Vec::new();
+    let result = fetch_sets(args, http, source, memory_profiler, &policy, &mut set_reports);
+    let failures = policy.failures();
+    let error = result.as_ref().err().map(|e| &**e);
+    let report = RunReport::new(started_at, set_reports, &failures, error);
+    if !report.sets.is_empty() {
+        print!("{}", display::render_run_summary(&report.sets));
+    }
+    match report.write(&output.out) {
+        Ok(()) => info!("Run report: {}/{}", output.out, RUN_REPORT_FILENAME),
+        Err(e) => warn!("Could not save the run report in {}: {}", output.out, e),
+    }
+    result?;
+    if args.fail_on_any_error && !failures.is_empty() {
+        return Err(format!(
+            "Error: {} failure(s) during the run (--fail-on-any-error); see {}/{}.",
+            failures.len(),
+            output.out,
+            RUN_REPORT_FILENAME
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Tells what is left to work with when the API is down: the sets fetched by earlier runs,
+/// which the run leaves untouched.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `outage` - Why the API is considered unreachable.
+fn report_outage(base_output_dir: &str, outage: &ApiUnreachable) {
+    error!("{}. Nothing was fetched.", outage);
+    let manifest = Manifest::load(base_output_dir).unwrap_or_default();
+    match manifest.sets.values().map(|entry| entry.fetched_at.as_str()).max() {
+        Some(latest) => warn!(
+            "Working offline: the {} set(s) in {} are kept as they were, last fetched at {}.",
+            manifest.sets.len(),
+            base_output_dir,
+            latest
+        ),
+        None => warn!("There is no earlier data in {} to fall back on.", base_output_dir),
+    }
+}
+
+/// The template of the `txt` files of a run: read from `--txt-template-file`, given with
+/// `--txt-template`, or else the default one.
+///
+/// # Returns
+/// A `Result` containing the parsed template, or an error if it cannot be read or parsed.
+fn txt_template(output: &OutputArgs) -> Result<CardTemplate, Box<dyn Error>> {
+    let source = match (&output.txt_template_file, &output.txt_template) {
+        (Some(path), _) => fs::read_to_string(path)
+            .map_err(|e| format!("Error: Could not read template file '{}': {}", path, e))?,
+        (None, Some(template)) => template.clone(),
+        (None, None) => return Ok(CardTemplate::default()),
+    };
+    CardTemplate::parse(source.trim_end_matches('\n'))
+}
+
+/// The directory of the HTTP cache of a fetch.
+fn http_cache_dir(args: &FetchArgs) -> PathBuf {
+    match &args.http_cache_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(&args.output.out).join(HTTP_CACHE_DIR),
+    }
+}
+
+/// Prints what a fetch would do, without requesting any card data or writing anything: how
+/// every set would be fetched, and the requests, downloads and disk space the run would take.
+///
+/// # Arguments
+/// * `args` - The options of the `fetch` command.
+/// * `http` - The HTTP client sets are discovered with for `--all`.
+/// * `source` - Where the API responses come from: the API or recorded fixtures.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn plan_fetch(
+    args: &FetchArgs,
+    http: &reqwest::blocking::Client,
+    source: &ApiSource,
+) -> Result<(), Box<dyn Error>> {
+    let output = &args.output;
+    if !args.languages.is_empty() || !card_filter(&args.filter).is_empty() {
+        return Err("Error: --dry-run only plans full fetches; leave out --lang and filters \
+                    such as --class."
+            .into());
+    }
+    let mut client = source
+        .client(http.clone())
+        .rate_limit(Duration::from_millis(args.delay_ms), args.burst)
+        .retry(RetryPolicy::new(args.retries, Duration::from_millis(args.retry_delay_ms)));
+    // The cache is only read, so a missing one is not created
+    let cache_dir = http_cache_dir(args);
+    if args.http_cache && cache_dir.is_dir() {
+        client = client.cache(HttpCache::open(&cache_dir)?);
+    }
+    let resumed = if args.resume {
+        let state = RunState::load(&output.out)?.ok_or_else(|| {
+            format!("Error: No interrupted run to resume in '{}'.", output.out)
+        })?;
+        Some(state)
+    } else {
+        None
+    };
+
+    let set_codes = resolve_set_codes(args, &client, resumed.as_ref(), false)?.set_codes;
+    if set_codes.is_empty() {
+        info!("No set codes to process. Exiting.");
+        return Ok(());
+    }
+    let manifest = Manifest::load(&output.out)?;
+    let mut plan = FetchPlan::default();
+    for set_code in &set_codes {
+        let previous = manifest.sets.get(set_code);
+        let existing = storage::read_existing_set(&output.out, set_code).ok();
+        let missing_images = existing
+            .as_deref()
+            .filter(|_| args.images)
+            .and_then(|json_content| serde_json::from_str::<SetResponse>(json_content).ok())
+            .map(|set| {
+                let mut seen = HashSet::new();
+                set.results
+                    .iter()
+                    .filter(|card| seen.insert(card.card_id.as_str()))
+                    .filter(|card| {
+                        images::image_on_disk(&output.out, set_code, card) == Some(false)
+                    })
+                    .count()
+            });
+        plan.sets.push(SetPlan::new(SetState {
+            set_code,
+            resumed: existing.is_some()
+                && resumed.as_ref().is_some_and(|state| state.completed.contains(set_code)),
+            previous,
+            conditional: args.incremental
+                && outputs_exist(output, set_code)
+                && previous.is_some_and(|entry| !entry.validators.is_empty()),
+            cached: client.cached_set_pages(set_code),
+            previous_size: existing.as_ref().map(|json_content| json_content.len() as u64),
+            missing_images,
+        }));
+    }
+    let image_sizes: Vec<u64> = manifest.images.values().map(|image| image.size).collect();
+    plan.average_image_size = (!image_sizes.is_empty())
+        .then(|| image_sizes.iter().sum::<u64>() / image_sizes.len() as u64);
+
+    print!("{}", display::render_fetch_plan(&plan, Duration::from_millis(args.delay_ms)));
+    let to_fetch: Vec<String> = plan
+        .sets
+        .iter()
+        .filter(|set| set.action != PlannedAction::Resumed)
+        .map(|set| set.set_code.clone())
+        .collect();
+    let mut required_space =
+        storage::estimate_required_space(&to_fetch, &output.out, &output.formats);
+    if args.images {
+        required_space += storage::estimate_image_space(&to_fetch, &output.out, &manifest);
+    }
+    let existing_dir = Path::new(&output.out).ancestors().find(|dir| dir.is_dir());
+    match existing_dir.map(fs2::available_space) {
+        Some(Ok(available)) => println!(
+            "Disk space: about {} MiB required, {} MiB available",
+            required_space.div_ceil(1024 * 1024),
+            available / (1024 * 1024)
+        ),
+        _ => println!("Disk space: about {} MiB required", required_space.div_ceil(1024 * 1024)),
+    }
+    info!("Dry run: nothing was fetched or written.");
+    Ok(())
+}
+
+/// What a fetch run has set up before downloading any set.
+struct PreparedFetch {
+    /// When the run started.
+    script_launch_time: DateTime<Local>,
+    /// The API client to send the requests with.
+    client: FabClient,
+    /// Shows the downloads and the write queue.
+    progress: Arc<DownloadProgress>,
+    /// The template of every card in the `txt` files.
+    txt_template: CardTemplate,
+    /// The webhooks to notify of the changes.
+    notifier: Notifier,
+    /// Where the output is uploaded at the end of the run, with `--upload`.
+    upload_storage: Option<Box<dyn Storage>>,
+    /// Where prices are taken from, with `--prices`.
+    price_client: Option<PriceClient>,
+    /// Every set of the run, in order.
+    set_codes: Vec<String>,
+    /// The sets left to fetch; the others were completed by the interrupted run.
+    to_fetch: Vec<String>,
+    /// The JSON of every set completed by the interrupted run, keyed by set code.
+    previously_fetched: HashMap<String, String>,
+    /// What the previous runs recorded.
+    manifest: Manifest,
+    /// Whether no set was fetched into the output directory before.
+    first_fetch: bool,
+    /// The release day of every set released after today, keyed by set code.
+    previews: BTreeMap<String, NaiveDate>,
+    /// The output settings of the sets released after today, in `previews/`.
+    preview_output: OutputArgs,
+    /// The sets completed so far, saved for `--resume`.
+    run_state: RunState,
+    /// Receives every change made to the dataset.
+    events: EventLog,
+}
+
+/// The body of `run_fetch`, run while the output directory is locked.
+///
+/// # Arguments
+/// * `args` - The options of the `fetch` command.
+/// * `http` - The HTTP client to send the requests with.
+/// * `source` - Where the API responses come from: the API or recorded fixtures.
+/// * `memory_profiler` - Records allocations of each stage when profiling is enabled.
+/// * `policy` - Decides which failures stop the run, and records all of them.
+/// * `set_reports` - Receives what happened to every set, for the run report.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn fetch_sets(
+    args: &FetchArgs,
+    http: &reqwest::blocking::Client,
+    source: &ApiSource,
+    memory_profiler: &MemoryProfiler,
+    policy: &FailurePolicy,
+    set_reports: &mut Vec<SetReport>,
+) -> Result<(), Box<dyn Error>> {
+    let Some(mut run) = prepare_fetch(args, http, source)? else {
+        return Ok(());
+    };
+    let fetched = fetch_prepared_sets(args, &mut run, memory_profiler, policy);
+    finalize_fetch(args, run, fetched, memory_profiler, policy, set_reports)
+}
+
+/// Sets up a fetch run: resolves its sets, checks the API and the disk space, and creates the
+/// output directories. Localized and filtered fetches are run here, as they skip the pipeline.
+///
+/// # Arguments
+/// * `args` - The options of the `fetch` command.
+/// * `http` - The HTTP client to send the requests with.
+/// * `source` - Where the API responses come from: the API or recorded fixtures.
+///
+/// # Returns
+/// A `Result` containing the prepared run, `None` if there is nothing left to fetch, or an
+/// error.
+fn prepare_fetch(
+    args: &FetchArgs,
+    http: &reqwest::blocking::Client,
+    source: &ApiSource,
+) -> Result<Option<PreparedFetch>, Box<dyn Error>> {
+    let script_launch_time: DateTime<Local> = Local::now();
+    let output = &args.output;
+
+    let progress = Arc::new(DownloadProgress::new());
+    let mut client = source
+        .client(http.clone())
+        .rate_limit(Duration::from_millis(args.delay_ms), args.burst)
+        .retry(RetryPolicy::new(args.retries, Duration::from_millis(args.retry_delay_ms)))
+        .progress(progress.clone());
+    if args.http_cache {
+        client = client.cache(HttpCache::open(http_cache_dir(args))?);
+    }
+    let txt_template = txt_template(output)?;
+    let notifier = notifier(args, http);
+    // A destination that cannot be opened stops the run before anything is fetched
+    let upload_storage = args.upload.as_deref().map(|dest| backend::open(dest, http)).transpose()?;
+
+    // An outage stops the run once, instead of failing every set after all of its retries
+    if !args.no_health_check
+        && let Err(e) = client.probe()
+    {
+        report_outage(&output.out, &e);
+        return Err(e.into());
+    }
+    // The command line requires --prices-url with --prices
+    let price_client = args.prices.zip(args.prices_url.as_deref()).map(|(provider, url)| {
+        let api_key =
+            args.prices_api_key.clone().or_else(|| std::env::var(PRICES_API_KEY_VAR).ok());
+        PriceClient::new(http.clone(), provider, url).api_key(api_key)
+    });
+
+    // An interrupted run is resumed with its own list of sets
+    let resumed = if args.resume {
+        let state = RunState::load(&output.out)?.ok_or_else(|| {
+            format!("Error: No interrupted run to resume in '{}'.", output.out)
+        })?;
+        info!(
+            "Resuming the run started at {} ({} of {} sets already fetched).",
+            state.started_at,
+            state.completed.len(),
+            state.set_codes.len()
+        );
+        Some(state)
+    } else {
+        None
+    };
+
+    let ResolvedSets { set_codes, discovered } =
+        resolve_set_codes(args, &client, resumed.as_ref(), true)?;
+
+    if set_codes.is_empty() {
+        info!("No set codes to process. Exiting.");
+        return Ok(None);
+    }
+
+    info!("Found {} set codes to process.", set_codes.len());
+    if !args.sets.is_empty() {
+        warn_unknown_set_codes(&set_codes, &args.sets_file, &output.out);
+    }
+
+    let filter = card_filter(&args.filter);
+    if !args.export.is_empty() && (!args.languages.is_empty() || !filter.is_empty()) {
+        warn!("Skipping --export: exports are built from the full per-set data only.");
+    }
+    if args.archive && (!args.languages.is_empty() || !filter.is_empty()) {
+        warn!("Skipping --archive: only full fetches are archived.");
+    }
+    if !args.languages.is_empty() {
+        if !filter.is_empty() {
+            return Err(
+                "Error: --lang cannot be combined with filters such as --class or --query.".into()
+            );
+        }
+        return run_localized_fetch(args, &client, &set_codes, &txt_template).map(|()| None);
+    }
+    if !filter.is_empty() {
+        return run_filtered_fetch(args, &client, &set_codes, &filter, &txt_template)
+            .map(|()| None);
+    }
+    if args.filter.filter_name.is_some() {
+        return Err("Error: --filter-name needs a filter such as --class or --query.".into());
+    }
+
+    // Sets released after today are written to their own folder, out of the main dataset
+    let manifest = Manifest::load(&output.out)?;
+    let first_fetch = manifest.sets.is_empty();
+    let previews = if args.include_previews {
+        BTreeMap::new()
+    } else {
+        find_previews(&client, discovered, &set_codes, &manifest)
+    };
+    let preview_output =
+        OutputArgs { out: format!("{}/{}", output.out, PREVIEWS_DIR), ..output.clone() };
+    let output_of = |set_code: &str| {
+        if previews.contains_key(set_code) { &preview_output } else { output }
+    };
+    for (set_code, day) in &previews {
+        info!("Set {} is released on {}; writing it to {}.", set_code, day, preview_output.out);
+    }
+
+    // Create directories for output files if they don't exist
+    storage::create_output_dirs(&output.out, &output.formats)?;
+    if !previews.is_empty() {
+        storage::create_output_dirs(&preview_output.out, &output.formats)?;
+    }
+
+    // Sets completed by the interrupted run are reused, as long as their output is still there
+    let mut run_state =
+        resumed.unwrap_or_else(|| RunState::new(script_launch_time.to_rfc3339(), set_codes.clone()));
+    let mut previously_fetched: HashMap<String, String> = HashMap::new();
+    for set_code in &set_codes {
+        if run_state.completed.contains(set_code) {
+            match storage::read_existing_set(&output_of(set_code).out, set_code) {
+                Ok(json_content) => {
+                    previously_fetched.insert(set_code.clone(), json_content);
+                }
+                Err(_) => {
+                    run_state.completed.remove(set_code);
+                }
+            }
+        }
+    }
+    let to_fetch = run_state.remaining();
+    if let Err(e) = run_state.save(&output.out) {
+        warn!("Could not save run state in {}: {}", output.out, e);
+    }
+
+    // Make sure the run can complete before fetching or clearing anything
+    let mut required_space =
+        storage::estimate_required_space(&to_fetch, &output.out, &output.formats);
+    if args.images {
+        required_space += storage::estimate_image_space(&to_fetch, &output.out, &manifest);
+    }
+    if let Err(e) = storage::check_disk_space(&output.out, required_space) {
+        error!("{}", e);
+        error!("Free up space or choose another location before running again.");
+        return Err(e);
+    }
+    info!(
+        "Disk space check passed (about {} MiB required).",
+        required_space.div_ceil(1024 * 1024)
+    );
+
+    // The snapshot collects what this run replaces; a resumed run adds to its own
+    if !args.resume
+        && let Err(e) = snapshot::clear(&output.out)
+    {
+        warn!("Could not clear the previous snapshot in {}: {}", output.out, e);
+    }
+
+    let events = if args.events { EventLog::open(&output.out)? } else { EventLog::disabled() };
+    Ok(Some(PreparedFetch {
+        script_launch_time,
+        client,
+        progress,
+        txt_template,
+        notifier,
+        upload_storage,
+        price_client,
+        set_codes,
+        to_fetch,
+        previously_fetched,
+        manifest,
+        first_fetch,
+        previews,
+        preview_output,
+        run_state,
+        events,
+    }))
+}
+
+/// Downloads and writes the sets of a prepared run in the fetch pipeline.
+///
+/// # Arguments
+/// * `args` - The options of the `fetch` command.
+/// * `run` - The prepared run, whose run state records the completed sets.
+/// * `memory_profiler` - Records allocations of each stage when profiling is enabled.
+/// * `policy` - Decides which failures stop the workers.
+///
+/// # Returns
+/// The outcome of every set, and how long both halves of the pipeline waited.
+fn fetch_prepared_sets(
+    args: &FetchArgs,
+    run: &mut PreparedFetch,
+    memory_profiler: &MemoryProfiler,
+    policy: &FailurePolicy,
+) -> PipelineResults {
+    let context = FetchContext {
+        client: &run.client,
+        output: &args.output,
+        incremental: args.incremental,
+        reminder_text: args.reminder_text,
+        inline_prices: run
+            .price_client
+            .as_ref()
+            .filter(|_| args.prices_storage == PriceStorage::Inline),
+        memory_profiler,
+        events: &run.events,
+        txt_template: &run.txt_template,
+        corrupt_sets: &run.manifest.corrupt_sets,
+    };
+    let preview_context = FetchContext { output: &run.preview_output, ..context };
+    let previews = &run.previews;
+    let context_of = |set_code: &str| {
+        if previews.contains_key(set_code) { &preview_context } else { &context }
+    };
+    let pipeline = Pipeline {
+        concurrency: args.concurrency,
+        write_queue: args.write_queue,
+        fail_fast: args.fail_fast,
+        policy,
+        progress: &run.progress,
+        out: &args.output.out,
+    };
+    pipeline.run(&run.to_fetch, &run.manifest.sets, context_of, &mut run.run_state)
+}
+
+/// Records what happened to every set of a run, then downloads the images and writes the
+/// combined files, the indexes, exports and metadata, before archiving, uploading and
+/// notifying the webhooks.
+///
+/// # Arguments
+/// * `args` - The options of the `fetch` command.
+/// * `run` - The prepared run.
+/// * `fetched` - What the pipeline did with the sets of the run.
+/// * `memory_profiler` - Records allocations of each stage when profiling is enabled.
+/// * `policy` - Decides which failures stop the run, and records all of them.
+/// * `set_reports` - Receives what happened to every set, for the run report.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn finalize_fetch(
+    args: &FetchArgs,
+    mut run: PreparedFetch,
+    fetched: PipelineResults,
+    memory_profiler: &MemoryProfiler,
+    policy: &FailurePolicy,
+    set_reports: &mut Vec<SetReport>,
+) -> Result<(), Box<dyn Error>> {
+    let RecordedSets {
+        all_sets_data,
+        corrupt_sets,
+        unchanged_sets,
+        timings,
+        mut fatal,
+        set_changes,
+        released_sets,
+    } = record_outcomes(args, &mut run, fetched.processed, policy, set_reports);
+    let PreparedFetch {
+        script_launch_time,
+        client,
+        txt_template,
+        notifier,
+        upload_storage,
+        price_client,
+        set_codes,
+        mut manifest,
+        first_fetch,
+        previews,
+        preview_output,
+        run_state,
+        events,
+        ..
+    } = run;
+    let output = &args.output;
+
+    // A released set's files in the previews folder are replaced by those just written
+    let last_preview = !manifest.sets.values().any(|entry| entry.preview_until.is_some());
+    for set_code in &released_sets {
+        info!(
+            "Set {} is released; moved it from {} into the main dataset.",
+            set_code, preview_output.out
+        );
+        if let Err(e) = remove_preview(&preview_output.out, set_code, last_preview) {
+            warn!("Could not remove the preview of set {}: {}", set_code, e);
+        }
+    }
+
+    // Anomalies are caught before any of the sets reaches the combined files
+    if fatal.is_none() && !all_sets_data.is_empty() {
+        for message in validate_fetched_sets(&output.out, &all_sets_data) {
+            if let Err(e) = policy.check(FailureStage::Validate, &message) {
+                fatal.get_or_insert(e);
+            }
+        }
+    }
+
+    // Snapshots are refreshed for every set, including those whose cards did not change
+    if let Some(price_client) = &price_client
+        && args.prices_storage == PriceStorage::Snapshot
+        && fatal.is_none()
+    {
+        for message in write_price_snapshots(price_client, &output.out, &all_sets_data, &events) {
+            if let Err(e) = policy.check(FailureStage::Prices, &message) {
+                fatal.get_or_insert(e);
+            }
+        }
+    }
+
+    if args.images && fatal.is_none() {
+        let images_stage = memory_profiler.start();
+        let failure = match storage::parse_all_sets(&all_sets_data) {
+            Ok(sets) => {
+                let sets = sets.iter().map(|(set_code, set)| (*set_code, set));
+                let (summary, per_set) =
+                    download_images(&client, &output.out, sets, &mut manifest, &events);
+                for set_report in set_reports.iter_mut() {
+                    if let Some(images) = per_set.get(set_report.set_code.as_str()) {
+                        set_report.images = Some(images.downloaded);
+                    }
+                }
+                (summary.failed > 0)
+                    .then(|| format!("{} image(s) could not be downloaded", summary.failed))
+            }
+            Err(e) => Some(format!("Could not read fetched sets for image downloads: {}", e)),
+        };
+        memory_profiler.finish("images", images_stage);
+        if let Some(message) = failure
+            && let Err(e) = policy.check(FailureStage::Images, &message)
+        {
+            fatal = Some(e);
+        }
+    }
+
+    // The files written so far are recorded even when the run is about to stop
+    if let Err(e) = manifest.save(&output.out) {
+        let message = format!("Could not save manifest in {}: {}", output.out, e);
+        if let Err(e) = policy.check(FailureStage::Manifest, &message) {
+            fatal.get_or_insert(e);
+        }
+    }
+
+    // Keep the run state around for --resume until every set has been fetched
+    let missing = run_state.remaining();
+    if missing.is_empty() {
+        if let Err(e) = RunState::remove(&output.out) {
+            warn!("Could not remove run state in {}: {}", output.out, e);
+        }
+    } else {
+        warn!(
+            "{} set(s) were not fetched ({}). Run again with --resume to retry only those.",
+            missing.len(),
+            missing.join(", ")
+        );
+    }
+    if let Some(e) = fatal {
+        return Err(e);
+    }
+
+    // Previews have combined files of their own, and stay out of the indexes and exports
+    let sets_processed = all_sets_data.len();
+    let (preview_sets_data, main_sets_data): (HashMap<String, String>, HashMap<String, String>) =
+        all_sets_data.into_iter().partition(|(set_code, _)| previews.contains_key(set_code));
+
+    // Create the combined files with all sets data, unless nothing changed at all
+    let combine_stage = memory_profiler.start();
+    let unchanged_in = |sets_data: &HashMap<String, String>| {
+        unchanged_sets.iter().filter(|set_code| sets_data.contains_key(*set_code)).count()
+            == sets_data.len()
+    };
+    let nothing_changed = unchanged_in(&main_sets_data);
+    let previews_unchanged = unchanged_in(&preview_sets_data);
+    let to_combine = [
+        (output, &main_sets_data, nothing_changed),
+        (&preview_output, &preview_sets_data, previews_unchanged),
+    ];
+    for (base, sets_data, unchanged) in to_combine {
+        if sets_data.is_empty() {
+            continue;
+        }
+        if unchanged && combined_files_exist(base) {
+            info!(
+                "No set changed since the last run; keeping existing combined files in '{}'.",
+                base.out
+            );
+            continue;
+        }
+        for message in storage::write_combined_files(
+            &base.out,
+            &output.formats,
+            sets_data,
+            output.pretty,
+            output.compress,
+            &txt_template,
+            &events,
+        ) {
+            policy.check(FailureStage::Combine, &message)?;
+        }
+    }
+    let indexes_exist = [name_index::NAME_INDEX_FILENAME, master_index::MASTER_INDEX_FILENAME]
+        .iter()
+        .all(|filename| Path::new(&output.out).join(filename).exists());
+    if !nothing_changed || !indexes_exist {
+        write_card_indexes(&output.out);
+    }
+    let exports: Vec<ExportFormat> = args
+        .export
+        .iter()
+        .copied()
+        .filter(|format| !nothing_changed || !Path::new(&format.filename(&output.out)).exists())
+        .collect();
+    write_exports(&output.out, &exports, policy)?;
+    memory_profiler.finish("combine", combine_stage);
+
+    let timing_summary = summarize_timings(&timings) + &fetched.waits.describe();
+    let mut output_structure: String = output
+        .formats
+        .iter()
+        .map(|format| format!("- {}\n", storage::output_location(&output.out, *format)))
+        .collect();
+    if !previews.is_empty() {
+        output_structure.push_str(&format!("- Previews: {}/\n", preview_output.out));
+    }
+
+    // Create metadata file with script info
+    let preview_list = if previews.is_empty() {
+        String::from("none")
+    } else {
+        let previews: Vec<String> = previews
+            .iter()
+            .map(|(set_code, day)| format!("{} (releases {})", set_code, day))
+            .collect();
+        previews.join(", ")
+    };
+    let unknown_set = String::from("UNKNOWN");
+    let latest_set = set_codes.last().unwrap_or(&unknown_set);
+    let metadata_filename = format!("{}/script_metadata.txt", output.out);
+    let metadata_content = format!(
+        "FAB Card Scrapper - Script Execution Metadata\n\
+        =============================================\n\
+        Script Launch Time: {}\n\
+        Latest Set Processed: {}\n\
+        Total Sets Processed: {}\n\
+        Unchanged Sets Skipped: {}\n\
+        Corrupt Sets Skipped: {}\n\
+        Preview Sets: {}\n\
+        Sets List: {}\n\
+        Output Structure:\n\
+        {}\
+        Timings:\n\
+        {}",
+        script_launch_time.format("%Y-%m-%d %H:%M:%S %Z"),
+        latest_set,
+        sets_processed,
+        if unchanged_sets.is_empty() { String::from("none") } else { unchanged_sets.join(", ") },
+        if corrupt_sets.is_empty() { String::from("none") } else { corrupt_sets.join(", ") },
+        preview_list,
+        set_codes.join(", "),
+        output_structure,
+        timing_summary
+    );
+
+    if let Err(e) = storage::save_data_to_file(&metadata_filename, &metadata_content) {
+        let message = format!("Could not save metadata file {}: {}", metadata_filename, e);
+        policy.check(FailureStage::Metadata, &message)?;
+    } else {
+        info!("Created metadata file: {}", metadata_filename);
+    }
+
+    if let Err(e) = manifest.record_files(&output.out).and_then(|()| manifest.save(&output.out)) {
+        let message = format!("Could not record output files in the manifest: {}", e);
+        policy.check(FailureStage::Manifest, &message)?;
+    }
+
+    publish_run(
+        args,
+        script_launch_time,
+        upload_storage.as_deref(),
+        &notifier,
+        set_changes,
+        first_fetch,
+        policy,
+    )?;
+
+    info!(
+        "Finished processing all set codes. Files are organized in '{}' directory:\n{}  - Metadata: {}",
+        output.out,
+        output_structure.lines().map(|line| format!("  {}\n", line)).collect::<String>(),
+        metadata_filename
+    );
+    info!("Timing summary:\n{}", timing_summary);
+    if let Some(cache) = client.http_cache() {
+        info!("{}", cache.describe());
+    }
+    Ok(())
+}
+
+/// What became of the sets of a fetch run.
+struct RecordedSets {
+    /// The JSON of every set for the combined files, keyed by set code.
+    all_sets_data: HashMap<String, String>,
+    /// Sets whose downloads stayed corrupt after every re-fetch.
+    corrupt_sets: Vec<String>,
+    /// Sets left untouched because they did not change since the last run.
+    unchanged_sets: Vec<String>,
+    /// Timing breakdown for every set that was attempted.
+    timings: Vec<SetTiming>,
+    /// The first failure of a fail-closed stage, which ends the run.
+    fatal: Option<Box<dyn Error>>,
+    /// What changed in every set written, for the webhooks.
+    set_changes: Vec<SetChanges>,
+    /// Earlier previews released since, now written to the main dataset.
+    released_sets: Vec<String>,
+}
+
+/// Records the outcome of every set of a run in the manifest and the run report, and checks
+/// its failures against the failure policy.
+///
+/// # Arguments
+/// * `args` - The options of the `fetch` command.
+/// * `run` - The prepared run, whose manifest is updated.
+/// * `processed` - What the pipeline did with every set left to fetch, in order.
+/// * `policy` - Decides which failures stop the run, and records all of them.
+/// * `set_reports` - Receives what happened to every set, for the run report.
+///
+/// # Returns
+/// The sets to combine, what became of the others, and the failure that ends the run, if any.
+fn record_outcomes(
+    args: &FetchArgs,
+    run: &mut PreparedFetch,
+    processed: Vec<Option<ProcessedSet>>,
+    policy: &FailurePolicy,
+    set_reports: &mut Vec<SetReport>,
+) -> RecordedSets {
+    // Sets resumed from the interrupted run are combined with those fetched now
+    let mut recorded = RecordedSets {
+        all_sets_data: std::mem::take(&mut run.previously_fetched),
+        corrupt_sets: Vec::new(),
+        unchanged_sets: Vec::new(),
+        timings: Vec::new(),
+        fatal: None,
+        set_changes: Vec::new(),
+        released_sets: Vec::new(),
+    };
+
+    // Resumed sets were saved by the interrupted run
+    for set_code in run.set_codes.iter().filter(|code| recorded.all_sets_data.contains_key(*code)) {
+        let cards = run.manifest.sets.get(set_code).map(|entry| entry.card_count);
+        set_reports.push(SetReport::untouched(set_code, SetStatus::Resumed, cards));
+    }
+
+    for (set_code, processed) in run.to_fetch.iter().zip(processed) {
+        let Some(processed) = processed else {
+            set_reports.push(SetReport::untouched(set_code, SetStatus::Skipped, None));
+            continue;
+        };
+        let set_code = processed.timing.set_code.clone();
+        let (status, cards) = match &processed.outcome {
+            SetOutcome::Saved(_) => {
+                (SetStatus::Saved, processed.manifest_entry.as_ref().map(|entry| entry.card_count))
+            }
+            SetOutcome::Unchanged(_) => {
+                let cards = run.manifest.sets.get(&set_code).map(|entry| entry.card_count);
+                (SetStatus::Unchanged, cards)
+            }
+            SetOutcome::Corrupt(_) => (SetStatus::Corrupt, None),
+            SetOutcome::Failed => (SetStatus::Failed, None),
+        };
+        let mut set_report = SetReport::new(&processed.timing, status, cards, &processed.failures);
+        if let Some(changes) = &processed.changes {
+            set_report.new_cards = Some(changes.added.len());
+            set_report.changed_cards = Some(changes.changed.len() + changes.new_printings.len());
+        }
+        set_reports.push(set_report);
+        if args.fail_fast && matches!(status, SetStatus::Failed | SetStatus::Corrupt) {
+            recorded.fatal.get_or_insert_with(|| {
+                format!("Error: Stopped after set {} failed (--fail-fast).", set_code).into()
+            });
+        }
+        recorded.set_changes.extend(processed.changes);
+        match processed.outcome {
+            SetOutcome::Saved(json_content) => {
+                run.manifest.corrupt_sets.remove(&set_code);
+                recorded.all_sets_data.insert(set_code.clone(), json_content);
+            }
+            SetOutcome::Unchanged(json_content) => {
+                recorded.all_sets_data.insert(set_code.clone(), json_content);
+                recorded.unchanged_sets.push(set_code.clone());
+            }
+            SetOutcome::Corrupt(corrupt) => {
+                if let Some(corrupt) = corrupt {
+                    run.manifest.corrupt_sets.insert(set_code.clone(), corrupt);
+                }
+                recorded.corrupt_sets.push(set_code.clone());
+            }
+            SetOutcome::Failed => {}
+        }
+        if let Some(mut entry) = processed.manifest_entry {
+            let was_preview =
+                run.manifest.sets.get(&set_code).is_some_and(|entry| entry.preview_until.is_some());
+            entry.preview_until = run.previews.get(&set_code).map(|day| day.to_string());
+            if was_preview && entry.preview_until.is_none() {
+                recorded.released_sets.push(set_code.clone());
+            }
+            run.manifest.sets.insert(set_code, entry);
+        }
+        for (stage, message) in &processed.failures {
+            if let Err(e) = policy.check(*stage, message) {
+                recorded.fatal.get_or_insert(e);
+            }
+        }
+        recorded.timings.push(processed.timing);
+    }
+    recorded
+}
+
+/// Archives, uploads and announces the output of a finished run.
+///
+/// # Arguments
+/// * `args` - The options of the `fetch` command.
+/// * `script_launch_time` - When the run started, naming its archive.
+/// * `upload_storage` - Where the output is uploaded, with `--upload`.
+/// * `notifier` - The webhooks to notify of the changes.
+/// * `set_changes` - What changed in every set written.
+/// * `first_fetch` - Whether no set was fetched into the output directory before, in which
+///   case the webhooks are not notified.
+/// * `policy` - Decides which failures stop the run, and records all of them.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn publish_run(
+    args: &FetchArgs,
+    script_launch_time: DateTime<Local>,
+    upload_storage: Option<&dyn Storage>,
+    notifier: &Notifier,
+    set_changes: Vec<SetChanges>,
+    first_fetch: bool,
+    policy: &FailurePolicy,
+) -> Result<(), Box<dyn Error>> {
+    let output = &args.output;
+    // The archive is taken last, so it holds the final manifest and metadata
+    if args.archive {
+        let archive_dir = match &args.archive_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => Path::new(&output.out).join(ARCHIVE_DIR),
+        };
+        match archive::write(&output.out, &archive_dir, script_launch_time) {
+            Ok((path, files)) => info!("Archived {} file(s) to {}", files, path.display()),
+            Err(e) => {
+                let message = format!("Could not write the run archive: {}", e);
+                policy.check(FailureStage::Archive, &message)?;
+            }
+        }
+    }
+
+    if let Some(storage) = upload_storage {
+        match backend::upload(storage, &output.out) {
+            Ok(summary) => info!(
+                "Uploaded {} file(s) to {} ({} unchanged, {} removed)",
+                summary.uploaded,
+                storage.describe(),
+                summary.unchanged,
+                summary.deleted
+            ),
+            Err(e) => {
+                let message = format!("Could not upload to {}: {}", storage.describe(), e);
+                policy.check(FailureStage::Upload, &message)?;
+            }
+        }
+    }
+
+    // Webhooks hear of the changes once everything is published
+    if !notifier.is_empty() {
+        let changelog = Changelog { generated_at: Local::now().to_rfc3339(), sets: set_changes };
+        if changelog.sets.iter().all(SetChanges::is_empty) {
+            info!("No card changed; no webhook notified.");
+        } else if first_fetch {
+            info!("First fetch of '{}'; webhooks are notified from the next run on.", output.out);
+        } else {
+            let failures = notifier.notify(&changelog);
+            for message in &failures {
+                policy.check(FailureStage::Notify, message)?;
+            }
+            if failures.is_empty() {
+                info!("Notified {} webhook(s) of the changes.", notifier.len());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The webhooks a fetch notifies of its changes.
+fn notifier(args: &FetchArgs, http: &reqwest::blocking::Client) -> Notifier {
+    let webhooks = args
+        .webhooks
+        .iter()
+        .map(|url| Webhook { url: url.clone(), kind: WebhookKind::Json })
+        .chain(
+            args.discord_webhooks
+                .iter()
+                .map(|url| Webhook { url: url.clone(), kind: WebhookKind::Discord }),
+        )
+        .collect();
+    Notifier::new(http.clone(), webhooks)
+}
+
+/// Warns about set codes that appear neither in the set codes file nor in earlier fetches,
+/// suggesting the closest known codes. Unknown codes are still fetched, as they may be new.
+///
+/// # Arguments
+/// * `set_codes` - The set codes given on the command line.
+/// * `sets_file` - The set codes file.
+/// * `base_output_dir` - The root output directory, whose manifest lists earlier fetches.
+fn warn_unknown_set_codes(set_codes: &[String], sets_file: &str, base_output_dir: &str) {
+    let mut known: Vec<String> = storage::read_set_codes(sets_file).unwrap_or_default();
+    if let Ok(manifest) = Manifest::load(base_output_dir) {
+        known.extend(manifest.sets.into_keys());
+    }
+    if known.is_empty() {
+        return;
+    }
+
+    for set_code in set_codes {
+        if !known.iter().any(|code| code.eq_ignore_ascii_case(set_code)) {
+            warn!(
+                "Set code '{}' is not in {} or any earlier fetch.{}",
+                set_code,
+                sets_file,
+                suggest::did_you_mean(set_code, known.iter().map(String::as_str))
+            );
+        }
+    }
+}
+
+/// The sets a fetch covers.
+struct ResolvedSets {
+    /// The set codes in fetch order.
+    set_codes: Vec<String>,
+    /// The sets the API lists, when they were requested for `--all`.
+    discovered: Option<Vec<SetInfo>>,
+}
+
+/// Works out the sets a fetch covers: those of the resumed run, every set the API lists with
+/// `--all`, those given with `--sets`, or else those of the set codes file. Duplicates are
+/// dropped.
+///
+/// # Arguments
+/// * `args` - The options of the `fetch` command.
+/// * `client` - The client sets are discovered with for `--all`.
+/// * `resumed` - The state of the run being resumed, if any.
+/// * `update_sets_file` - Whether `--all` rewrites the set codes file with the discovered sets.
+///
+/// # Returns
+/// A `Result` containing the set codes in fetch order, or an error.
+fn resolve_set_codes(
+    args: &FetchArgs,
+    client: &FabClient,
+    resumed: Option<&RunState>,
+    update_sets_file: bool,
+) -> Result<ResolvedSets, Box<dyn Error>> {
+    let mut discovered_sets = None;
+    let mut set_codes = if let Some(state) = resumed {
+        state.set_codes.clone()
+    } else if args.all {
+        // Keep the local list in sync with the API, then fetch everything (or what is newer)
+        let discovered = client.discover_sets()?;
+        let all_codes: Vec<String> = discovered.iter().map(|set| set.code.clone()).collect();
+        if update_sets_file {
+            storage::write_set_codes(&args.sets_file, &all_codes)?;
+            info!("Updated {} with {} set codes.", args.sets_file, all_codes.len());
+        }
+
+        discovered_sets = Some(discovered.clone());
+        let selected = match &args.since {
+            Some(since) => api::sets_released_after(discovered, since)?,
+            None => discovered,
+        };
+        selected.into_iter().map(|set| set.code).collect()
+    } else if args.sets.is_empty() {
+        info!("Reading set codes from: {}", args.sets_file);
+        if args.sets_file == SET_CODES_FILENAME {
+            warn!(
+                "Reading the sets to fetch from {} is deprecated; list them as `sets = [...]` \
+                in {} instead.",
+                SET_CODES_FILENAME, CONFIG_FILENAME
+            );
+        }
+
+        // Read set codes from the file
+        match storage::read_set_codes(&args.sets_file) {
+            Ok(codes) => codes,
+            Err(e) => {
+                error!("{}", e);
+                error!("Please ensure '{}' exists in the same directory as the executable or in the project root if using 'cargo run'.", args.sets_file);
+                error!("The file should contain one set code per line (e.g., WTR, ARC), or pass --sets WTR,ARC or --all instead.");
+                return Err(e); // Propagate the error to stop execution
+            }
+        }
+    } else {
+        args.sets.iter().map(|code| code.trim().to_string()).filter(|code| !code.is_empty()).collect()
+    };
+
+    // Fetching a set twice would have two workers writing the same files
+    let mut seen = HashSet::new();
+    set_codes.retain(|code| seen.insert(code.clone()));
+    Ok(ResolvedSets { set_codes, discovered: discovered_sets })
+}
+
+/// Works out which sets of a fetch are not released yet, and the day each one is released.
+///
+/// # Arguments
+/// * `client` - The client the sets listing is requested with, unless it was for `--all`.
+/// * `discovered` - The sets listing requested for `--all`, if any.
+/// * `set_codes` - The set codes of the fetch.
+/// * `manifest` - The manifest, whose earlier previews stay previews when the listing cannot
+///   be requested.
+///
+/// # Returns
+/// The release day of every set released after today, keyed by set code.
+fn find_previews(
+    client: &FabClient,
+    discovered: Option<Vec<SetInfo>>,
+    set_codes: &[String],
+    manifest: &Manifest,
+) -> BTreeMap<String, NaiveDate> {
+    let listing = match discovered {
+        Some(sets) => Ok(sets),
+        None => client.discover_sets(),
+    };
+    let release_days: HashMap<String, NaiveDate> = match listing {
+        Ok(sets) => {
+            sets.iter().filter_map(|set| Some((set.code.clone(), set.release_day()?))).collect()
+        }
+        Err(e) => {
+            warn!(
+                "Could not list the release dates of the sets ({}); only earlier previews are \
+                kept in {}/.",
+                e, PREVIEWS_DIR
+            );
+            manifest
+                .sets
+                .iter()
+                .filter_map(|(set_code, entry)| {
+                    let day = entry.preview_until.as_deref()?;
+                    Some((set_code.clone(), NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()?))
+                })
+                .collect()
+        }
+    };
+    let today = Local::now().date_naive();
+    set_codes
+        .iter()
+        .filter_map(|set_code| {
+            let day = release_days.get(set_code).filter(|day| **day > today)?;
+            Some((set_code.clone(), *day))
+        })
+        .collect()
+}
+
+/// Removes the files of a set released since it was fetched as a preview, and the previews
+/// folder once it holds no other set.
+///
+/// # Arguments
+/// * `preview_dir` - The previews folder of the output directory.
+/// * `set_code` - The set code (e.g., "WTR").
+/// * `last_preview` - Whether no other set is a preview any more.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn remove_preview(
+    preview_dir: &str,
+    set_code: &str,
+    last_preview: bool,
+) -> Result<(), Box<dyn Error>> {
+    if last_preview {
+        return match fs::remove_dir_all(preview_dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        };
+    }
+    let per_set_formats =
+        [OutputFormat::Json, OutputFormat::Txt, OutputFormat::Csv, OutputFormat::Ndjson];
+    for format in per_set_formats {
+        for file in storage::list_set_files(preview_dir, format)? {
+            if file.set_code == set_code {
+                fs::remove_file(&file.path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Builds the card filter given on the command line.
+fn card_filter(args: &FilterArgs) -> CardFilter {
+    let values = |values: &[String]| -> Vec<String> {
+        values
+            .iter()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .collect()
+    };
+    CardFilter {
+        classes: values(&args.classes),
+        rarities: values(&args.rarities),
+        pitches: values(&args.pitches),
+        keywords: values(&args.keywords),
+        promos: args.promos.clone(),
+        artists: values(&args.artists),
+        flavor: values(&args.flavor),
+        numbers: args.numbers.clone(),
+        query: args.query.clone().filter(|query| !query.trim().is_empty()),
+    }
+}
+
+/// Fetches every requested set in each language given with `--lang`, into a folder per
+/// language inside every format directory.
+///
+/// Like filtered fetches, these leave the default per-set and combined files, the manifest
+/// and the run state untouched. The JSON-based formats get one combined file keyed by
+/// language; the others get a combined file in every language folder.
+///
+/// # Arguments
+/// * `args` - The options of the `fetch` command.
+/// * `client` - The API client to send the requests with.
+/// * `set_codes` - The sets to fetch.
+///
+/// # Returns
+/// A `Result` indicating success, or an error if a language code is invalid, or a set could
+/// not be fetched or written and that stage is fail-closed.
+fn run_localized_fetch(
+    args: &FetchArgs,
+    client: &FabClient,
+    set_codes: &[String],
+    template: &CardTemplate,
+) -> Result<(), Box<dyn Error>> {
+    let output = &args.output;
+    let policy = FailurePolicy::new(&args.failures.fail_closed, args.failures.strict);
+
+    // Language codes name folders, so only plain codes such as "fr" or "pt-br" are accepted
+    let mut languages: Vec<String> = Vec::new();
+    for language in &args.languages {
+        let language = language.trim().to_lowercase();
+        if language.is_empty()
+            || !language.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            let message = format!("Error: Invalid language code '{}' given to --lang.", language);
+            return Err(message.into());
+        }
+        if !languages.contains(&language) {
+            languages.push(language);
+        }
+    }
+
+    let mut fetched: BTreeMap<&str, Vec<(&str, SetResponse)>> = BTreeMap::new();
+    for language in &languages {
+        for set_code in set_codes {
+            info!("Processing set {} in '{}'", set_code, language);
+            let mut set = match client.localized_set_cards(set_code, language) {
+                Ok(set) => set,
+                Err(e) => {
+                    let message =
+                        format!("Could not fetch set {} in '{}': {}", set_code, language, e);
+                    policy.check(FailureStage::Fetch, &message)?;
+                    continue;
+                }
+            };
+            reminder::apply(args.reminder_text, &mut set);
+            promo::apply(&mut set);
+
+            let json_content = serde_json::to_string(&set)?;
+            for format in output.formats.iter().filter(|format| format.per_set_files()) {
+                let filename =
+                    storage::localized_set_filename(&output.out, *format, language, set_code);
+                let saved = storage::render_set(*format, set_code, &json_content, &set, template)
+                    .and_then(|content| save_creating_dirs(&filename, &content, output.compress));
+                match saved {
+                    Err(e) => {
+                        let message = format!("Could not save {} file {}: {}", format, filename, e);
+                        policy.check(FailureStage::Write, &message)?;
+                    }
+                    Ok(written) => info!("Successfully saved {}", written),
+                }
+            }
+            fetched.entry(language).or_default().push((set_code, set));
+        }
+    }
+
+    for format in &output.formats {
+        let mut written = Vec::new();
+        match format {
+            OutputFormat::Json => {
+                let combined: BTreeMap<&str, BTreeMap<&str, &SetResponse>> = fetched
+                    .iter()
+                    .map(|(language, sets)| {
+                        (*language, sets.iter().map(|(set_code, set)| (*set_code, set)).collect())
+                    })
+                    .collect();
+                let content = if output.pretty {
+                    serde_json::to_string_pretty(&combined)?
+                } else {
+                    serde_json::to_string(&combined)?
+                };
+                let filename = storage::localized_combined_filename(&output.out, *format, "");
+                let saved = save_creating_dirs(&filename, &content, output.compress);
+                written.push((filename, saved));
+            }
+            OutputFormat::Txt | OutputFormat::Csv | OutputFormat::Ndjson => {
+                for (language, sets) in &fetched {
+                    let sets = sets.iter().map(|(set_code, set)| (*set_code, set));
+                    let content = match format {
+                        OutputFormat::Txt => Ok(template.render_sets(sets)),
+                        OutputFormat::Csv => export::csv::render(sets),
+                        _ => export::ndjson::render(sets),
+                    };
+                    let filename =
+                        storage::localized_combined_filename(&output.out, *format, language);
+                    let saved = content.and_then(|content| {
+                        save_creating_dirs(&filename, &content, output.compress)
+                    });
+                    written.push((filename, saved));
+                }
+            }
+            OutputFormat::Sqlite | OutputFormat::Parquet => {
+                warn!("Skipping {} output: it holds a single language.", format);
+            }
+        }
+        for (filename, saved) in written {
+            match saved {
+                Err(e) => {
+                    let message =
+                        format!("Could not save combined {} file {}: {}", format, filename, e);
+                    policy.check(FailureStage::Combine, &message)?;
+                }
+                Ok(written) => info!("Successfully saved combined {} file: {}", format, written),
+            }
+        }
+    }
+    info!("Fetched {} set(s) in {} language(s).", set_codes.len(), languages.len());
+    Ok(())
+}
+
+/// Saves a file, compressed if requested, creating its directory first.
+///
+/// # Returns
+/// A `Result` containing the path written, or an error.
+fn save_creating_dirs(
+    filename: &str,
+    content: &str,
+    compress: Option<Compression>,
+) -> Result<String, Box<dyn Error>> {
+    if let Some(dir) = Path::new(filename).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    compression::write(filename, content.as_bytes(), compress)
+}
+
+/// Fetches the cards of every requested set that match a filter into one named output.
+///
+/// The per-set and combined files, the manifest and the run state are left untouched, so a
+/// filtered fetch never replaces a full dataset with a partial one.
+///
+/// # Arguments
+/// * `args` - The options of the `fetch` command.
+/// * `client` - The API client to send the requests with.
+/// * `set_codes` - The sets to search.
+/// * `filter` - Which cards to keep.
+///
+/// # Returns
+/// A `Result` indicating success, or an error if a set could not be fetched or the output
+/// could not be written.
+fn run_filtered_fetch(
+    args: &FetchArgs,
+    client: &FabClient,
+    set_codes: &[String],
+    filter: &CardFilter,
+    template: &CardTemplate,
+) -> Result<(), Box<dyn Error>> {
+    let output = &args.output;
+    let policy = FailurePolicy::new(&args.failures.fail_closed, args.failures.strict);
+    let name = args.filter.filter_name.clone().unwrap_or_else(|| filter.default_name());
+
+    let mut sets = Vec::new();
+    for set_code in set_codes {
+        info!("Searching set: {}", set_code);
+        let fetched = match &filter.query {
+            Some(query) => client.search_set(set_code, query),
+            None => client.set_cards(set_code),
+        };
+        match fetched {
+            Ok(mut set) => {
+                promo::apply(&mut set);
+                set.results.retain(|card| filter.matches(card));
+                reminder::apply(args.reminder_text, &mut set);
+                info!("{} card(s) of set {} match the filter.", set.results.len(), set_code);
+                sets.push((set_code.as_str(), set));
+            }
+            Err(e) => {
+                let message = format!("Could not fetch JSON data for set {}: {}", set_code, e);
+                policy.check(FailureStage::Fetch, &message)?;
+            }
+        }
+    }
+
+    // All matching cards in one listing, in set order
+    let results: Vec<Card> = sets.iter().flat_map(|(_, set)| set.results.iter().cloned()).collect();
+    let merged = SetResponse {
+        count: Some(results.len() as u64),
+        next: None,
+        previous: None,
+        results,
+        extra: Default::default(),
+    };
+    let json_content = if output.pretty {
+        serde_json::to_string_pretty(&merged)?
+    } else {
+        serde_json::to_string(&merged)?
+    };
+
+    fs::create_dir_all(format!("{}/{}", output.out, storage::FILTERED_DIR))?;
+    for format in &output.formats {
+        let filename = storage::filtered_filename(&output.out, *format, &name);
+        let content = match format {
+            OutputFormat::Json => json_content.clone(),
+            OutputFormat::Txt => {
+                template.render_sets(sets.iter().map(|(set_code, set)| (*set_code, set)))
+            }
+            OutputFormat::Csv => {
+                export::csv::render(sets.iter().map(|(set_code, set)| (*set_code, set)))?
+            }
+            OutputFormat::Ndjson => {
+                export::ndjson::render(sets.iter().map(|(set_code, set)| (*set_code, set)))?
+            }
+            OutputFormat::Sqlite | OutputFormat::Parquet => {
+                warn!("Skipping {} output: filtered fetches are only written as text.", format);
+                continue;
+            }
+        };
+        match compression::write(&filename, content.as_bytes(), output.compress) {
+            Err(e) => {
+                let message = format!("Could not save {} file {}: {}", format, filename, e);
+                policy.check(FailureStage::Write, &message)?;
+            }
+            Ok(written) => info!("Successfully saved {}", written),
+        }
+    }
+    info!("{} card(s) matched the filter across {} set(s).", merged.results.len(), sets.len());
+    Ok(())
+}
+
+/// Rebuilds the combined files from the per-set JSON files of earlier runs.
+///
+/// # Arguments
+/// * `args` - The options of the `combine` command.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn run_combine(args: &CombineArgs) -> Result<(), Box<dyn Error>> {
+    let output = &args.output;
+    // Held while reading too, so a concurrent fetch cannot rewrite the sets being combined
+    let _lock = OutputLock::acquire(&output.out)?;
+    let local_sets = storage::load_fetched_sets(&output.out)?;
+    let all_sets_data: HashMap<String, String> = local_sets
+        .into_iter()
+        .map(|local| (local.set_code, local.json))
+        .collect();
+
+    info!("Found {} fetched sets in {}.", all_sets_data.len(), output.out);
+    storage::create_output_dirs(&output.out, &output.formats)?;
+    let policy = FailurePolicy::new(&args.failures.fail_closed, args.failures.strict);
+    for message in storage::write_combined_files(
+        &output.out,
+        &output.formats,
+        &all_sets_data,
+        output.pretty,
+        output.compress,
+        &txt_template(output)?,
+        &EventLog::disabled(),
+    ) {
+        policy.check(FailureStage::Combine, &message)?;
+    }
+    write_card_indexes(&output.out);
+
+    let mut manifest = Manifest::load(&output.out)?;
+    manifest.record_files(&output.out)?;
+    manifest.save(&output.out)
+}
+
+/// Rebuilds the card name index and the master card index from every set in the output
+/// directory, not only the sets of the current run. A failure is only reported, as neither is
+/// needed by later runs.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+fn write_card_indexes(base_output_dir: &str) {
+    let local_sets = match storage::load_fetched_sets(base_output_dir) {
+        Ok(local_sets) => local_sets,
+        Err(e) => {
+            warn!("Could not index the cards in {}: {}", base_output_dir, e);
+            return;
+        }
+    };
+    let sets = || local_sets.iter().map(|local| (local.set_code.as_str(), &local.set));
+    match name_index::write(base_output_dir, sets()) {
+        Ok(()) => info!(
+            "Updated the card name index: {}/{}",
+            base_output_dir,
+            name_index::NAME_INDEX_FILENAME
+        ),
+        Err(e) => warn!("Could not write the card name index in {}: {}", base_output_dir, e),
+    }
+    match master_index::write(base_output_dir, sets()) {
+        Ok((cards, printings)) => info!(
+            "Updated the master card index: {}/{} ({} card(s), {} printing(s))",
+            base_output_dir,
+            master_index::MASTER_INDEX_FILENAME,
+            cards,
+            printings
+        ),
+        Err(e) => warn!("Could not write the master card index in {}: {}", base_output_dir, e),
+    }
+}
+
+/// Validates the sets of a run and writes the anomalies found to the validation report.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `all_sets_data` - The JSON data of each set, keyed by set code.
+///
+/// # Returns
+/// A message for every set with anomalies, and for a report that could not be written.
+fn validate_fetched_sets(
+    base_output_dir: &str,
+    all_sets_data: &HashMap<String, String>,
+) -> Vec<String> {
+    let sets = match storage::parse_all_sets(all_sets_data) {
+        Ok(sets) => sets,
+        Err(e) => return vec![format!("Could not read fetched sets for validation: {}", e)],
+    };
+    let sets: Vec<(&str, &SetResponse)> = sets.iter().map(|(code, set)| (*code, set)).collect();
+    let report = ValidationReport::new(Local::now().to_rfc3339(), &sets);
+    let mut messages: Vec<String> = report
+        .by_set()
+        .into_iter()
+        .map(|(set_code, count)| {
+            format!(
+                "Set {} has {} anomal{} (see {}/{})",
+                set_code,
+                count,
+                if count == 1 { "y" } else { "ies" },
+                base_output_dir,
+                validate::VALIDATION_REPORT_FILENAME
+            )
+        })
+        .collect();
+    match report.write(base_output_dir) {
+        Ok(()) if report.anomalies.is_empty() => {
+            info!("Validated {} card(s): no anomalies found.", report.cards)
+        }
+        Ok(()) => {}
+        Err(e) => messages.push(format!(
+            "Could not write the validation report in {}: {}",
+            base_output_dir, e
+        )),
+    }
+    messages
+}
+
+/// Writes catalog exports of every set in the output directory.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `formats` - The exports to write.
+/// * `policy` - Whether a failed export stops the run.
+///
+/// # Returns
+/// A `Result` indicating success, or an error if an export failed and exports are fail-closed.
+fn write_exports(
+    base_output_dir: &str,
+    formats: &[ExportFormat],
+    policy: &FailurePolicy,
+) -> Result<(), Box<dyn Error>> {
+    if formats.is_empty() {
+        return Ok(());
+    }
+    let local_sets = storage::load_fetched_sets(base_output_dir)?;
+    for format in formats {
+        let sets = local_sets.iter().map(|local| (local.set_code.as_str(), &local.set));
+        match export::write(*format, base_output_dir, sets) {
+            Ok(filename) => info!("Successfully saved {} export: {}", format, filename),
+            Err(e) => {
+                let message = format!("Could not write the {} export: {}", format, e);
+                policy.check(FailureStage::Export, &message)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a deck-builder card list or a spreadsheet of every fetched set.
+///
+/// # Arguments
+/// * `format` - The export to write.
+/// * `args` - The options of the `export fabrary` or `export xlsx` command.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn run_export_catalog(
+    format: ExportFormat,
+    args: &CatalogExportArgs,
+) -> Result<(), Box<dyn Error>> {
+    let local_sets = storage::load_fetched_sets(&args.out)?;
+    if local_sets.is_empty() {
+        info!("No fetched sets found in {}. Nothing to export.", args.out);
+        return Ok(());
+    }
+    let _lock = OutputLock::acquire(&args.out)?;
+    let selected: Vec<SetResponse> =
+        local_sets.iter().map(|local| promo::select(&local.set, args.promos)).collect();
+    let sets = local_sets.iter().zip(&selected).map(|(local, set)| (local.set_code.as_str(), set));
+    let filename = export::write(format, &args.out, sets)?;
+    info!("Exported {} set(s) to {}", local_sets.len(), filename);
+    Ok(())
+}
+
+/// Writes a hero matchup grid scaffold built from the fetched card data.
+///
+/// # Arguments
+/// * `args` - The options of the `export matchups` command.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn run_export_matchups(args: &MatchupsArgs) -> Result<(), Box<dyn Error>> {
+    let local_sets = storage::load_fetched_sets(&args.out)?;
+    let sets: Vec<&SetResponse> = local_sets.iter().map(|local| &local.set).collect();
+    let heroes = export::matchups::constructed_heroes(&sets);
+    if heroes.is_empty() {
+        info!("No constructed heroes found in the fetched sets. Nothing to export.");
+        return Ok(());
+    }
+
+    let matchups_dir = format!("{}/matchups", args.out);
+    fs::create_dir_all(&matchups_dir)?;
+    let generated_at = Local::now().to_rfc3339();
+    let set_codes: Vec<&str> = local_sets.iter().map(|local| local.set_code.as_str()).collect();
+
+    let csv_filename = format!("{}/matchup_grid.csv", matchups_dir);
+    storage::save_data_to_file(&csv_filename, &export::matchups::render_csv(&heroes)?)?;
+    info!("Successfully saved {}", csv_filename);
+
+    let json_filename = format!("{}/matchup_grid.json", matchups_dir);
+    let json = export::matchups::render_json(&heroes, &generated_at, &set_codes)?;
+    storage::save_data_to_file(&json_filename, &json)?;
+    info!("Successfully saved {}", json_filename);
+
+    info!("Exported a {} x {} hero matchup grid.", heroes.len(), heroes.len());
+    Ok(())
+}
+
+/// Writes the card pool of a hero, from the sets fetched earlier, to the `card-pool/` folder as
+/// CSV and JSON.
+///
+/// # Arguments
+/// * `args` - The options of the `export card-pool` command.
+///
+/// # Returns
+/// A `Result` indicating success, or an error if the hero is not found or a file cannot be
+/// written.
+fn run_export_card_pool(args: &CardPoolArgs) -> Result<(), Box<dyn Error>> {
+    let local_sets = storage::load_fetched_sets(&args.out)?;
+    let sets: Vec<&SetResponse> = local_sets.iter().map(|local| &local.set).collect();
+    let cards: Vec<&Card> = sets.iter().flat_map(|set| &set.results).collect();
+    let hero = PoolHero::new(card_pool::find_hero(&cards, &args.hero)?);
+    let pool = card_pool::card_pool(&hero, &sets);
+
+    let pool_dir = format!("{}/card-pool", args.out);
+    fs::create_dir_all(&pool_dir)?;
+    let generated_at = Local::now().to_rfc3339();
+    let set_codes: Vec<&str> = local_sets.iter().map(|local| local.set_code.as_str()).collect();
+
+    let csv_filename = format!("{}/{}.csv", pool_dir, hero.card.card_id);
+    storage::save_data_to_file(&csv_filename, &card_pool::render_csv(&pool)?)?;
+    info!("Successfully saved {}", csv_filename);
+
+    let json_filename = format!("{}/{}.json", pool_dir, hero.card.card_id);
+    let json = card_pool::render_json(&hero, &pool, &generated_at, &set_codes)?;
+    storage::save_data_to_file(&json_filename, &json)?;
+    info!("Successfully saved {}", json_filename);
+
+    let supertypes: Vec<&str> = hero.classes.iter().chain(&hero.talents).copied().collect();
+    info!(
+        "Exported the {} card(s) legal with {} ({}).",
+        pool.len(),
+        hero.card.name,
+        if supertypes.is_empty() { "generic only".to_string() } else { supertypes.join(" ") }
+    );
+    Ok(())
+}
+
+/// Writes the feature matrix of every card of the sets fetched earlier to the `features/`
+/// folder, as CSV, as a NumPy array and with the labels of the array in JSON.
+///
+/// # Arguments
+/// * `args` - The options of the `export features` command.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn run_export_features(args: &FeaturesArgs) -> Result<(), Box<dyn Error>> {
+    let local_sets = storage::load_fetched_sets(&args.out)?;
+    let sets: Vec<&SetResponse> = local_sets.iter().map(|local| &local.set).collect();
+    let words = WordOptions { max_words: args.max_words, min_cards: args.min_word_cards };
+    let matrix = features::build(&sets, words);
+    if matrix.card_ids.is_empty() {
+        info!("No cards found in the fetched sets. Nothing to export.");
+        return Ok(());
+    }
+
+    let features_dir = format!("{}/features", args.out);
+    fs::create_dir_all(&features_dir)?;
+    let generated_at = Local::now().to_rfc3339();
+    let set_codes: Vec<&str> = local_sets.iter().map(|local| local.set_code.as_str()).collect();
+
+    let csv_filename = format!("{}/card_features.csv", features_dir);
+    storage::save_data_to_file(&csv_filename, &features::render_csv(&matrix)?)?;
+    info!("Successfully saved {}", csv_filename);
+
+    let npy_filename = format!("{}/card_features.npy", features_dir);
+    storage::write_atomically(Path::new(&npy_filename), &features::render_npy(&matrix))?;
+    info!("Successfully saved {}", npy_filename);
+
+    let labels_filename = format!("{}/card_features_labels.json", features_dir);
+    let labels = features::render_labels(&matrix, &generated_at, &set_codes)?;
+    storage::save_data_to_file(&labels_filename, &labels)?;
+    info!("Successfully saved {}", labels_filename);
+
+    info!(
+        "Exported {} card(s) with {} feature(s) each.",
+        matrix.card_ids.len(),
+        matrix.columns.len()
+    );
+    Ok(())
+}
+
+/// Removes the per-set output files matching the given sets, formats and age.
+///
+/// Sets losing a file are dropped from the manifest, so the next incremental fetch downloads
+/// and writes them again instead of trusting stale validators.
+///
+/// # Arguments
+/// * `args` - The options of the `clean` command.
+///
+/// # Returns
+/// A `Result` indicating success, or an error if a file could not be removed.
+fn run_clean(args: &CleanArgs) -> Result<(), Box<dyn Error>> {
+    if !Path::new(&args.out).is_dir() {
+        return Err(format!("Error: Output directory '{}' not found.", args.out).into());
+    }
+    let _lock = OutputLock::acquire(&args.out)?;
+
+    let formats: Vec<OutputFormat> = if args.formats.is_empty() {
+        vec![OutputFormat::Json, OutputFormat::Txt, OutputFormat::Csv, OutputFormat::Ndjson]
+    } else {
+        for format in args.formats.iter().filter(|format| !format.per_set_files()) {
+            warn!("Skipping {}: it has no per-set files.", format);
+        }
+        args.formats.iter().copied().filter(|format| format.per_set_files()).collect()
+    };
+    let cutoff = args.older_than.map(|age| SystemTime::now() - age);
+    let wanted = |set_code: &str| {
+        args.sets.is_empty()
+            || args.sets.iter().any(|wanted| wanted.trim().eq_ignore_ascii_case(set_code))
+    };
+
+    let mut selected = Vec::new();
+    let mut matched_sets = HashSet::new();
+    for format in formats {
+        for file in storage::list_set_files(&args.out, format)? {
+            if !wanted(&file.set_code) {
+                continue;
+            }
+            matched_sets.insert(file.set_code.to_uppercase());
+            let metadata = fs::metadata(&file.path)?;
+            if let Some(cutoff) = cutoff
+                && metadata.modified()? >= cutoff
+            {
+                continue;
+            }
+            selected.push((file, format, metadata.len()));
+        }
+    }
+    let unmatched =
+        args.sets.iter().filter(|code| !matched_sets.contains(&code.trim().to_uppercase()));
+    for set_code in unmatched {
+        warn!("No output files of set {} in '{}'.", set_code.trim(), args.out);
+    }
+    if selected.is_empty() {
+        info!("No output files match. Nothing to clean.");
+        return Ok(());
+    }
+
+    let total_bytes: u64 = selected.iter().map(|(_, _, size)| size).sum();
+    let total_mib = total_bytes as f64 / (1024.0 * 1024.0);
+    if args.dry_run {
+        for (file, _, size) in &selected {
+            println!("{} ({} bytes)", file.path.display(), size);
+        }
+        info!(
+            "Dry run: {} file(s), {:.1} MiB, would be removed. Run again without --dry-run to \
+             remove them.",
+            selected.len(),
+            total_mib
+        );
+        return Ok(());
+    }
+
+    let mut cleaned_sets = HashSet::new();
+    for (file, _, _) in &selected {
+        fs::remove_file(&file.path)
+            .map_err(|e| format!("Error: Could not remove {}: {}", file.path.display(), e))?;
+        debug!("Removed {}", file.path.display());
+        if file.language.is_none() {
+            cleaned_sets.insert(file.set_code.to_uppercase());
+        }
+    }
+    info!("Removed {} file(s), {:.1} MiB.", selected.len(), total_mib);
+
+    let mut manifest = Manifest::load(&args.out)?;
+    manifest.sets.retain(|set_code, _| !cleaned_sets.contains(&set_code.to_uppercase()));
+    manifest.record_files(&args.out)?;
+    manifest.save(&args.out)?;
+    // The card indexes are built from the JSON files
+    let json_removed = selected
+        .iter()
+        .any(|(file, format, _)| file.language.is_none() && *format == OutputFormat::Json);
+    if json_removed {
+        write_card_indexes(&args.out);
+    }
+    info!("Run 'fab-scrapper combine' to rebuild the combined files without the removed data.");
+    Ok(())
+}
+
+/// Prints the fetched cards matching a full-text search, with their set, rarity and pitch.
+///
+/// # Arguments
+/// * `args` - The options of the `search` command.
+///
+/// # Returns
+/// A `Result` indicating success, or an error if there are no fetched sets to search.
+fn run_search(args: &SearchArgs) -> Result<(), Box<dyn Error>> {
+    let query = args.query.join(" ");
+    let index = SearchIndex::open(&args.out)?;
+    let hits = index.search(&query);
+    if hits.is_empty() {
+        info!("No card matches '{}'.", query);
+        return Ok(());
+    }
+
+    println!("{:<40} {:<6} {:<8} Pitch", "Name", "Set", "Rarity");
+    for hit in hits.iter().take(args.limit) {
+        let card = hit.document;
+        println!(
+            "{:<40} {:<6} {:<8} {}",
+            card.name,
+            card.set_code,
+            if card.rarities.is_empty() { String::from("-") } else { card.rarities.join(",") },
+            card.pitch.as_deref().unwrap_or("-")
+        );
+    }
+    if hits.len() > args.limit {
+        info!("{} more card(s) match; raise --limit to see them.", hits.len() - args.limit);
+    }
+    Ok(())
+}
+
+/// Prints how often every keyword, type, rarity and pitch occurs in the fetched cards.
+///
+/// # Arguments
+/// * `args` - The options of the `stats` command.
+///
+/// # Returns
+/// A `Result` indicating success, or an error if the fetched sets cannot be read.
+fn run_stats(args: &StatsArgs) -> Result<(), Box<dyn Error>> {
+    let mut local_sets = storage::load_fetched_sets(&args.out)?;
+    if !args.sets.is_empty() {
+        let wanted: HashSet<String> = args.sets.iter().map(|code| code.to_uppercase()).collect();
+        local_sets.retain(|local| wanted.contains(&local.set_code.to_uppercase()));
+        if local_sets.is_empty() {
+            return Err(format!(
+                "Error: None of the sets {} were fetched into '{}'.",
+                args.sets.join(", "),
+                args.out
+            )
+            .into());
+        }
+    }
+    if local_sets.is_empty() {
+        info!("No fetched sets found in {}. Nothing to count.", args.out);
+        return Ok(());
+    }
+
+    let sets: Vec<(&str, &SetResponse)> =
+        local_sets.iter().map(|local| (local.set_code.as_str(), &local.set)).collect();
+    let counts = stats::compute(&sets);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&counts)?);
+    } else {
+        print!("{}", stats::render_text(&counts, args.top));
+    }
+    Ok(())
+}
+
+/// Writes the reprint history of every card built from the fetched card data.
+///
+/// Sets are dated with the release dates listed by the API; when it cannot be reached, they
+/// are ordered as in the set codes file instead, without dates.
+///
+/// # Arguments
+/// * `args` - The options of the `export reprints` command.
+/// * `http` - The HTTP client to list the sets with.
+/// * `source` - Where the API responses come from: the API or recorded fixtures.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn run_export_reprints(
+    args: &ReprintsArgs,
+    http: &reqwest::blocking::Client,
+    source: &ApiSource,
+) -> Result<(), Box<dyn Error>> {
+    let local_sets = storage::load_fetched_sets(&args.out)?;
+    if local_sets.is_empty() {
+        info!("No fetched sets found in {}. Nothing to export.", args.out);
+        return Ok(());
+    }
+
+    let releases = match source.client(http.clone()).discover_sets() {
+        Ok(releases) => releases,
+        Err(e) => {
+            warn!(
+                "Could not list the sets for their release dates ({}); ordering sets as in {}.",
+                e, args.sets_file
+            );
+            storage::read_set_codes(&args.sets_file)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|code| SetInfo {
+                    code,
+                    name: None,
+                    release_date: None,
+                    extra: Default::default(),
+                })
+                .collect()
+        }
+    };
+    let sets = local_sets.iter().map(|local| (local.set_code.as_str(), &local.set));
+    let histories = export::reprints::card_histories(sets, &releases);
+
+    let reprints_dir = format!("{}/reprints", args.out);
+    fs::create_dir_all(&reprints_dir)?;
+    let generated_at = Local::now().to_rfc3339();
+    let set_codes: Vec<&str> = local_sets.iter().map(|local| local.set_code.as_str()).collect();
+
+    let csv_filename = format!("{}/reprint_history.csv", reprints_dir);
+    storage::save_data_to_file(&csv_filename, &export::reprints::render_csv(&histories)?)?;
+    info!("Successfully saved {}", csv_filename);
+
+    let json_filename = format!("{}/reprint_history.json", reprints_dir);
+    let json = export::reprints::render_json(&histories, &generated_at, &set_codes)?;
+    storage::save_data_to_file(&json_filename, &json)?;
+    info!("Successfully saved {}", json_filename);
+
+    let reprinted = histories.iter().filter(|history| history.reprints() > 0).count();
+    info!("Exported the history of {} card(s), {} of them reprinted.", histories.len(), reprinted);
+    Ok(())
+}
+
+/// Downloads the card images of sets fetched by earlier runs.
+///
+/// # Arguments
+/// * `args` - The options of the `images` command.
+/// * `http` - The HTTP client to send the requests with.
+///
+/// # Returns
+/// A `Result` indicating success, or an error if any image could not be downloaded.
+fn run_images(args: &ImagesArgs, http: &reqwest::blocking::Client) -> Result<(), Box<dyn Error>> {
+    let mut local_sets = storage::load_fetched_sets(&args.out)?;
+    if !args.sets.is_empty() {
+        for code in args.sets.iter().map(|code| code.trim()) {
+            if !local_sets.iter().any(|local| local.set_code.eq_ignore_ascii_case(code)) {
+                let fetched = local_sets.iter().map(|local| local.set_code.as_str());
+                warn!(
+                    "Set '{}' has not been fetched into '{}'.{}",
+                    code,
+                    args.out,
+                    suggest::did_you_mean(code, fetched)
+                );
+            }
+        }
+        local_sets.retain(|local| {
+            args.sets.iter().any(|code| code.trim().eq_ignore_ascii_case(&local.set_code))
+        });
+        if local_sets.is_empty() {
+            return Err(format!(
+                "Error: None of the given sets have been fetched into '{}'.",
+                args.out
+            )
+            .into());
+        }
+    }
+
+    let _lock = OutputLock::acquire(&args.out)?;
+    let client =
+        FabClient::with_http(http.clone()).rate_limit(Duration::from_millis(args.delay_ms), 1);
+    let mut manifest = Manifest::load(&args.out)?;
+    let sets = local_sets.iter().map(|local| (local.set_code.as_str(), &local.set));
+    let (summary, _) =
+        download_images(&client, &args.out, sets, &mut manifest, &EventLog::disabled());
+    manifest.save(&args.out)?;
+
+    if summary.failed > 0 {
+        return Err(format!("{} image(s) could not be downloaded", summary.failed).into());
+    }
+    Ok(())
+}
+
+/// Downloads the endpoints besides the card search into folders of the output directory.
+///
+/// # Arguments
+/// * `args` - The options of the `mirror` command.
+/// * `http` - The HTTP client to send the requests with.
+/// * `source` - Where the API responses come from: the API or recorded fixtures.
+///
+/// # Returns
+/// A `Result` indicating success, or an error if any endpoint could not be mirrored.
+fn run_mirror(
+    args: &MirrorArgs,
+    http: &reqwest::blocking::Client,
+    source: &ApiSource,
+) -> Result<(), Box<dyn Error>> {
+    let endpoints = if args.only.is_empty() { Endpoint::ALL.to_vec() } else { args.only.clone() };
+    let _lock = OutputLock::acquire(&args.out)?;
+    let client =
+        source.client(http.clone()).rate_limit(Duration::from_millis(args.delay_ms), 1);
+
+    let mut failed = Vec::new();
+    for endpoint in endpoints {
+        match mirror::mirror(&client, endpoint, &args.out) {
+            Ok((filename, count)) => info!("Saved {} {} entries to {}", count, endpoint, filename),
+            Err(e) => {
+                warn!("Could not mirror {}: {}", endpoint, e);
+                failed.push(endpoint.name());
+            }
+        }
+    }
+
+    let mut manifest = Manifest::load(&args.out)?;
+    manifest.record_files(&args.out)?;
+    manifest.save(&args.out)?;
+    if !failed.is_empty() {
+        return Err(format!("Error: Could not mirror {}.", failed.join(", ")).into());
+    }
+    Ok(())
+}
+
+/// Fetches the current prices of every given set into its `prices/<SET>.json` snapshot.
+///
+/// # Arguments
+/// * `price_client` - The client of the pricing provider.
+/// * `base_output_dir` - The root output directory.
+/// * `sets` - The fetched sets, keyed by set code.
+/// * `events` - Receives an event for every snapshot written.
+///
+/// # Returns
+/// A message for every set whose prices could not be fetched or saved.
+fn write_price_snapshots(
+    price_client: &PriceClient,
+    base_output_dir: &str,
+    sets: &HashMap<String, String>,
+    events: &EventLog,
+) -> Vec<String> {
+    let mut set_codes: Vec<&String> = sets.keys().collect();
+    set_codes.sort_unstable();
+    info!("Fetching {} prices of {} set(s)...", price_client.provider(), set_codes.len());
+
+    let mut failures = Vec::new();
+    for set_code in set_codes {
+        let written = price_client
+            .set_prices(set_code)
+            .and_then(|snapshot| prices::write_snapshot(base_output_dir, set_code, &snapshot));
+        match written {
+            Ok(filename) => {
+                info!("Successfully saved {}", filename);
+                events.record(Event::FileWritten { path: &filename });
+            }
+            Err(e) => failures.push(format!("Could not save prices of set {}: {}", set_code, e)),
+        }
+    }
+    failures
+}
+
+/// Downloads the images of every given set and records them in the manifest.
+///
+/// # Arguments
+/// * `client` - The client whose HTTP client and rate limiter every download uses.
+/// * `base_output_dir` - The root output directory.
+/// * `sets` - Pairs of set code and the set's cards.
+/// * `manifest` - The manifest of the output directory.
+/// * `events` - Receives an event for every image downloaded.
+///
+/// # Returns
+/// The number of images downloaded, skipped and failed across all sets, and for each set.
+fn download_images<'a>(
+    client: &FabClient,
+    base_output_dir: &str,
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+    manifest: &mut Manifest,
+    events: &EventLog,
+) -> (ImageSummary, HashMap<&'a str, ImageSummary>) {
+    info!("Downloading card images...");
+    let sets: Vec<(&str, &SetResponse)> = sets.into_iter().collect();
+    let bar = logging::progress_bar(sets.len() as u64, "sets");
+    let mut total = ImageSummary::default();
+    let mut per_set = HashMap::new();
+    for (set_code, set) in sets {
+        bar.set_message(format!("images of set {}", set_code));
+        let summary = images::download_set_images(
+            client.http(),
+            client.limiter(),
+            base_output_dir,
+            set_code,
+            set,
+            manifest,
+            events,
+        );
+        debug!(
+            "Images of set {}: {} downloaded, {} already present, {} failed, {} corrupt.",
+            set_code, summary.downloaded, summary.skipped, summary.failed, summary.corrupt
+        );
+        total.add(&summary);
+        per_set.insert(set_code, summary);
+        bar.inc(1);
+    }
+    bar.finish_and_clear();
+    (total, per_set)
+}
+
+/// Prints a single card from the fetched data.
+///
+/// The card is looked up by id, then by exact name and finally by partial name, ignoring
+/// case. A card printed in several sets is shown once with the printings of every set.
+///
+/// # Arguments
+/// * `args` - The options of the `card show` command.
+///
+/// # Returns
+/// A `Result` indicating success, or an error if no single card matches.
+fn run_card_show(args: &CardShowArgs) -> Result<(), Box<dyn Error>> {
+    // The name index tells which sets print the card, so only those are loaded; without an
+    // index, or when it does not know the card, every set is searched
+    let indexed = match NameIndex::open(&args.out) {
+        Ok(index) => index.map(|index| index.resolve(&args.card)).unwrap_or_default(),
+        Err(e) => {
+            warn!("{}; searching every set instead.", e);
+            Vec::new()
+        }
+    };
+    let local_sets = if indexed.is_empty() {
+        storage::load_fetched_sets(&args.out)?
+    } else {
+        let mut set_codes: Vec<&str> =
+            indexed.iter().map(|entry| entry.set_code.as_str()).collect();
+        set_codes.sort_unstable();
+        set_codes.dedup();
+        debug!("Name index found '{}' in {}.", args.card, set_codes.join(", "));
+        set_codes
+            .into_iter()
+            .map(|set_code| storage::load_fetched_set(&args.out, set_code))
+            .collect::<Result<Vec<_>, _>>()
+            .or_else(|_| storage::load_fetched_sets(&args.out))?
+    };
+    let cards: Vec<&Card> = local_sets.iter().flat_map(|local| &local.set.results).collect();
+    let query = args.card.trim().to_lowercase();
+
+    let find = |matches: &dyn Fn(&Card) -> bool| -> Vec<&Card> {
+        cards.iter().copied().filter(|card| matches(card)).collect()
+    };
+    let mut matches = find(&|card| card.card_id.to_lowercase() == query);
+    if matches.is_empty() {
+        matches = find(&|card| card.name.to_lowercase() == query);
+    }
+    if matches.is_empty() {
+        matches = find(&|card| card.name.to_lowercase().contains(&query));
+    }
+
+    let mut matched_ids: Vec<&str> = matches.iter().map(|card| card.card_id.as_str()).collect();
+    matched_ids.sort_unstable();
+    matched_ids.dedup();
+    match matched_ids.as_slice() {
+        [] => {
+            let names = cards.iter().map(|card| card.name.as_str());
+            let suggestion = suggest::did_you_mean(&args.card, names);
+            let message = format!("Error: No card matching '{}' in '{}'.{}", args.card, args.out, suggestion);
+            return Err(message.into());
+        }
+        [_] => {}
+        candidates => {
+            eprintln!("'{}' matches {} cards:", args.card, candidates.len());
+            let candidates =
+                candidates.iter().filter_map(|id| matches.iter().find(|card| card.card_id == *id));
+            for card in candidates {
+                eprintln!("  {} ({})", card.name, card.card_id);
+            }
+            return Err("Error: Ambiguous card name; pass one of the ids above.".into());
+        }
+    }
+
+    // Each set lists the card with its own printings
+    let mut card = matches[0].clone();
+    for other in &matches[1..] {
+        for printing in &other.printings {
+            if !card.printings.iter().any(|known| known.print_id == printing.print_id) {
+                card.printings.push(printing.clone());
+            }
+        }
+    }
+    print!("{}", display::render_card(&card, display::use_color(args.no_color)));
+    Ok(())
+}
+
+/// Reports what the latest fetch changed, compared with the snapshot it kept.
+///
+/// The changelog is printed and also written to `changelog.json` in the output directory.
+///
+/// # Arguments
+/// * `args` - The options of the `diff` command.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn run_diff(args: &DiffArgs) -> Result<(), Box<dyn Error>> {
+    let mut sets = Vec::new();
+    for (set_code, previous) in snapshot::load(&args.out)? {
+        // A set whose output was deleted since counts as removed entirely
+        let current = match storage::read_existing_set(&args.out, &set_code) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(_) => SetResponse { results: Vec::new(), ..previous.clone() },
+        };
+        sets.push(diff::compare_set(&set_code, &previous, &current));
+    }
+
+    let changelog = Changelog { generated_at: Local::now().to_rfc3339(), sets };
+    print!("{}", diff::render_text(&changelog));
+
+    let json_filename = format!("{}/{}", args.out, CHANGELOG_FILENAME);
+    storage::save_data_to_file(&json_filename, &serde_json::to_string_pretty(&changelog)?)?;
+    info!("Saved changelog to {}", json_filename);
+    Ok(())
+}
+
+/// Scores the completeness of every fetched set and appends the scores to the history.
+///
+/// # Arguments
+/// * `args` - The options of the `completeness` command.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn run_completeness(args: &CompletenessArgs) -> Result<(), Box<dyn Error>> {
+    let local_sets = storage::load_fetched_sets(&args.out)?;
+    let selected: Vec<SetResponse> =
+        local_sets.iter().map(|local| promo::select(&local.set, args.promos)).collect();
+    let sets: Vec<(&str, &SetResponse)> = local_sets
+        .iter()
+        .zip(&selected)
+        .map(|(local, set)| (local.set_code.as_str(), set))
+        .collect();
+    let sets = completeness::score_sets(&args.out, &sets);
+    let report = Report { generated_at: Local::now().to_rfc3339(), sets };
+
+    let previous = completeness::latest(&args.out).unwrap_or_else(|e| {
+        warn!("Could not read the previous completeness report: {}", e);
+        None
+    });
+    print!("{}", completeness::render_text(&report, previous.as_ref()));
+    if let Some(previous) = &previous {
+        info!("Changes are since the report of {}.", previous.generated_at);
+    }
+
+    // Scores of a subset of the printings would not compare with the rest of the history
+    if args.promos != PromoSelection::All {
+        return Ok(());
+    }
+    completeness::append(&args.out, &report)?;
+    info!("Appended the scores to {}/{}", args.out, completeness::HISTORY_FILENAME);
+    Ok(())
+}
+
+/// Checks the sets fetched by earlier runs for anomalies, printing them and writing them to the
+/// validation report.
+///
+/// # Arguments
+/// * `args` - The options of the `validate` command.
+///
+/// # Returns
+/// A `Result` indicating success, or an error if the sets cannot be read or, with
+/// `--fail-on-anomalies`, anomalies were found.
+fn run_validate(args: &ValidateArgs) -> Result<(), Box<dyn Error>> {
+    let mut local_sets = storage::load_fetched_sets(&args.out)?;
+    if !args.sets.is_empty() {
+        let wanted: HashSet<String> = args.sets.iter().map(|code| code.to_uppercase()).collect();
+        local_sets.retain(|local| wanted.contains(&local.set_code.to_uppercase()));
+        if local_sets.is_empty() {
+            return Err(format!(
+                "Error: None of the sets {} were fetched into '{}'.",
+                args.sets.join(", "),
+                args.out
+            )
+            .into());
+        }
+    }
+    let sets: Vec<(&str, &SetResponse)> =
+        local_sets.iter().map(|local| (local.set_code.as_str(), &local.set)).collect();
+    let report = ValidationReport::new(Local::now().to_rfc3339(), &sets);
+    print!("{}", validate::render_text(&report, args.limit));
+    report.write(&args.out)?;
+    info!("Wrote the report to {}/{}", args.out, validate::VALIDATION_REPORT_FILENAME);
+
+    if args.fail_on_anomalies && !report.anomalies.is_empty() {
+        return Err(format!("Error: Found {} anomalies.", report.anomalies.len()).into());
+    }
+    Ok(())
+}
+
+/// Fetches new and changed sets incrementally, again and again at a fixed interval.
+///
+/// A failing run is reported and retried at the next interval instead of stopping the
+/// watch. Runs lock the output directory like `fetch` does, so a fetch started by hand in
+/// the meantime fails instead of colliding with a run. A run also starts at midnight on the
+/// day a preview set is released, which moves the set into the main dataset.
+///
+/// # Arguments
+/// * `args` - The options of the `watch` command.
+/// * `http` - The HTTP client to send the requests with.
+/// * `source` - Where the API responses come from: the API or recorded fixtures.
+/// * `memory_profiler` - Records allocations of each stage when profiling is enabled.
+///
+/// # Returns
+/// An error if the options cannot be watched with; otherwise it never returns.
+fn run_watch(
+    mut args: WatchArgs,
+    http: &reqwest::blocking::Client,
+    source: &ApiSource,
+    memory_profiler: &MemoryProfiler,
+) -> Result<(), Box<dyn Error>> {
+    if args.fetch.resume {
+        return Err(
+            "Error: watch cannot --resume; every run already fetches what earlier runs missed."
+                .into(),
+        );
+    }
+    if args.fetch.dry_run {
+        return Err("Error: watch cannot --dry-run; run `fetch --dry-run` to see a plan.".into());
+    }
+    // Localized fetches do not use the manifest, so they are always fetched in full
+    args.fetch.incremental = args.fetch.languages.is_empty();
+
+    loop {
+        let started = Instant::now();
+        info!("[watch] Checking for new and changed sets in '{}'.", args.fetch.output.out);
+        if let Err(e) = run_fetch(&args.fetch, http, source, memory_profiler) {
+            error!("[watch] Run failed, retrying at the next interval: {}", e);
+        }
+        let mut wait = args.interval.saturating_sub(started.elapsed());
+        // A preview is moved into the main dataset on the day it is released
+        if let Some(release) = next_preview_release(&args.fetch.output.out)
+            && let Ok(until_release) = (release - Local::now()).to_std()
+            && until_release < wait
+        {
+            info!("[watch] A preview set is released at {}.", release.format("%Y-%m-%d %H:%M"));
+            wait = until_release;
+        }
+        let next_run = Local::now() + wait;
+        info!("[watch] Next run at {}.", next_run.format("%Y-%m-%d %H:%M:%S"));
+        thread::sleep(wait);
+    }
+}
+
+/// The start of the day the next preview set in an output directory is released, if any.
+fn next_preview_release(base_output_dir: &str) -> Option<DateTime<Local>> {
+    let manifest = Manifest::load(base_output_dir).ok()?;
+    let today = Local::now().date_naive();
+    manifest
+        .sets
+        .values()
+        .filter_map(|entry| entry.preview_until.as_deref())
+        .filter_map(|day| NaiveDate::parse_from_str(day, "%Y-%m-%d").ok())
+        .filter(|day| *day > today)
+        .min()
+        .and_then(|day| day.and_hms_opt(0, 0, 0)?.and_local_timezone(Local).earliest())
+}
+
+/// Lists the sets known to the API and optionally writes them to the set codes file.
+///
+/// # Arguments
+/// * `args` - The options of the `sets list` command.
+/// * `http` - The HTTP client to send the requests with.
+/// * `source` - Where the API responses come from: the API or recorded fixtures.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+fn run_sets_list(
+    args: &SetsListArgs,
+    http: &reqwest::blocking::Client,
+    source: &ApiSource,
+) -> Result<(), Box<dyn Error>> {
+    let discovered = source.client(http.clone()).discover_sets()?;
+
+    if args.write {
+        let all_codes: Vec<String> = discovered.iter().map(|set| set.code.clone()).collect();
+        storage::write_set_codes(&args.sets_file, &all_codes)?;
+        info!("Updated {} with {} set codes.", args.sets_file, all_codes.len());
+    }
+
+    let listed = match &args.since {
+        Some(since) => api::sets_released_after(discovered, since)?,
+        None => discovered,
+    };
+    for set in &listed {
+        println!(
+            "{:<6} {:<12} {}",
+            set.code,
+            set.release_date.as_deref().unwrap_or("-"),
+            set.name.as_deref().unwrap_or("")
+        );
+    }
+    Ok(())
+}
+
+/// Runs each command listed in a batch file within this process.
+///
+/// Lines hold the same arguments as the command line (e.g. `fetch --sets WTR`); blank lines
+/// and lines starting with `#` are ignored. Every command shares the HTTP client.
+///
+/// # Arguments
+/// * `args` - The options of the `batch` command.
+/// * `http` - The HTTP client to send the requests with.
+/// * `source` - Where the API responses come from: the API or recorded fixtures.
+/// * `config` - The settings filling in options a line does not give.
+/// * `memory_profiler` - Records allocations of each stage when profiling is enabled.
+///
+/// # Returns
+/// A `Result` indicating success, or the error of the first failing command unless
+/// `--keep-going` is set.
+fn run_batch(
+    args: &BatchArgs,
+    http: &reqwest::blocking::Client,
+    source: &ApiSource,
+    config: &Config,
+    memory_profiler: &MemoryProfiler,
+) -> Result<(), Box<dyn Error>> {
+    let content = fs::read_to_string(&args.file)
+        .map_err(|e| format!("Error: Could not read batch file '{}': {}", args.file, e))?;
+
+    let mut failures = 0;
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line_number = index + 1;
+        let _span = info_span!("batch", file = %args.file, line = line_number).entered();
+        info!("[batch] {}:{}: {}", args.file, line_number, line);
+
+        let result = parse_batch_line(line, config).and_then(|command| match command {
+            Command::Batch(_) => Err("batch files cannot run other batch files".into()),
+            Command::Watch(_) => Err("batch files cannot run watch, which never returns".into()),
+            command => run_command(command, http, source, config, memory_profiler),
+        });
+        if let Err(e) = result {
+            error!("[batch] {}:{} failed: {}", args.file, line_number, e);
+            if !args.keep_going {
+                return Err(e);
+            }
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        return Err(format!("{} batch command(s) failed", failures).into());
+    }
+    Ok(())
+}
+
+/// Parses one batch file line into a command, as if it were given on the command line.
+///
+/// # Arguments
+/// * `line` - The arguments of the command, quoted like in a shell.
+/// * `config` - The settings filling in options the line does not give.
+///
+/// # Returns
+/// A `Result` containing the parsed command, or an error describing the invalid line.
+fn parse_batch_line(line: &str, config: &Config) -> Result<Command, Box<dyn Error>> {
+    let words = shlex::split(line).ok_or("unbalanced quotes")?;
+    let args = std::iter::once(String::from("fab-scrapper")).chain(words);
+    let matches = Cli::command().try_get_matches_from(args)?;
+    let cli = Cli::from_arg_matches(&matches)?;
+    let mut command = cli.command.unwrap_or(Command::Fetch(Box::new(cli.fetch)));
+    config.apply(&mut command, &matches);
+    Ok(command)
+}
+
+/// Dispatches a parsed command to its implementation.
+///
+/// # Arguments
+/// * `command` - The command to run.
+/// * `http` - The HTTP client shared by every command.
+/// * `source` - Where the API responses come from: the API or recorded fixtures.
+/// * `config` - The settings of the config file, used by batch files.
+/// * `memory_profiler` - Records allocations of each stage when profiling is enabled.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+pub fn run_command(
+    command: Command,
+    http: &reqwest::blocking::Client,
+    source: &ApiSource,
+    config: &Config,
+    memory_profiler: &MemoryProfiler,
+) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Fetch(args) => run_fetch(&args, http, source, memory_profiler),
+        Command::Combine(args) => run_combine(&args),
+        Command::Batch(args) => run_batch(&args, http, source, config, memory_profiler),
+        Command::Sets(SetsCommand::List(args)) => run_sets_list(&args, http, source),
+        Command::Export(ExportCommand::Matchups(args)) => run_export_matchups(&args),
+        Command::Export(ExportCommand::Fabrary(args)) => {
+            run_export_catalog(ExportFormat::Fabrary, &args)
+        }
+        Command::Export(ExportCommand::Xlsx(args)) => run_export_catalog(ExportFormat::Xlsx, &args),
+        Command::Export(ExportCommand::Reprints(args)) => {
+            run_export_reprints(&args, http, source)
+        }
+        Command::Export(ExportCommand::Features(args)) => run_export_features(&args),
+        Command::Export(ExportCommand::CardPool(args)) => run_export_card_pool(&args),
+        Command::Images(args) => run_images(&args, http),
+        Command::Mirror(args) => run_mirror(&args, http, source),
+        Command::Card(CardCommand::Show(args)) => run_card_show(&args),
+        Command::Diff(args) => run_diff(&args),
+        Command::Completeness(args) => run_completeness(&args),
+        Command::Validate(args) => run_validate(&args),
+        Command::Watch(args) => run_watch(args, http, source, memory_profiler),
+        Command::Search(args) => run_search(&args),
+        Command::Stats(args) => run_stats(&args),
+        Command::Clean(args) => run_clean(&args),
+    }
+}
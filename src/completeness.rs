@@ -0,0 +1,209 @@
+//! Completeness scores of the fetched sets: how many card fields are populated, and how many
+//! cards have their image downloaded, rulings and translations.
+//!
+//! Every report is appended to `completeness.jsonl` in the output directory, so the scores
+//! can be followed over time and compared with the previous report.
+//!
+//! Sets, and the cards of each set, are scored in parallel: every card is checked on its own
+//! in a single pass, so scoring the full dataset scales with the number of cores.
+
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::images;
+use crate::model::{Card, Printing, SetResponse};
+
+// History of every completeness report, one JSON object per line, inside the output directory
+pub const HISTORY_FILENAME: &str = "completeness.jsonl";
+
+// Field of a card listing its rulings, when the API provides them
+const RULINGS_FIELD: &str = "rulings";
+
+// Fields of a card listing its translations, when the API provides them
+const TRANSLATION_FIELDS: [&str; 2] = ["translations", "languages"];
+
+// Number of fields checked on every card by `populated_fields`
+const CHECKED_FIELDS: usize = 7;
+
+/// The completeness of one set, as percentages of its cards.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SetCompleteness {
+    pub set_code: String,
+    pub cards: usize,
+    /// Share of the checked card and printing fields that are populated.
+    pub fields: f64,
+    /// Share of the cards whose image of this set has been downloaded.
+    pub images: f64,
+    /// Share of the cards with rulings.
+    pub rulings: f64,
+    /// Share of the cards with translations.
+    pub translations: f64,
+    /// The average of the four shares.
+    pub score: f64,
+}
+
+/// The completeness of every fetched set at one point in time.
+#[derive(Serialize, Deserialize)]
+pub struct Report {
+    pub generated_at: String,
+    pub sets: Vec<SetCompleteness>,
+}
+
+/// Scores the completeness of several sets in parallel.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory, whose `images/` folder is checked.
+/// * `sets` - The set codes and cards of the sets to score.
+///
+/// # Returns
+/// The scores of the sets, in the given order.
+pub fn score_sets(base_output_dir: &str, sets: &[(&str, &SetResponse)]) -> Vec<SetCompleteness> {
+    sets.par_iter()
+        .map(|(set_code, set)| score_set(base_output_dir, set_code, set))
+        .collect()
+}
+
+/// Scores the completeness of a set.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory, whose `images/` folder is checked.
+/// * `set_code` - The set code (e.g., "WTR").
+/// * `set` - The set's cards.
+pub fn score_set(base_output_dir: &str, set_code: &str, set: &SetResponse) -> SetCompleteness {
+    let cards = set.results.len();
+    // One pass over the cards, each thread summing populated fields, images, rulings and
+    // translations of its own cards
+    let (populated, with_image, with_rulings, with_translations) = set
+        .results
+        .par_iter()
+        .map(|card| {
+            (
+                populated_fields(card, set_code),
+                usize::from(images::image_on_disk(base_output_dir, set_code, card) == Some(true)),
+                usize::from(has_values(card, &[RULINGS_FIELD])),
+                usize::from(has_values(card, &TRANSLATION_FIELDS)),
+            )
+        })
+        .reduce(|| (0, 0, 0, 0), |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2, a.3 + b.3));
+    let fields = share(populated, cards * CHECKED_FIELDS);
+    let images = share(with_image, cards);
+    let rulings = share(with_rulings, cards);
+    let translations = share(with_translations, cards);
+    let score = ((fields + images + rulings + translations) / 4.0 * 10.0).round() / 10.0;
+
+    SetCompleteness {
+        set_code: set_code.to_string(),
+        cards,
+        fields,
+        images,
+        rulings,
+        translations,
+        score,
+    }
+}
+
+/// The number of checked fields a card has populated: its type line, text and types, and
+/// whether it has a printing in the set with a rarity, an image and an artist.
+fn populated_fields(card: &Card, set_code: &str) -> usize {
+    let printings: Vec<&Printing> = card
+        .printings
+        .iter()
+        .filter(|printing| {
+            printing
+                .set_code
+                .as_deref()
+                .is_none_or(|code| code.eq_ignore_ascii_case(set_code))
+        })
+        .collect();
+    let filled = |value: &Option<String>| value.as_deref().is_some_and(|v| !v.trim().is_empty());
+    [
+        filled(&card.type_text),
+        filled(&card.text),
+        !card.types.is_empty(),
+        !printings.is_empty(),
+        printings.iter().any(|printing| filled(&printing.rarity)),
+        printings.iter().any(|printing| filled(&printing.image_url)),
+        printings.iter().any(|printing| !printing.artists.is_empty()),
+    ]
+    .into_iter()
+    .filter(|populated| *populated)
+    .count()
+}
+
+/// Whether any of the given fields of a card holds a non-empty value.
+fn has_values(card: &Card, fields: &[&str]) -> bool {
+    fields.iter().any(|field| match card.extra.get(*field) {
+        Some(serde_json::Value::Array(values)) => !values.is_empty(),
+        Some(serde_json::Value::Object(values)) => !values.is_empty(),
+        Some(serde_json::Value::String(value)) => !value.trim().is_empty(),
+        _ => false,
+    })
+}
+
+/// `part` as a percentage of `total`, rounded to one decimal.
+fn share(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    (part as f64 * 1000.0 / total as f64).round() / 10.0
+}
+
+/// Reads the most recent report of an output directory's history.
+///
+/// # Returns
+/// A `Result` containing the last report, `None` if there is no history yet, or an error if
+/// the history cannot be read or its last line is invalid.
+pub fn latest(base_output_dir: &str) -> Result<Option<Report>, Box<dyn Error>> {
+    let path = Path::new(base_output_dir).join(HISTORY_FILENAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)?;
+    match content.lines().rev().find(|line| !line.trim().is_empty()) {
+        Some(line) => Ok(Some(serde_json::from_str(line)?)),
+        None => Ok(None),
+    }
+}
+
+/// Appends a report to an output directory's history.
+pub fn append(base_output_dir: &str, report: &Report) -> Result<(), Box<dyn Error>> {
+    let path = Path::new(base_output_dir).join(HISTORY_FILENAME);
+    let mut line = serde_json::to_string(report)?;
+    line.push('\n');
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Renders a report as a table, with the change of every score since the previous report.
+pub fn render_text(report: &Report, previous: Option<&Report>) -> String {
+    let mut out = format!(
+        "{:<8} {:>6} {:>8} {:>8} {:>8} {:>13} {:>8}\n",
+        "Set", "Cards", "Fields", "Images", "Rulings", "Translations", "Score"
+    );
+    for set in &report.sets {
+        let change = previous
+            .and_then(|previous| previous.sets.iter().find(|old| old.set_code == set.set_code))
+            .map(|old| set.score - old.score)
+            .filter(|change| change.abs() >= 0.05)
+            .map(|change| format!(" ({:+.1})", change))
+            .unwrap_or_default();
+        out.push_str(&format!(
+            "{:<8} {:>6} {:>7.1}% {:>7.1}% {:>7.1}% {:>12.1}% {:>7.1}%{}\n",
+            set.set_code,
+            set.cards,
+            set.fields,
+            set.images,
+            set.rulings,
+            set.translations,
+            set.score,
+            change
+        ));
+    }
+    out
+}
@@ -0,0 +1,343 @@
+//! Persistent settings from `fab-scrapper.toml`.
+//!
+//! The file is looked up in the working directory first, then in the user's config directory
+//! (`$XDG_CONFIG_HOME/fab-scrapper/` or `~/.config/fab-scrapper/`), unless `--config` names
+//! one. Its values replace the built-in defaults; options given on the command line still win.
+//!
+//! Older versions read the sets to fetch from `sets_codes.txt` only. On the first fetch that
+//! would read it without any config file, that file is imported into a new `fab-scrapper.toml` (see
+//! [`migrate_set_codes_file`]), so existing setups keep fetching the same sets.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::ArgMatches;
+use clap::parser::ValueSource;
+use serde::Deserialize;
+use tracing::warn;
+
+use fab_scrapper::api::{ApiSource, DEFAULT_BASE_URL, NetworkOptions};
+use fab_scrapper::fixtures::Fixtures;
+use fab_scrapper::storage::{self, OutputFormat};
+
+use crate::cli::{self, CardCommand, Cli, Command, ExportCommand, SetsCommand, WatchArgs};
+
+// Name of the config file, in the working directory or the user's config directory
+pub const CONFIG_FILENAME: &str = "fab-scrapper.toml";
+
+/// The settings a config file can hold; all of them are optional.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Output directory of every command.
+    pub out: Option<String>,
+    /// Output formats of `fetch` and `combine`.
+    pub formats: Option<Vec<OutputFormat>>,
+    /// Template of every card in the txt files of `fetch` and `combine`.
+    pub txt_template: Option<String>,
+    /// Delay between requests of `fetch` and `images`, in milliseconds.
+    pub delay_ms: Option<u64>,
+    /// Number of sets `fetch` downloads in parallel.
+    pub concurrency: Option<usize>,
+    /// User-Agent header sent with every request.
+    pub user_agent: Option<String>,
+    /// Proxy every request is sent through.
+    pub proxy: Option<String>,
+    /// Limit on a whole request, as an interval such as `30s`.
+    pub timeout: Option<String>,
+    /// Limit on connecting to the server or proxy, as an interval such as `10s`.
+    pub connect_timeout: Option<String>,
+    /// PEM file of certificate authorities to trust on top of the system ones.
+    pub ca_bundle: Option<String>,
+    /// Base URL of the card API, e.g. a mirror or a local mock.
+    pub api_url: Option<String>,
+    /// Whether `fetch` also downloads card images.
+    pub images: Option<bool>,
+    /// Sets `fetch` downloads when none are given, instead of reading the set codes file.
+    pub sets: Option<Vec<String>>,
+    /// URLs `fetch` posts the changes of a run to as JSON, unless `--webhook` is given.
+    pub webhooks: Option<Vec<String>>,
+    /// Discord webhook URLs `fetch` announces the changes of a run to, unless
+    /// `--discord-webhook` is given.
+    pub discord_webhooks: Option<Vec<String>>,
+    /// Destination `fetch` copies the output directory to after every run, unless `--upload`
+    /// is given.
+    pub upload: Option<String>,
+}
+
+impl Config {
+    /// Loads the config file.
+    ///
+    /// # Arguments
+    /// * `path` - The file given with `--config`, which must exist; otherwise the default
+    ///   locations are searched and a missing file means no settings.
+    ///
+    /// # Returns
+    /// A `Result` containing the settings and where they came from, or an error if the file
+    /// cannot be read or is invalid.
+    pub fn load(path: Option<&str>) -> Result<(Config, Option<PathBuf>), Box<dyn Error>> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match default_locations().into_iter().find(|path| path.is_file()) {
+                Some(path) => path,
+                None => return Ok((Config::default(), None)),
+            },
+        };
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Error: Could not read config file {}: {}", path.display(), e))?;
+        let config = toml::from_str(&content)
+            .map_err(|e| format!("Error: Invalid config file {}: {}", path.display(), e))?;
+        Ok((config, Some(path)))
+    }
+
+    /// Combines the network options of the command line with the settings.
+    ///
+    /// # Arguments
+    /// * `cli` - The parsed command line, whose global options take precedence.
+    ///
+    /// # Returns
+    /// A `Result` containing the options of the HTTP client, or an error if a timeout in the
+    /// settings is invalid.
+    pub fn network(&self, cli: &Cli) -> Result<NetworkOptions, Box<dyn Error>> {
+        let interval = |given: Option<Duration>, setting: &Option<String>, key: &str| {
+            match (given, setting) {
+                (Some(given), _) => Ok(Some(given)),
+                (None, Some(setting)) => cli::parse_interval(setting)
+                    .map(Some)
+                    .map_err(|e| format!("Error: Invalid {} in config file: {}", key, e)),
+                (None, None) => Ok(None),
+            }
+        };
+        let defaults = NetworkOptions::default();
+        Ok(NetworkOptions {
+            user_agent: cli
+                .user_agent
+                .clone()
+                .or_else(|| self.user_agent.clone())
+                .unwrap_or(defaults.user_agent),
+            proxy: cli.proxy.clone().or_else(|| self.proxy.clone()),
+            timeout: interval(cli.timeout, &self.timeout, "timeout")?.or(defaults.timeout),
+            connect_timeout: interval(
+                cli.connect_timeout,
+                &self.connect_timeout,
+                "connect_timeout",
+            )?,
+            ca_bundle: cli.ca_bundle.as_ref().or(self.ca_bundle.as_ref()).map(PathBuf::from),
+        })
+    }
+
+    /// Builds where API responses come from, from the command line and the settings.
+    ///
+    /// # Arguments
+    /// * `cli` - The parsed command line, whose options win over the settings.
+    ///
+    /// # Returns
+    /// A `Result` containing the source, or an error if the fixtures directory to replay cannot
+    /// be found or the one to record into cannot be created.
+    pub fn api_source(&self, cli: &Cli) -> Result<ApiSource, Box<dyn Error>> {
+        let fixtures = match (&cli.fixtures, &cli.record_fixtures) {
+            (Some(dir), _) => Some(Fixtures::replay(dir)?),
+            (None, Some(dir)) => Some(Fixtures::record(dir)?),
+            (None, None) => None,
+        };
+        let base_url = cli.api_url.as_ref().or(self.api_url.as_ref());
+        Ok(ApiSource {
+            base_url: base_url.map_or(DEFAULT_BASE_URL, String::as_str).to_string(),
+            fixtures,
+        })
+    }
+
+    /// Uses the template of the settings unless one was given on the command line.
+    fn apply_txt_template(&self, output: &mut cli::OutputArgs) {
+        if output.txt_template.is_none() && output.txt_template_file.is_none() {
+            output.txt_template.clone_from(&self.txt_template);
+        }
+    }
+
+    /// Fills in the options of a parsed command that were not given on the command line.
+    ///
+    /// # Arguments
+    /// * `command` - The parsed command.
+    /// * `matches` - The matches the command was parsed from, telling which options were
+    ///   given explicitly.
+    pub fn apply(&self, command: &mut Command, matches: &ArgMatches) {
+        let matches = innermost(matches);
+        let unset = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+        let set = |target: &mut String, value: &Option<String>, id: &str| {
+            if let Some(value) = value
+                && unset(id)
+            {
+                target.clone_from(value);
+            }
+        };
+
+        match command {
+            Command::Fetch(args) | Command::Watch(WatchArgs { fetch: args, .. }) => {
+                set(&mut args.output.out, &self.out, "out");
+                if let Some(formats) = &self.formats
+                    && unset("formats")
+                {
+                    args.output.formats.clone_from(formats);
+                }
+                self.apply_txt_template(&mut args.output);
+                if let Some(delay_ms) = self.delay_ms
+                    && unset("delay_ms")
+                {
+                    args.delay_ms = delay_ms;
+                }
+                if let Some(concurrency) = self.concurrency
+                    && unset("concurrency")
+                {
+                    args.concurrency = concurrency;
+                }
+                if let Some(images) = self.images
+                    && unset("images")
+                {
+                    args.images = images;
+                }
+                if let Some(webhooks) = &self.webhooks
+                    && args.webhooks.is_empty()
+                {
+                    args.webhooks.clone_from(webhooks);
+                }
+                if let Some(webhooks) = &self.discord_webhooks
+                    && args.discord_webhooks.is_empty()
+                {
+                    args.discord_webhooks.clone_from(webhooks);
+                }
+                if let Some(upload) = &self.upload
+                    && args.upload.is_none()
+                {
+                    args.upload = Some(upload.clone());
+                }
+                // An explicit --sets-file is read instead of the configured sets
+                if let Some(sets) = &self.sets
+                    && args.sets.is_empty()
+                    && unset("sets_file")
+                    && !args.all
+                    && !args.resume
+                {
+                    args.sets.clone_from(sets);
+                }
+                if self.sets.is_some() && args.all && !args.resume && unset("sets_file") {
+                    warn_sets_file_unread("--all", &args.sets_file);
+                }
+            }
+            Command::Combine(args) => {
+                set(&mut args.output.out, &self.out, "out");
+                if let Some(formats) = &self.formats
+                    && unset("formats")
+                {
+                    args.output.formats.clone_from(formats);
+                }
+                self.apply_txt_template(&mut args.output);
+            }
+            Command::Images(args) => {
+                set(&mut args.out, &self.out, "out");
+                if let Some(delay_ms) = self.delay_ms
+                    && unset("delay_ms")
+                {
+                    args.delay_ms = delay_ms;
+                }
+            }
+            Command::Mirror(args) => {
+                set(&mut args.out, &self.out, "out");
+                if let Some(delay_ms) = self.delay_ms
+                    && unset("delay_ms")
+                {
+                    args.delay_ms = delay_ms;
+                }
+            }
+            Command::Export(ExportCommand::Matchups(args)) => set(&mut args.out, &self.out, "out"),
+            Command::Export(ExportCommand::Reprints(args)) => set(&mut args.out, &self.out, "out"),
+            Command::Export(ExportCommand::Features(args)) => set(&mut args.out, &self.out, "out"),
+            Command::Export(ExportCommand::CardPool(args)) => set(&mut args.out, &self.out, "out"),
+            Command::Export(ExportCommand::Fabrary(args) | ExportCommand::Xlsx(args)) => {
+                set(&mut args.out, &self.out, "out")
+            }
+            Command::Card(CardCommand::Show(args)) => set(&mut args.out, &self.out, "out"),
+            Command::Diff(args) => set(&mut args.out, &self.out, "out"),
+            Command::Completeness(args) => set(&mut args.out, &self.out, "out"),
+            Command::Validate(args) => set(&mut args.out, &self.out, "out"),
+            Command::Search(args) => set(&mut args.out, &self.out, "out"),
+            Command::Stats(args) => set(&mut args.out, &self.out, "out"),
+            Command::Clean(args) => set(&mut args.out, &self.out, "out"),
+            Command::Sets(SetsCommand::List(args)) => {
+                if self.sets.is_some() && args.write && unset("sets_file") {
+                    warn_sets_file_unread("sets list --write", &args.sets_file);
+                }
+            }
+            Command::Batch(_) => {}
+        }
+    }
+}
+
+/// Warns that a command updates the legacy set codes file, which is not read while the config
+/// file lists the sets to fetch.
+fn warn_sets_file_unread(command: &str, sets_file: &str) {
+    warn!(
+        "{} updates {}, but the sets to fetch are read from the `sets` key of {}; list the new \
+        sets there, or pass --sets-file, to fetch them on later runs.",
+        command, sets_file, CONFIG_FILENAME
+    );
+}
+
+/// Imports a legacy set codes file into a new config file in the working directory.
+///
+/// Nothing is migrated once a config file exists in any of the default locations, or when
+/// the set codes file is missing or empty. The set codes file itself is left in place for
+/// commands that still update it and for older versions.
+///
+/// # Arguments
+/// * `set_codes_file` - The legacy set codes file, one code per line.
+///
+/// # Returns
+/// A `Result` containing the path of the new config file, `None` if there was nothing to
+/// migrate, or an error if the set codes or the config file could not be read or written.
+pub fn migrate_set_codes_file(set_codes_file: &str) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    if default_locations().iter().any(|path| path.exists()) || !Path::new(set_codes_file).is_file()
+    {
+        return Ok(None);
+    }
+    let codes = storage::read_set_codes(set_codes_file)?;
+    if codes.is_empty() {
+        return Ok(None);
+    }
+    let sets = toml::Value::Array(codes.into_iter().map(toml::Value::String).collect());
+    let content = format!(
+        "# Imported from {} on {}. The sets below are fetched when --sets/--all are not\n\
+        # given, and {} is no longer read for them.\n\
+        sets = {}\n",
+        set_codes_file,
+        chrono::Local::now().format("%Y-%m-%d"),
+        set_codes_file,
+        sets
+    );
+    let path = PathBuf::from(CONFIG_FILENAME);
+    fs::write(&path, content)
+        .map_err(|e| format!("Error: Could not write config file {}: {}", path.display(), e))?;
+    Ok(Some(path))
+}
+
+/// The places searched for the config file, in order.
+fn default_locations() -> Vec<PathBuf> {
+    let mut locations = vec![PathBuf::from(CONFIG_FILENAME)];
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(config_home) = config_home {
+        locations.push(config_home.join("fab-scrapper").join(CONFIG_FILENAME));
+    }
+    locations
+}
+
+/// The matches of the innermost subcommand, which hold its options.
+fn innermost(matches: &ArgMatches) -> &ArgMatches {
+    let mut matches = matches;
+    while let Some((_, sub_matches)) = matches.subcommand() {
+        matches = sub_matches;
+    }
+    matches
+}
@@ -0,0 +1,283 @@
+//! Changelogs between two versions of a set: new cards and printings, changed card text and
+//! stats (errata), and removals.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::model::{Card, SetResponse};
+
+/// Every change the latest fetch made to the card data.
+#[derive(Serialize)]
+pub struct Changelog {
+    pub generated_at: String,
+    pub sets: Vec<SetChanges>,
+}
+
+/// The changes to a single set.
+#[derive(Serialize)]
+pub struct SetChanges {
+    pub set_code: String,
+    /// Whether the set had not been fetched before.
+    pub new_set: bool,
+    pub added: Vec<CardRef>,
+    pub new_printings: Vec<NewPrintings>,
+    pub changed: Vec<CardChanges>,
+    pub removed: Vec<CardRef>,
+}
+
+#[derive(Serialize)]
+pub struct CardRef {
+    pub card_id: String,
+    pub name: String,
+}
+
+/// Printings that appeared for a card that was already listed.
+#[derive(Serialize)]
+pub struct NewPrintings {
+    pub card_id: String,
+    pub name: String,
+    pub print_ids: Vec<String>,
+}
+
+/// The fields of a card whose value changed.
+#[derive(Serialize)]
+pub struct CardChanges {
+    pub card_id: String,
+    pub name: String,
+    pub fields: Vec<FieldChange>,
+}
+
+#[derive(Serialize)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl SetChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.new_printings.is_empty()
+            && self.changed.is_empty()
+            && self.removed.is_empty()
+    }
+}
+
+/// The fields compared between two versions of a card.
+fn compared_fields(card: &Card) -> [(&'static str, Option<String>); 11] {
+    let joined = |values: &[String]| (!values.is_empty()).then(|| values.join(", "));
+    [
+        ("name", Some(card.name.clone())),
+        ("pitch", card.pitch.clone()),
+        ("cost", card.cost.clone()),
+        ("power", card.power.clone()),
+        ("defense", card.defense.clone()),
+        ("health", card.health.clone()),
+        ("intelligence", card.intelligence.clone()),
+        ("types", joined(&card.types)),
+        ("type_text", card.type_text.clone()),
+        ("text", card.text.clone()),
+        ("keywords", joined(&card.keywords)),
+    ]
+}
+
+/// Compares two versions of a set.
+///
+/// # Arguments
+/// * `set_code` - The set code (e.g., "WTR").
+/// * `previous` - The set before the latest fetch; empty if it had not been fetched before.
+/// * `current` - The set as fetched by the latest run.
+pub fn compare_set(set_code: &str, previous: &SetResponse, current: &SetResponse) -> SetChanges {
+    let card_ref = |card: &Card| CardRef { card_id: card.card_id.clone(), name: card.name.clone() };
+    let previous_cards: HashMap<&str, &Card> =
+        previous.results.iter().map(|card| (card.card_id.as_str(), card)).collect();
+    let current_ids: Vec<&str> = current.results.iter().map(|card| card.card_id.as_str()).collect();
+
+    let mut changes = SetChanges {
+        set_code: set_code.to_string(),
+        new_set: previous.results.is_empty(),
+        added: Vec::new(),
+        new_printings: Vec::new(),
+        changed: Vec::new(),
+        removed: Vec::new(),
+    };
+    for card in &current.results {
+        let Some(before) = previous_cards.get(card.card_id.as_str()) else {
+            changes.added.push(card_ref(card));
+            continue;
+        };
+
+        let print_ids: Vec<String> = card
+            .printings
+            .iter()
+            .filter(|printing| {
+                !before.printings.iter().any(|known| known.print_id == printing.print_id)
+            })
+            .map(|printing| printing.print_id.clone())
+            .collect();
+        if !print_ids.is_empty() {
+            changes.new_printings.push(NewPrintings {
+                card_id: card.card_id.clone(),
+                name: card.name.clone(),
+                print_ids,
+            });
+        }
+
+        let fields: Vec<FieldChange> = compared_fields(before)
+            .into_iter()
+            .zip(compared_fields(card))
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((field, before), (_, after))| FieldChange { field, before, after })
+            .collect();
+        if !fields.is_empty() {
+            changes.changed.push(CardChanges {
+                card_id: card.card_id.clone(),
+                name: card.name.clone(),
+                fields,
+            });
+        }
+    }
+    changes.removed = previous
+        .results
+        .iter()
+        .filter(|card| !current_ids.contains(&card.card_id.as_str()))
+        .map(card_ref)
+        .collect();
+    changes
+}
+
+/// Renders a changelog for reading in the terminal.
+pub fn render_text(changelog: &Changelog) -> String {
+    let changed_sets: Vec<&SetChanges> =
+        changelog.sets.iter().filter(|set| !set.is_empty()).collect();
+    if changed_sets.is_empty() {
+        return String::from("No cards changed in the latest fetch.\n");
+    }
+
+    let mut out = String::new();
+    for set in changed_sets {
+        if set.new_set {
+            out.push_str(&format!("{} (new set)\n", set.set_code));
+        } else {
+            out.push_str(&format!("{}\n", set.set_code));
+        }
+        for card in &set.added {
+            out.push_str(&format!("  + {} ({})\n", card.name, card.card_id));
+        }
+        for card in &set.new_printings {
+            let print_ids = card.print_ids.join(", ");
+            out.push_str(&format!("  + {}: new printings {}\n", card.name, print_ids));
+        }
+        for card in &set.changed {
+            out.push_str(&format!("  ~ {} ({})\n", card.name, card.card_id));
+            for field in &card.fields {
+                out.push_str(&format!(
+                    "      {}: {} -> {}\n",
+                    field.field,
+                    field.before.as_deref().unwrap_or("(none)"),
+                    field.after.as_deref().unwrap_or("(none)")
+                ));
+            }
+        }
+        for card in &set.removed {
+            out.push_str(&format!("  - {} ({})\n", card.name, card.card_id));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn set(cards: Value) -> SetResponse {
+        serde_json::from_value(json!({ "results": cards })).unwrap()
+    }
+
+    fn previous() -> SetResponse {
+        set(json!([
+            {
+                "card_id": "snatch-red",
+                "name": "Snatch",
+                "pitch": 1,
+                "cost": 0,
+                "text": "If Snatch hits, draw a card.",
+                "printings": [{"print_id": "WTR163"}]
+            },
+            {"card_id": "sigil-of-solace-red", "name": "Sigil of Solace", "printings": []},
+            {"card_id": "energy-potion", "name": "Energy Potion", "printings": []}
+        ]))
+    }
+
+    #[test]
+    fn reports_added_changed_removed_cards_and_new_printings() {
+        let current = set(json!([
+            {
+                "card_id": "snatch-red",
+                "name": "Snatch",
+                "pitch": 1,
+                "cost": 1,
+                "text": "When Snatch hits, draw a card.",
+                "printings": [{"print_id": "WTR163"}, {"print_id": "1HP357"}]
+            },
+            {"card_id": "energy-potion", "name": "Energy Potion", "printings": []},
+            {"card_id": "command-and-conquer", "name": "Command and Conquer", "printings": []}
+        ]));
+        let changes = compare_set("WTR", &previous(), &current);
+
+        assert!(!changes.new_set && !changes.is_empty());
+        let ids = |cards: &[CardRef]| cards.iter().map(|card| card.card_id.clone()).collect();
+        let added: Vec<String> = ids(&changes.added);
+        assert_eq!(added, ["command-and-conquer"]);
+        let removed: Vec<String> = ids(&changes.removed);
+        assert_eq!(removed, ["sigil-of-solace-red"]);
+        assert_eq!(changes.new_printings.len(), 1);
+        assert_eq!(changes.new_printings[0].print_ids, ["1HP357"]);
+
+        assert_eq!(changes.changed.len(), 1);
+        let fields: Vec<(&str, Option<&str>, Option<&str>)> = changes.changed[0]
+            .fields
+            .iter()
+            .map(|change| (change.field, change.before.as_deref(), change.after.as_deref()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("cost", Some("0"), Some("1")),
+                (
+                    "text",
+                    Some("If Snatch hits, draw a card."),
+                    Some("When Snatch hits, draw a card.")
+                ),
+            ]
+        );
+
+        let text = render_text(&Changelog { generated_at: String::new(), sets: vec![changes] });
+        assert!(text.starts_with("WTR\n"), "{}", text);
+        assert!(text.contains("  + Command and Conquer (command-and-conquer)\n"), "{}", text);
+        assert!(text.contains("  + Snatch: new printings 1HP357\n"), "{}", text);
+        assert!(text.contains("      cost: 0 -> 1\n"), "{}", text);
+        assert!(text.contains("  - Sigil of Solace (sigil-of-solace-red)\n"), "{}", text);
+    }
+
+    #[test]
+    fn a_set_fetched_for_the_first_time_is_all_added() {
+        let changes = compare_set("WTR", &set(json!([])), &previous());
+        assert!(changes.new_set);
+        assert_eq!(changes.added.len(), 3);
+        assert!(changes.changed.is_empty() && changes.removed.is_empty());
+        let text = render_text(&Changelog { generated_at: String::new(), sets: vec![changes] });
+        assert!(text.starts_with("WTR (new set)\n"), "{}", text);
+    }
+
+    #[test]
+    fn an_unchanged_set_has_no_changes() {
+        let changes = compare_set("WTR", &previous(), &previous());
+        assert!(changes.is_empty());
+        let text = render_text(&Changelog { generated_at: String::new(), sets: vec![changes] });
+        assert_eq!(text, "No cards changed in the latest fetch.\n");
+    }
+}
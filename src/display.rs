@@ -0,0 +1,332 @@
+//! Terminal rendering of single cards for `card show`, of the summary table ending a fetch,
+//! and of the plan of a dry run.
+//!
+//! Colours are plain ANSI escape codes, left out when the output is not a terminal, when
+//! `NO_COLOR` is set or when `--no-color` is given.
+
+use std::io::IsTerminal;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use fab_scrapper::model::Card;
+use fab_scrapper::plan::FetchPlan;
+use fab_scrapper::report::SetReport;
+
+// Width rules text is wrapped to
+const TEXT_WIDTH: usize = 80;
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+const RESET: &str = "\x1b[0m";
+
+/// Whether card output should be colourized.
+///
+/// # Arguments
+/// * `no_color` - Whether colours were turned off on the command line.
+pub fn use_color(no_color: bool) -> bool {
+    !no_color && std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+}
+
+/// Renders the summary table of a fetch, one row per set.
+///
+/// # Arguments
+/// * `sets` - What happened to every set, as recorded in the run report.
+///
+/// # Returns
+/// The table to print, ending with a newline.
+pub fn render_run_summary(sets: &[SetReport]) -> String {
+    let count = |value: Option<usize>| value.map_or_else(|| String::from("-"), |n| n.to_string());
+    let mut out = format!(
+        "{:<6} {:>6} {:>5} {:>5} {:>7} {:>6} {:>9}  Status\n",
+        "Set", "Cards", "Pages", "New", "Changed", "Images", "Duration"
+    );
+    for set in sets {
+        out.push_str(&format!(
+            "{:<6} {:>6} {:>5} {:>5} {:>7} {:>6} {:>8.2}s  {}\n",
+            set.set_code,
+            count(set.cards),
+            set.pages,
+            count(set.new_cards),
+            count(set.changed_cards),
+            count(set.images),
+            set.durations.total,
+            set.status
+        ));
+    }
+    out
+}
+
+/// Renders the plan of a dry run: one row per set, then the totals of the run.
+///
+/// # Arguments
+/// * `plan` - The plan of every set.
+/// * `delay` - The delay between requests, which bounds how fast the run can go.
+///
+/// # Returns
+/// The plan to print, ending with a newline.
+pub fn render_fetch_plan(plan: &FetchPlan, delay: Duration) -> String {
+    let requests = |min: usize, max: Option<usize>| match max {
+        Some(max) if max == min => min.to_string(),
+        Some(max) => format!("{}-{}", min, max),
+        None => format!("{}+", min),
+    };
+    let count = |value: Option<usize>| value.map_or_else(|| String::from("-"), |n| n.to_string());
+    let mut out = format!(
+        "{:<6} {:<10} {:>5} {:>8} {:>10} {:>6}\n",
+        "Set", "Action", "Pages", "Requests", "Download", "Images"
+    );
+    for set in &plan.sets {
+        out.push_str(&format!(
+            "{:<6} {:<10} {:>5} {:>8} {:>10} {:>6}\n",
+            set.set_code,
+            set.action.name(),
+            count(set.pages),
+            requests(set.min_requests, set.max_requests),
+            set.download_bytes.map_or_else(|| String::from("?"), format_size),
+            count(set.missing_images)
+        ));
+    }
+
+    let images = plan.missing_images();
+    let max_requests = plan.max_requests();
+    let duration = |requests: usize| format_duration(delay * (requests + images) as u32);
+    out.push_str(&format!(
+        "\n{} set(s), {} request(s) for card data",
+        plan.sets.len(),
+        requests(plan.min_requests(), max_requests)
+    ));
+    match max_requests {
+        Some(max) if max != plan.min_requests() => out.push_str(&format!(
+            ", taking {} to {} at one request every {} ms\n",
+            duration(plan.min_requests()),
+            duration(max),
+            delay.as_millis()
+        )),
+        Some(max) => out.push_str(&format!(
+            ", taking {} at one request every {} ms\n",
+            duration(max),
+            delay.as_millis()
+        )),
+        None => out.push_str(&format!(
+            ", taking at least {} at one request every {} ms\n",
+            duration(plan.min_requests()),
+            delay.as_millis()
+        )),
+    }
+    match plan.unknown_sizes() {
+        0 => out.push_str(&format!("Card data: up to {}\n", format_size(plan.download_bytes()))),
+        unknown if unknown == plan.sets.len() => {
+            out.push_str("Card data: unknown, as none of the sets was fetched before\n")
+        }
+        unknown => out.push_str(&format!(
+            "Card data: up to {}, plus {} set(s) of unknown size\n",
+            format_size(plan.download_bytes()),
+            unknown
+        )),
+    }
+    if plan.sets.iter().any(|set| set.missing_images.is_some()) || images > 0 {
+        out.push_str(&format!("Images: {} to download", images));
+        if let Some(size) = plan.average_image_size.filter(|_| images > 0) {
+            out.push_str(&format!(" (about {})", format_size(size * images as u64)));
+        }
+        let unknown = plan.sets.iter().filter(|set| set.missing_images.is_none()).count();
+        if unknown > 0 {
+            out.push_str(&format!(", plus those of {} set(s) not fetched yet", unknown));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Formats a number of bytes, e.g. `12.5 MiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Formats a duration to the second, e.g. `1h 05m` or `3m 20s`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m {:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Wraps text in an ANSI style, or leaves it as-is when colours are off.
+struct Style {
+    color: bool,
+}
+
+impl Style {
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", code, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Renders a card with its stats, rules text, printings and legality.
+///
+/// # Arguments
+/// * `card` - The card to render, with the printings of every fetched set.
+/// * `color` - Whether to use ANSI colours.
+///
+/// # Returns
+/// The text to print, ending with a newline.
+pub fn render_card(card: &Card, color: bool) -> String {
+    let style = Style { color };
+    let mut out = String::new();
+
+    let pitch = card.pitch.as_deref().map(|pitch| (pitch, pitch_color(pitch)));
+    let title = match pitch {
+        Some((_, Some(code))) => style.paint(code, &style.paint(BOLD, &card.name)),
+        _ => style.paint(BOLD, &card.name),
+    };
+    out.push_str(&title);
+    out.push('\n');
+    let type_line = card.type_text.clone().unwrap_or_else(|| card.types.join(" "));
+    if !type_line.is_empty() {
+        out.push_str(&style.paint(DIM, &type_line));
+        out.push('\n');
+    }
+    out.push_str(&style.paint(DIM, &card.card_id));
+    out.push_str("\n\n");
+
+    let stats: Vec<String> = [
+        ("Pitch", &card.pitch),
+        ("Cost", &card.cost),
+        ("Power", &card.power),
+        ("Defense", &card.defense),
+        ("Health", &card.health),
+        ("Intelligence", &card.intelligence),
+    ]
+    .into_iter()
+    .filter_map(|(label, value)| {
+        let value = value.as_deref()?;
+        let painted = match (label, pitch_color(value)) {
+            ("Pitch", Some(code)) => style.paint(code, value),
+            _ => style.paint(BOLD, value),
+        };
+        Some(format!("{} {}", label, painted))
+    })
+    .collect();
+    if !stats.is_empty() {
+        out.push_str(&stats.join("   "));
+        out.push_str("\n\n");
+    }
+
+    if let Some(text) = card.text.as_deref().filter(|text| !text.trim().is_empty()) {
+        for paragraph in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            // Bold phrases may be wrapped onto the next line
+            let mut bold = false;
+            for line in wrap(paragraph, TEXT_WIDTH) {
+                out.push_str(&format_markup(&line, bold, &style));
+                out.push('\n');
+                bold ^= line.matches("**").count() % 2 == 1;
+            }
+        }
+        out.push('\n');
+    }
+
+    if !card.keywords.is_empty() {
+        out.push_str(&format!("Keywords: {}\n\n", card.keywords.join(", ")));
+    }
+
+    if !card.printings.is_empty() {
+        out.push_str(&style.paint(BOLD, "Printings"));
+        out.push('\n');
+        for printing in &card.printings {
+            let fields = [
+                printing.set_code.as_deref().unwrap_or("-"),
+                printing.rarity.as_deref().unwrap_or("-"),
+                printing.foiling.as_deref().unwrap_or("-"),
+                printing.edition.as_deref().unwrap_or("-"),
+            ];
+            out.push_str(&format!("  {:<12} {}", printing.print_id, fields.join("  ")));
+            if !printing.artists.is_empty() {
+                out.push_str(&format!("  {}", style.paint(DIM, &printing.artists.join(", "))));
+            }
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+
+    if let Some(Value::Object(legality)) = card.extra.get("legality") {
+        let formats: Vec<String> = legality
+            .iter()
+            .filter_map(|(format, legal)| {
+                let legal = legal.as_bool()?;
+                let (code, mark) = if legal { (GREEN, "legal") } else { (RED, "not legal") };
+                Some(format!("{} {}", format.to_uppercase(), style.paint(code, mark)))
+            })
+            .collect();
+        if !formats.is_empty() {
+            out.push_str(&format!("Legality: {}\n", formats.join(", ")));
+        }
+    }
+    out
+}
+
+/// The colour of a pitch value: 1 is red, 2 yellow and 3 blue.
+fn pitch_color(pitch: &str) -> Option<&'static str> {
+    match pitch.trim() {
+        "1" => Some(RED),
+        "2" => Some(YELLOW),
+        "3" => Some(BLUE),
+        _ => None,
+    }
+}
+
+/// Turns the `**bold**` markup of rules text into bold text, or strips it without colours.
+///
+/// # Arguments
+/// * `line` - A line of rules text.
+/// * `bold` - Whether the line starts inside a bold phrase.
+/// * `style` - Whether to use ANSI colours.
+fn format_markup(line: &str, bold: bool, style: &Style) -> String {
+    line.split("**")
+        .enumerate()
+        .map(|(index, part)| {
+            if (index % 2 == 1) != bold { style.paint(BOLD, part) } else { part.to_string() }
+        })
+        .collect()
+}
+
+/// Greedily wraps text at word boundaries, ignoring markup when measuring; words longer
+/// than `width` get their own line.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let visible = |text: &str| text.replace("**", "").chars().count();
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && visible(&line) + 1 + visible(word) > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
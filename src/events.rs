@@ -0,0 +1,91 @@
+//! Append-only JSONL log of every change a run makes to the dataset.
+//!
+//! Each line is one event with a timestamp, so downstream tools can tail the file and react
+//! to new data instead of polling the output directory. Lines are appended under an
+//! exclusive file lock, keeping them whole when several workers or processes write at once.
+
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::Local;
+use fs2::FileExt;
+use serde::Serialize;
+use tracing::warn;
+
+// Name of the event log inside the output directory
+pub const EVENT_LOG_FILENAME: &str = "events.jsonl";
+
+/// A change to the dataset.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// A set was downloaded and differs from the previous run.
+    SetFetched { set_code: &'a str, card_count: usize, sha256: &'a str },
+    /// A set was checked and found unchanged.
+    SetUnchanged { set_code: &'a str },
+    /// A card appeared in a set.
+    CardAdded { set_code: &'a str, card_id: &'a str, name: &'a str },
+    /// A card is no longer listed in a set.
+    CardRemoved { set_code: &'a str, card_id: &'a str },
+    /// An output file was written.
+    FileWritten { path: &'a str },
+    /// A card image was downloaded.
+    ImageDownloaded { path: &'a str, url: &'a str },
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    timestamp: String,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// The event log of an output directory, or a no-op when logging is disabled.
+pub struct EventLog {
+    file: Option<Mutex<File>>,
+}
+
+impl EventLog {
+    /// Opens the event log of an output directory for appending, creating it if needed.
+    pub fn open(base_output_dir: &str) -> Result<Self, Box<dyn Error>> {
+        let path = Path::new(base_output_dir).join(EVENT_LOG_FILENAME);
+        let file = OpenOptions::new().create(true).append(true).open(&path).map_err(|e| {
+            format!("Error: Could not open event log {}: {}", path.display(), e)
+        })?;
+        Ok(EventLog { file: Some(Mutex::new(file)) })
+    }
+
+    /// An event log that discards every event.
+    pub fn disabled() -> Self {
+        EventLog { file: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Appends an event. Failures are reported as warnings; they never stop a run.
+    pub fn record(&self, event: Event) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let record = Record { timestamp: Local::now().to_rfc3339(), event: &event };
+        if let Err(e) = append_line(file, &record) {
+            warn!("Could not append to the event log: {}", e);
+        }
+    }
+}
+
+fn append_line(file: &Mutex<File>, record: &Record) -> Result<(), Box<dyn Error>> {
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+
+    let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+    file.lock_exclusive()?;
+    let written = file.write_all(line.as_bytes());
+    FileExt::unlock(&*file)?;
+    Ok(written?)
+}
@@ -0,0 +1,351 @@
+//! The card pool of a hero: every fetched card a deck built around the hero may include.
+//!
+//! The deck-building rules applied are those of the comprehensive rules:
+//!
+//! - A card's supertypes — its classes and talents — must all be supertypes of the hero.
+//!   `Generic` cards have none, so every hero may play them.
+//! - A specialization card (`Bravo Specialization`) is only legal with the hero it names.
+//! - Heroes and tokens are never part of a card pool.
+//!
+//! Format-specific limits such as the banned list or rarity restrictions are not applied.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use serde_json::json;
+
+use crate::model::{Card, SetResponse};
+use crate::suggest;
+
+// Classes a card or hero can have
+const CLASSES: [&str; 17] = [
+    "Adjudicator",
+    "Assassin",
+    "Bard",
+    "Brute",
+    "Guardian",
+    "Illusionist",
+    "Mechanologist",
+    "Merchant",
+    "Necromancer",
+    "Ninja",
+    "Pirate",
+    "Ranger",
+    "Runeblade",
+    "Shapeshifter",
+    "Thief",
+    "Warrior",
+    "Wizard",
+];
+
+// Talents a card or hero can have
+const TALENTS: [&str; 12] = [
+    "Chaos",
+    "Draconic",
+    "Earth",
+    "Elemental",
+    "Ice",
+    "Light",
+    "Lightning",
+    "Mystic",
+    "Revered",
+    "Reviled",
+    "Royal",
+    "Shadow",
+];
+
+// Word following the hero's name in the text of a specialization card
+const SPECIALIZATION: &str = "specialization";
+
+/// A hero and the supertypes its card pool is built from.
+pub struct PoolHero<'a> {
+    pub card: &'a Card,
+    pub classes: Vec<&'a str>,
+    pub talents: Vec<&'a str>,
+}
+
+impl<'a> PoolHero<'a> {
+    pub fn new(card: &'a Card) -> Self {
+        PoolHero { card, classes: supertypes(card, &CLASSES), talents: supertypes(card, &TALENTS) }
+    }
+
+    /// The name specialization cards refer to the hero by: its name up to the first comma,
+    /// e.g. "Bravo" for "Bravo, Showstopper".
+    pub fn short_name(&self) -> &'a str {
+        short_name(self.card)
+    }
+
+    /// Whether a card is legal in the hero's card pool.
+    pub fn allows(&self, card: &Card) -> bool {
+        if has_type(card, "Hero") || has_type(card, "Token") {
+            return false;
+        }
+        let classes_allowed =
+            supertypes(card, &CLASSES).iter().all(|class| self.classes.contains(class));
+        let talents_allowed =
+            supertypes(card, &TALENTS).iter().all(|talent| self.talents.contains(talent));
+        classes_allowed && talents_allowed && self.allows_specialization(card)
+    }
+
+    /// Whether a card is not a specialization, or one of this hero's.
+    fn allows_specialization(&self, card: &Card) -> bool {
+        let text = card.text.as_deref().unwrap_or_default().to_lowercase();
+        let is_specialization = text.contains(SPECIALIZATION)
+            || card.keywords.iter().any(|keyword| keyword.eq_ignore_ascii_case(SPECIALIZATION));
+        if !is_specialization {
+            return true;
+        }
+        let own = format!("{} {}", self.short_name().to_lowercase(), SPECIALIZATION);
+        text.contains(&own)
+    }
+}
+
+/// A hero's name up to the first comma.
+fn short_name(card: &Card) -> &str {
+    card.name.split(',').next().unwrap_or_default().trim()
+}
+
+/// Whether a card has a type, ignoring case.
+fn has_type(card: &Card, wanted: &str) -> bool {
+    card.types.iter().any(|t| t.eq_ignore_ascii_case(wanted))
+}
+
+/// The types of a card that are among the given supertypes, as spelled in the list.
+fn supertypes<'a>(card: &Card, known: &[&'a str]) -> Vec<&'a str> {
+    known.iter().copied().filter(|supertype| has_type(card, supertype)).collect()
+}
+
+/// Finds a hero by card id or name among the given cards.
+///
+/// The query is matched against card ids, then full names, then names up to the first comma
+/// ("Bravo"), and last against parts of names, as long as it names a single hero.
+///
+/// # Arguments
+/// * `cards` - The cards to look in; only those typed `Hero` are candidates.
+/// * `query` - The card id or name looked for.
+///
+/// # Returns
+/// A `Result` containing the hero, or an error if no hero or several match.
+pub fn find_hero<'a>(cards: &[&'a Card], query: &str) -> Result<&'a Card, Box<dyn Error>> {
+    let mut heroes: BTreeMap<&str, &Card> = BTreeMap::new();
+    for card in cards.iter().copied().filter(|card| has_type(card, "Hero")) {
+        heroes.entry(card.card_id.as_str()).or_insert(card);
+    }
+    let query = query.trim();
+    let lowercase = query.to_lowercase();
+    let tests: [&dyn Fn(&Card) -> bool; 4] = [
+        &|card| card.card_id.to_lowercase() == lowercase,
+        &|card| card.name.to_lowercase() == lowercase,
+        &|card| short_name(card).to_lowercase() == lowercase,
+        &|card| card.name.to_lowercase().contains(&lowercase),
+    ];
+    for test in tests {
+        let matches: Vec<&Card> = heroes.values().copied().filter(|card| test(card)).collect();
+        match matches.as_slice() {
+            [] => continue,
+            [hero] => return Ok(hero),
+            candidates => {
+                let names: Vec<String> = candidates
+                    .iter()
+                    .map(|card| format!("{} ({})", card.name, card.card_id))
+                    .collect();
+                return Err(format!(
+                    "Error: '{}' matches {} heroes: {}. Give the card id to pick one.",
+                    query,
+                    candidates.len(),
+                    names.join(", ")
+                )
+                .into());
+            }
+        }
+    }
+    let suggestion = suggest::did_you_mean(query, heroes.values().map(|card| card.name.as_str()));
+    Err(format!("Error: No hero matching '{}'.{}", query, suggestion).into())
+}
+
+/// Collects the card pool of a hero from the given sets, ordered by name and card id.
+///
+/// A card printed in several sets is listed once.
+pub fn card_pool<'a>(hero: &PoolHero, sets: &[&'a SetResponse]) -> Vec<&'a Card> {
+    let mut pool: BTreeMap<(&str, &str), &Card> = BTreeMap::new();
+    for card in sets.iter().flat_map(|set| &set.results) {
+        if hero.allows(card) {
+            pool.entry((card.name.as_str(), card.card_id.as_str())).or_insert(card);
+        }
+    }
+    pool.into_values().collect()
+}
+
+/// Renders a card pool as CSV, one row per card.
+pub fn render_csv(pool: &[&Card]) -> Result<String, Box<dyn Error>> {
+    let mut writer = ::csv::Writer::from_writer(Vec::new());
+    writer.write_record(["card_id", "name", "pitch", "cost", "type_text"])?;
+    for card in pool {
+        writer.write_record([
+            card.card_id.as_str(),
+            card.name.as_str(),
+            card.pitch.as_deref().unwrap_or_default(),
+            card.cost.as_deref().unwrap_or_default(),
+            card.type_text.as_deref().unwrap_or_default(),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Renders a card pool as JSON, with the hero it was built for.
+///
+/// # Arguments
+/// * `hero` - The hero of the pool.
+/// * `pool` - The cards of the pool.
+/// * `generated_at` - When the pool was built.
+/// * `source_sets` - The set codes the cards were taken from.
+pub fn render_json(
+    hero: &PoolHero,
+    pool: &[&Card],
+    generated_at: &str,
+    source_sets: &[&str],
+) -> Result<String, Box<dyn Error>> {
+    let document = json!({
+        "generated_at": generated_at,
+        "source_sets": source_sets,
+        "hero": {
+            "card_id": hero.card.card_id,
+            "name": hero.card.name,
+            "classes": hero.classes,
+            "talents": hero.talents,
+        },
+        "count": pool.len(),
+        "cards": pool
+            .iter()
+            .map(|card| json!({
+                "card_id": card.card_id,
+                "name": card.name,
+                "pitch": card.pitch,
+                "types": card.types,
+            }))
+            .collect::<Vec<_>>(),
+    });
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(card_id: &str, name: &str, types: &[&str], text: &str) -> Card {
+        serde_json::from_value(json!({
+            "card_id": card_id,
+            "name": name,
+            "types": types,
+            "text": text,
+        }))
+        .unwrap()
+    }
+
+    fn bravo() -> Card {
+        card("bravo-showstopper", "Bravo, Showstopper", &["Guardian", "Hero"], "")
+    }
+
+    fn oldhim() -> Card {
+        let types = ["Elemental", "Guardian", "Hero"];
+        card("oldhim-grandfather-of-eternity", "Oldhim, Grandfather of Eternity", &types, "")
+    }
+
+    fn pool() -> Vec<Card> {
+        vec![
+            card("snatch-red", "Snatch", &["Generic", "Action", "Attack"], ""),
+            card("crippling-crush", "Crippling Crush", &["Guardian", "Action", "Attack"], ""),
+            card("anothos", "Anothos", &["Guardian", "Weapon", "Hammer"], ""),
+            card("show-time-blue", "Show Time!", &["Guardian", "Action"], "Bravo Specialization"),
+            card("winters-wail", "Winter's Wail", &["Elemental", "Guardian", "Weapon"], ""),
+            card("pulverize-red", "Pulverize", &["Guardian", "Action", "Attack"], ""),
+            card("mighty-windup", "Mighty Windup", &["Brute", "Action", "Attack"], ""),
+            card("rosetta-thorn", "Rosetta Thorn", &["Earth", "Runeblade", "Weapon"], ""),
+            card("seismic-surge", "Seismic Surge", &["Generic", "Token", "Aura"], ""),
+            bravo(),
+            oldhim(),
+        ]
+    }
+
+    fn allowed(hero: &Card) -> Vec<String> {
+        let hero = PoolHero::new(hero);
+        pool()
+            .iter()
+            .filter(|card| hero.allows(card))
+            .map(|card| card.card_id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn reads_the_classes_and_talents_of_a_hero() {
+        let oldhim = oldhim();
+        let hero = PoolHero::new(&oldhim);
+        assert_eq!(hero.short_name(), "Oldhim");
+        assert_eq!((hero.classes, hero.talents), (vec!["Guardian"], vec!["Elemental"]));
+    }
+
+    #[test]
+    fn allows_generic_cards_and_cards_of_the_heros_supertypes() {
+        let expected =
+            ["snatch-red", "crippling-crush", "anothos", "show-time-blue", "pulverize-red"];
+        assert_eq!(allowed(&bravo()), expected);
+    }
+
+    #[test]
+    fn allows_talent_cards_only_to_heroes_with_the_talent() {
+        let expected =
+            ["snatch-red", "crippling-crush", "anothos", "winters-wail", "pulverize-red"];
+        assert_eq!(allowed(&oldhim()), expected);
+    }
+
+    #[test]
+    fn allows_specializations_only_to_their_hero() {
+        let show_time = &pool()[3];
+        assert!(PoolHero::new(&bravo()).allows(show_time));
+        assert!(!PoolHero::new(&oldhim()).allows(show_time));
+    }
+
+    #[test]
+    fn never_allows_heroes_or_tokens() {
+        let bravo = bravo();
+        let hero = PoolHero::new(&bravo);
+        assert!(!hero.allows(&bravo));
+        assert!(!hero.allows(&pool()[8]));
+    }
+
+    #[test]
+    fn finds_heroes_by_id_name_and_part_of_name() {
+        let pool = pool();
+        let cards: Vec<&Card> = pool.iter().collect();
+        let find = |query| find_hero(&cards, query).map(|hero| hero.card_id.as_str());
+        assert_eq!(find("bravo-showstopper").unwrap(), "bravo-showstopper");
+        let oldhim = "oldhim-grandfather-of-eternity";
+        assert_eq!(find("Oldhim, Grandfather of Eternity").unwrap(), oldhim);
+        assert_eq!(find("bravo").unwrap(), "bravo-showstopper");
+        assert_eq!(find("grandfather").unwrap(), oldhim);
+        // Snatch is a card, not a hero
+        assert!(find("Snatch").is_err());
+
+        let error = find("o").unwrap_err().to_string();
+        assert!(error.contains("matches 2 heroes"), "{}", error);
+        let error = find("Bravo, Showstoper").unwrap_err().to_string();
+        assert!(error.contains("No hero matching"), "{}", error);
+        assert!(error.contains("Did you mean 'Bravo, Showstopper'?"), "{}", error);
+    }
+
+    #[test]
+    fn pools_every_card_once_ordered_by_name() {
+        let wtr: SetResponse = serde_json::from_value(json!({"results": pool()})).unwrap();
+        let reprints: SetResponse =
+            serde_json::from_value(json!({"results": [pool()[0].clone(), pool()[2].clone()]}))
+                .unwrap();
+        let bravo = bravo();
+        let pool = card_pool(&PoolHero::new(&bravo), &[&wtr, &reprints]);
+        let names: Vec<&str> = pool.iter().map(|card| card.name.as_str()).collect();
+        assert_eq!(names, ["Anothos", "Crippling Crush", "Pulverize", "Show Time!", "Snatch"]);
+
+        let csv = render_csv(&pool).unwrap();
+        assert!(csv.starts_with("card_id,name,pitch,cost,type_text\nanothos,Anothos,,,\n"));
+        assert_eq!(csv.lines().count(), 6);
+    }
+}
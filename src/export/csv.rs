@@ -0,0 +1,107 @@
+//! Flattened CSV export with one row per printing.
+
+use std::error::Error;
+
+use crate::model::{Card, Printing, SetResponse};
+use crate::promo;
+
+// Column headers, in the order rows are written
+pub(crate) const HEADERS: [&str; 16] = [
+    "set_code",
+    "card_id",
+    "print_id",
+    "name",
+    "pitch",
+    "cost",
+    "power",
+    "defense",
+    "health",
+    "intelligence",
+    "types",
+    "rarity",
+    "foiling",
+    "edition",
+    "text",
+    "promo",
+];
+
+/// Renders the printings of one or more sets as CSV, with a header row.
+///
+/// Only printings belonging to each set are written, so reprints listed on a card do not
+/// show up under every set the card appears in.
+///
+/// # Arguments
+/// * `sets` - Pairs of set code and the set's cards.
+///
+/// # Returns
+/// A `Result` containing the CSV text, or an error.
+pub fn render<'a>(
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+) -> Result<String, Box<dyn Error>> {
+    let mut writer = ::csv::Writer::from_writer(Vec::new());
+    writer.write_record(HEADERS)?;
+    for (set_code, set) in sets {
+        for card in &set.results {
+            for row in card_rows(set_code, card) {
+                writer.write_record(row)?;
+            }
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// The rows of a card: one per printing in the set, with the columns of `HEADERS`.
+pub(crate) fn card_rows(set_code: &str, card: &Card) -> Vec<[String; HEADERS.len()]> {
+    set_printings(set_code, card)
+        .into_iter()
+        .map(|printing| row(set_code, card, printing))
+        .collect()
+}
+
+/// The printings of a card that belong to a set, one row each. A card without a printing in
+/// the set still gets a row, just without printing details.
+pub(crate) fn set_printings<'a>(set_code: &str, card: &'a Card) -> Vec<Option<&'a Printing>> {
+    let printings: Vec<Option<&Printing>> = card
+        .printings
+        .iter()
+        .filter(|printing| {
+            printing
+                .set_code
+                .as_deref()
+                .is_none_or(|code| code.eq_ignore_ascii_case(set_code))
+        })
+        .map(Some)
+        .collect();
+    if printings.is_empty() { vec![None] } else { printings }
+}
+
+fn row(set_code: &str, card: &Card, printing: Option<&Printing>) -> [String; HEADERS.len()] {
+    let stat = |value: &Option<String>| value.clone().unwrap_or_default();
+    let printing_field = |field: fn(&Printing) -> &Option<String>| {
+        printing.and_then(|p| field(p).clone()).unwrap_or_default()
+    };
+
+    [
+        printing
+            .and_then(|p| p.set_code.clone())
+            .unwrap_or_else(|| set_code.to_string()),
+        card.card_id.clone(),
+        printing.map(|p| p.print_id.clone()).unwrap_or_default(),
+        card.name.clone(),
+        stat(&card.pitch),
+        stat(&card.cost),
+        stat(&card.power),
+        stat(&card.defense),
+        stat(&card.health),
+        stat(&card.intelligence),
+        card.types.join(", "),
+        printing_field(|p| &p.rarity),
+        printing_field(|p| &p.foiling),
+        printing_field(|p| &p.edition),
+        stat(&card.text),
+        printing
+            .and_then(promo::classify)
+            .map(|program| program.name().to_string())
+            .unwrap_or_default(),
+    ]
+}
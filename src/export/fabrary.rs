@@ -0,0 +1,105 @@
+//! A deck-builder card list in the layout of the FaB DB card API, imported by Fabrary and other
+//! deck builders.
+//!
+//! Every card is listed once, with its printings from all fetched sets, ordered by name:
+//!
+//! ```json
+//! {"cards": [{"identifier": "bravo-showstopper", "name": "Bravo, Showstopper", "pitch": null,
+//!   "printings": [{"id": "WTR006", "set": "WTR", "rarity": "C", "image": "..."}], ...}]}
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use serde::Serialize;
+
+use crate::model::{Card, SetResponse};
+use crate::promo;
+
+#[derive(Serialize)]
+struct CardList<'a> {
+    generated_at: &'a str,
+    cards: Vec<ListedCard<'a>>,
+}
+
+#[derive(Serialize)]
+struct ListedCard<'a> {
+    identifier: &'a str,
+    name: &'a str,
+    pitch: Option<&'a str>,
+    cost: Option<&'a str>,
+    power: Option<&'a str>,
+    defense: Option<&'a str>,
+    health: Option<&'a str>,
+    intelligence: Option<&'a str>,
+    types: &'a [String],
+    keywords: &'a [String],
+    text: Option<&'a str>,
+    printings: Vec<ListedPrinting<'a>>,
+}
+
+#[derive(Serialize)]
+struct ListedPrinting<'a> {
+    id: &'a str,
+    set: &'a str,
+    rarity: Option<&'a str>,
+    edition: Option<&'a str>,
+    foiling: Option<&'a str>,
+    image: Option<&'a str>,
+    promo: Option<&'static str>,
+}
+
+/// Renders the card list of the given sets.
+///
+/// # Arguments
+/// * `sets` - Pairs of set code and the set's cards.
+/// * `generated_at` - When the list is generated, recorded in it.
+///
+/// # Returns
+/// A `Result` containing the JSON text, or an error.
+pub fn render<'a>(
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+    generated_at: &str,
+) -> Result<String, Box<dyn Error>> {
+    // A card printed in several sets is listed once, with the printings of every set
+    let mut by_id: BTreeMap<&str, (&Card, Vec<ListedPrinting>)> = BTreeMap::new();
+    for (set_code, set) in sets {
+        for card in &set.results {
+            let (_, printings) = by_id.entry(card.card_id.as_str()).or_insert((card, Vec::new()));
+            for printing in &card.printings {
+                if printings.iter().any(|listed| listed.id == printing.print_id) {
+                    continue;
+                }
+                printings.push(ListedPrinting {
+                    id: &printing.print_id,
+                    set: printing.set_code.as_deref().unwrap_or(set_code),
+                    rarity: printing.rarity.as_deref(),
+                    edition: printing.edition.as_deref(),
+                    foiling: printing.foiling.as_deref(),
+                    image: printing.image_url.as_deref(),
+                    promo: promo::classify(printing).map(|program| program.name()),
+                });
+            }
+        }
+    }
+
+    let mut cards: Vec<ListedCard> = by_id
+        .into_values()
+        .map(|(card, printings)| ListedCard {
+            identifier: &card.card_id,
+            name: &card.name,
+            pitch: card.pitch.as_deref(),
+            cost: card.cost.as_deref(),
+            power: card.power.as_deref(),
+            defense: card.defense.as_deref(),
+            health: card.health.as_deref(),
+            intelligence: card.intelligence.as_deref(),
+            types: &card.types,
+            keywords: &card.keywords,
+            text: card.text.as_deref(),
+            printings,
+        })
+        .collect();
+    cards.sort_by(|a, b| a.name.cmp(b.name).then(a.identifier.cmp(b.identifier)));
+    Ok(serde_json::to_string_pretty(&CardList { generated_at, cards })?)
+}
@@ -0,0 +1,253 @@
+//! Numeric feature matrices of the cards, for clustering and recommendation experiments.
+//!
+//! Every card becomes one row of numbers, in these groups of columns:
+//!
+//! - `type:<type>` and `keyword:<keyword>`: 1 if the card has the type (class, talent and card
+//!   types alike) or keyword, 0 otherwise
+//! - `pitch:1` to `pitch:3`: the pitch, one-hot
+//! - `cost`, `power`, `defense`, `health`, `intelligence`: the stat scaled to 0–1 over every
+//!   card that has it, each followed by a `<stat>:missing` column that is 1 when the card has no
+//!   numeric value for it (no stat, or an X cost)
+//! - `word:<word>`: how many times a word occurs in the rules text, for the words used by
+//!   enough cards
+//!
+//! The matrix is written as CSV, and as a NumPy `.npy` file of 32-bit floats that
+//! `numpy.load` reads directly, with its row and column labels in a JSON file beside it.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+
+use serde_json::json;
+
+use crate::model::{Card, SetResponse};
+
+// Stats scaled into columns of their own, in column order
+const STATS: [&str; 5] = ["cost", "power", "defense", "health", "intelligence"];
+
+// Words of the rules text kept as columns by default, the most used first
+pub const DEFAULT_MAX_WORDS: usize = 500;
+
+// Fewest cards a word must occur in, by default, to become a column
+pub const DEFAULT_MIN_WORD_CARDS: usize = 2;
+
+/// Which words of the rules text become columns.
+#[derive(Debug, Clone, Copy)]
+pub struct WordOptions {
+    /// The most words kept, the most used first.
+    pub max_words: usize,
+    /// The fewest cards a word must occur in.
+    pub min_cards: usize,
+}
+
+impl Default for WordOptions {
+    fn default() -> Self {
+        WordOptions { max_words: DEFAULT_MAX_WORDS, min_cards: DEFAULT_MIN_WORD_CARDS }
+    }
+}
+
+/// The cards as rows of numbers.
+pub struct FeatureMatrix {
+    pub card_ids: Vec<String>,
+    pub names: Vec<String>,
+    pub columns: Vec<String>,
+    /// The values of every row in turn, `columns.len()` per card.
+    pub values: Vec<f32>,
+    /// The lowest and highest value of each stat, which its column is scaled by.
+    pub stat_ranges: BTreeMap<&'static str, (f64, f64)>,
+}
+
+impl FeatureMatrix {
+    /// The values of one card.
+    pub fn row(&self, index: usize) -> &[f32] {
+        let width = self.columns.len();
+        &self.values[index * width..(index + 1) * width]
+    }
+}
+
+/// Builds the feature matrix of the given sets.
+///
+/// # Arguments
+/// * `sets` - The fetched sets. A card printed in several sets is one row, taken from the first
+///   set listing it; rows are ordered by card id.
+/// * `words` - Which words of the rules text become columns.
+pub fn build(sets: &[&SetResponse], words: WordOptions) -> FeatureMatrix {
+    let mut cards: BTreeMap<&str, &Card> = BTreeMap::new();
+    for card in sets.iter().flat_map(|set| &set.results) {
+        cards.entry(card.card_id.as_str()).or_insert(card);
+    }
+    let cards: Vec<&Card> = cards.into_values().collect();
+
+    let types: BTreeSet<&str> =
+        cards.iter().flat_map(|card| &card.types).map(String::as_str).collect();
+    let keywords: BTreeSet<&str> =
+        cards.iter().flat_map(|card| &card.keywords).map(String::as_str).collect();
+    let card_words: Vec<HashMap<String, usize>> =
+        cards.iter().map(|card| word_counts(card.text.as_deref().unwrap_or(""))).collect();
+    let vocabulary = vocabulary(&card_words, words);
+    let stats: Vec<Vec<Option<f64>>> = cards
+        .iter()
+        .map(|card| STATS.iter().map(|stat| stat_value(card, stat)).collect())
+        .collect();
+    let mut stat_ranges = BTreeMap::new();
+    for (index, stat) in STATS.iter().enumerate() {
+        let values = stats.iter().filter_map(|row| row[index]);
+        let range = values.fold(None, |range: Option<(f64, f64)>, value| match range {
+            Some((low, high)) => Some((low.min(value), high.max(value))),
+            None => Some((value, value)),
+        });
+        if let Some(range) = range {
+            stat_ranges.insert(*stat, range);
+        }
+    }
+
+    let mut columns: Vec<String> = types.iter().map(|t| format!("type:{}", t)).collect();
+    columns.extend(keywords.iter().map(|keyword| format!("keyword:{}", keyword)));
+    columns.extend((1..=3).map(|pitch| format!("pitch:{}", pitch)));
+    for stat in STATS {
+        columns.push(stat.to_string());
+        columns.push(format!("{}:missing", stat));
+    }
+    columns.extend(vocabulary.iter().map(|word| format!("word:{}", word)));
+
+    let mut values = Vec::with_capacity(cards.len() * columns.len());
+    for ((card, counts), card_stats) in cards.iter().zip(&card_words).zip(&stats) {
+        let flag = |present: bool| if present { 1.0 } else { 0.0 };
+        values.extend(types.iter().map(|t| flag(card.types.iter().any(|own| own == t))));
+        values.extend(keywords.iter().map(|k| flag(card.keywords.iter().any(|own| own == k))));
+        let pitch = card.pitch.as_deref().map(str::trim);
+        values.extend(["1", "2", "3"].map(|value| flag(pitch == Some(value))));
+        for (stat, value) in STATS.iter().zip(card_stats) {
+            let scaled = match (value, stat_ranges.get(stat)) {
+                (Some(value), Some((low, high))) if high > low => (value - low) / (high - low),
+                _ => 0.0,
+            };
+            values.push(scaled as f32);
+            values.push(flag(value.is_none()));
+        }
+        values.extend(vocabulary.iter().map(|word| *counts.get(*word).unwrap_or(&0) as f32));
+    }
+
+    FeatureMatrix {
+        card_ids: cards.iter().map(|card| card.card_id.clone()).collect(),
+        names: cards.iter().map(|card| card.name.clone()).collect(),
+        columns,
+        values,
+        stat_ranges,
+    }
+}
+
+/// The numeric value of a card's stat, if it has one.
+fn stat_value(card: &Card, stat: &str) -> Option<f64> {
+    let value = match stat {
+        "cost" => &card.cost,
+        "power" => &card.power,
+        "defense" => &card.defense,
+        "health" => &card.health,
+        "intelligence" => &card.intelligence,
+        _ => return None,
+    };
+    value.as_deref()?.trim().parse().ok()
+}
+
+/// Counts the words of a rules text, lowercased, leaving out markup and single letters.
+fn word_counts(text: &str) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if word.chars().count() > 1 {
+            *counts.entry(word.to_lowercase()).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Picks the words that become columns: those in enough cards, the most used first, then
+/// ordered alphabetically.
+fn vocabulary(card_words: &[HashMap<String, usize>], options: WordOptions) -> Vec<&str> {
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for counts in card_words {
+        for word in counts.keys() {
+            *document_frequency.entry(word.as_str()).or_insert(0) += 1;
+        }
+    }
+    let mut words: Vec<(&str, usize)> = document_frequency
+        .into_iter()
+        .filter(|(_, cards)| *cards >= options.min_cards.max(1))
+        .collect();
+    words.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    words.truncate(options.max_words);
+    let mut words: Vec<&str> = words.into_iter().map(|(word, _)| word).collect();
+    words.sort_unstable();
+    words
+}
+
+/// Renders the matrix as CSV: a `card_id` and a `name` column, then one column per feature.
+pub fn render_csv(matrix: &FeatureMatrix) -> Result<String, Box<dyn Error>> {
+    let mut writer = ::csv::Writer::from_writer(Vec::new());
+    let mut header = vec!["card_id".to_string(), "name".to_string()];
+    header.extend(matrix.columns.iter().cloned());
+    writer.write_record(&header)?;
+    for (index, (card_id, name)) in matrix.card_ids.iter().zip(&matrix.names).enumerate() {
+        let mut record = vec![card_id.clone(), name.clone()];
+        record.extend(matrix.row(index).iter().map(|value| value.to_string()));
+        writer.write_record(&record)?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Renders the matrix as a NumPy `.npy` file (format 1.0): a little-endian `float32` array of
+/// one row per card.
+pub fn render_npy(matrix: &FeatureMatrix) -> Vec<u8> {
+    const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        matrix.card_ids.len(),
+        matrix.columns.len()
+    );
+    // The header is padded with spaces and ends with a newline, so the data starts aligned
+    // to 64 bytes after the magic string and the 2-byte header length
+    let unpadded = MAGIC.len() + 2 + header.len() + 1;
+    header.push_str(&" ".repeat(unpadded.next_multiple_of(64) - unpadded));
+    header.push('\n');
+
+    let mut npy = Vec::with_capacity(MAGIC.len() + 2 + header.len() + matrix.values.len() * 4);
+    npy.extend_from_slice(MAGIC);
+    npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    npy.extend_from_slice(header.as_bytes());
+    for value in &matrix.values {
+        npy.extend_from_slice(&value.to_le_bytes());
+    }
+    npy
+}
+
+/// Renders the labels of the `.npy` matrix as JSON: its rows, its columns and the ranges the
+/// stats were scaled by.
+///
+/// # Arguments
+/// * `matrix` - The feature matrix.
+/// * `generated_at` - When the matrix was built.
+/// * `source_sets` - The set codes the cards were taken from.
+pub fn render_labels(
+    matrix: &FeatureMatrix,
+    generated_at: &str,
+    source_sets: &[&str],
+) -> Result<String, Box<dyn Error>> {
+    let stat_ranges: serde_json::Map<String, serde_json::Value> = matrix
+        .stat_ranges
+        .iter()
+        .map(|(stat, (low, high))| (stat.to_string(), json!({ "min": low, "max": high })))
+        .collect();
+    let labels = json!({
+        "generated_at": generated_at,
+        "source_sets": source_sets,
+        "shape": [matrix.card_ids.len(), matrix.columns.len()],
+        "rows": matrix
+            .card_ids
+            .iter()
+            .zip(&matrix.names)
+            .map(|(card_id, name)| json!({ "card_id": card_id, "name": name }))
+            .collect::<Vec<_>>(),
+        "columns": matrix.columns,
+        "stat_ranges": stat_ranges,
+    });
+    Ok(serde_json::to_string_pretty(&labels)?)
+}
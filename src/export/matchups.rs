@@ -0,0 +1,87 @@
+//! Hero matchup grid scaffolds, ready for win rates to be filled in.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use serde_json::{Map, Value, json};
+
+use crate::model::SetResponse;
+
+/// A hero included in the grid.
+pub struct Hero {
+    pub name: String,
+    pub card_id: String,
+    pub types: Vec<String>,
+}
+
+/// Collects the constructed (adult) heroes from the given sets, ordered by name.
+///
+/// Heroes are cards typed `Hero`; `Young` heroes are left out as they are only played in
+/// Blitz. A hero printed in several sets is listed once.
+///
+/// # Arguments
+/// * `sets` - The fetched sets to take heroes from.
+pub fn constructed_heroes(sets: &[&SetResponse]) -> Vec<Hero> {
+    let has_type = |types: &[String], wanted: &str| types.iter().any(|t| t.eq_ignore_ascii_case(wanted));
+
+    let mut heroes = BTreeMap::new();
+    for card in sets.iter().flat_map(|set| &set.results) {
+        if has_type(&card.types, "Hero") && !has_type(&card.types, "Young") {
+            heroes.entry(card.name.clone()).or_insert_with(|| Hero {
+                name: card.name.clone(),
+                card_id: card.card_id.clone(),
+                types: card.types.clone(),
+            });
+        }
+    }
+    heroes.into_values().collect()
+}
+
+/// Renders the grid as CSV: one row and one column per hero, with empty cells.
+pub fn render_csv(heroes: &[Hero]) -> Result<String, Box<dyn Error>> {
+    let mut writer = ::csv::Writer::from_writer(Vec::new());
+
+    let mut header = vec![String::from("hero")];
+    header.extend(heroes.iter().map(|hero| hero.name.clone()));
+    writer.write_record(&header)?;
+
+    for hero in heroes {
+        let mut row = vec![hero.name.clone()];
+        row.extend(heroes.iter().map(|_| String::new()));
+        writer.write_record(&row)?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Renders the grid as JSON with metadata and a `null` win rate for every pairing.
+///
+/// # Arguments
+/// * `heroes` - The heroes of the grid.
+/// * `generated_at` - When the grid was generated.
+/// * `source_sets` - The set codes the hero list was built from.
+pub fn render_json(
+    heroes: &[Hero],
+    generated_at: &str,
+    source_sets: &[&str],
+) -> Result<String, Box<dyn Error>> {
+    let mut matchups = Map::new();
+    for hero in heroes {
+        let opponents: Map<String, Value> = heroes
+            .iter()
+            .map(|opponent| (opponent.name.clone(), Value::Null))
+            .collect();
+        matchups.insert(hero.name.clone(), Value::Object(opponents));
+    }
+
+    let grid = json!({
+        "generated_at": generated_at,
+        "format": "classic_constructed",
+        "source_sets": source_sets,
+        "heroes": heroes
+            .iter()
+            .map(|hero| json!({ "name": hero.name, "card_id": hero.card_id, "types": hero.types }))
+            .collect::<Vec<_>>(),
+        "matchups": matchups,
+    });
+    Ok(serde_json::to_string_pretty(&grid)?)
+}
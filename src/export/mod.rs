@@ -0,0 +1,100 @@
+//! Exporters that turn fetched card data into formats other tools consume.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use chrono::Local;
+
+use crate::model::SetResponse;
+use crate::storage;
+
+pub mod card_pool;
+pub mod csv;
+pub mod fabrary;
+pub mod features;
+pub mod matchups;
+pub mod ndjson;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod reprints;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
+
+// Name of the SQLite database file inside the output directory
+pub const DATABASE_FILENAME: &str = "cards.db";
+
+// Name of the Parquet file of every printing inside the output directory
+pub const PARQUET_FILENAME: &str = "printings.parquet";
+
+// Name of the deck-builder card list inside the `fabrary/` folder of the output directory
+pub const FABRARY_FILENAME: &str = "fabrary_cards.json";
+
+// Name of the XLSX workbook inside the `xlsx/` folder of the output directory
+pub const XLSX_FILENAME: &str = "cards.xlsx";
+
+/// A catalog export written after a fetch with `--export`, or by the `export` command.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ExportFormat {
+    /// A deck-builder card list in the FaB DB layout, for Fabrary and other deck builders
+    Fabrary,
+    /// An XLSX workbook with one sheet per set
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportFormat::Fabrary => "fabrary",
+            ExportFormat::Xlsx => "xlsx",
+        }
+    }
+
+    /// Returns the path of the export: `{base}/{export}/<file>`.
+    pub fn filename(self, base_output_dir: &str) -> String {
+        let file = match self {
+            ExportFormat::Fabrary => FABRARY_FILENAME,
+            ExportFormat::Xlsx => XLSX_FILENAME,
+        };
+        format!("{}/{}/{}", base_output_dir, self.name(), file)
+    }
+}
+
+/// Writes a catalog export of the given sets into the output directory.
+///
+/// # Arguments
+/// * `format` - The export to write.
+/// * `base_output_dir` - The root output directory.
+/// * `sets` - Pairs of set code and the set's cards.
+///
+/// # Returns
+/// A `Result` containing the path written, or an error.
+pub fn write<'a>(
+    format: ExportFormat,
+    base_output_dir: &str,
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+) -> Result<String, Box<dyn Error>> {
+    let filename = format.filename(base_output_dir);
+    let content = match format {
+        ExportFormat::Fabrary => fabrary::render(sets, &Local::now().to_rfc3339())?.into_bytes(),
+        #[cfg(feature = "xlsx")]
+        ExportFormat::Xlsx => xlsx::render(sets)?,
+        #[cfg(not(feature = "xlsx"))]
+        ExportFormat::Xlsx => {
+            drop(sets);
+            return Err("XLSX export needs a build with the `xlsx` feature".into());
+        }
+    };
+    fs::create_dir_all(Path::new(base_output_dir).join(format.name()))?;
+    storage::write_atomically(Path::new(&filename), &content)?;
+    Ok(filename)
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
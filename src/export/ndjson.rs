@@ -0,0 +1,40 @@
+//! Newline-delimited JSON export with one card per line.
+//!
+//! Every line is a complete card object with the code of the set it was listed in added as
+//! `set_code`, so tools like jq, DuckDB or BigQuery can stream the data a card at a time.
+
+use std::error::Error;
+
+use serde::Serialize;
+
+use crate::model::{Card, SetResponse};
+
+// Name of the file holding the cards of every set
+pub const ALL_CARDS_FILENAME: &str = "all_cards.ndjson";
+
+#[derive(Serialize)]
+struct Line<'a> {
+    set_code: &'a str,
+    #[serde(flatten)]
+    card: &'a Card,
+}
+
+/// Renders the cards of one or more sets as NDJSON, one line per card and set.
+///
+/// # Arguments
+/// * `sets` - Pairs of set code and the set's cards.
+///
+/// # Returns
+/// A `Result` containing the NDJSON text, or an error.
+pub fn render<'a>(
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+) -> Result<String, Box<dyn Error>> {
+    let mut out = String::new();
+    for (set_code, set) in sets {
+        for card in &set.results {
+            out.push_str(&serde_json::to_string(&Line { set_code, card })?);
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,257 @@
+//! Parquet export: a columnar file of every printing with typed columns, for DuckDB, Spark or
+//! pandas.
+//!
+//! Each row is one printing of a card in a set, like a row of the CSV export, with the card's
+//! fields repeated on every printing. Numeric stats are 32-bit integers, null when a card has
+//! no such stat or a value like `X` or `*` that is not a number; types, keywords and artists
+//! are lists of strings. Every set is a row group of its own.
+
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use ::parquet::basic::Compression;
+use ::parquet::column::writer::ColumnWriter;
+use ::parquet::data_type::ByteArray;
+use ::parquet::file::properties::WriterProperties;
+use ::parquet::file::writer::SerializedFileWriter;
+use ::parquet::schema::parser::parse_message_type;
+
+use crate::model::{Card, Printing, SetResponse};
+use crate::promo;
+use crate::storage;
+
+use super::csv::set_printings;
+
+// Schema of the rows, in the order the columns of `Columns` are written
+const SCHEMA: &str = "
+    message printing {
+        REQUIRED BYTE_ARRAY set_code (UTF8);
+        REQUIRED BYTE_ARRAY card_id (UTF8);
+        OPTIONAL BYTE_ARRAY print_id (UTF8);
+        REQUIRED BYTE_ARRAY name (UTF8);
+        OPTIONAL INT32 pitch;
+        OPTIONAL INT32 cost;
+        OPTIONAL INT32 power;
+        OPTIONAL INT32 defense;
+        OPTIONAL INT32 health;
+        OPTIONAL INT32 intelligence;
+        OPTIONAL BYTE_ARRAY type_text (UTF8);
+        REQUIRED group types (LIST) {
+            REPEATED group list {
+                REQUIRED BYTE_ARRAY element (UTF8);
+            }
+        }
+        REQUIRED group keywords (LIST) {
+            REPEATED group list {
+                REQUIRED BYTE_ARRAY element (UTF8);
+            }
+        }
+        OPTIONAL BYTE_ARRAY text (UTF8);
+        OPTIONAL BYTE_ARRAY rarity (UTF8);
+        OPTIONAL BYTE_ARRAY foiling (UTF8);
+        OPTIONAL BYTE_ARRAY edition (UTF8);
+        OPTIONAL BYTE_ARRAY image_url (UTF8);
+        REQUIRED group artists (LIST) {
+            REPEATED group list {
+                REQUIRED BYTE_ARRAY element (UTF8);
+            }
+        }
+        OPTIONAL BYTE_ARRAY promo (UTF8);
+    }
+";
+
+/// The values of one column of a row group, with the levels Parquet stores next to them.
+#[derive(Default)]
+struct Column {
+    strings: Vec<ByteArray>,
+    integers: Vec<i32>,
+    /// Whether each value is present, left empty for required columns.
+    definition: Vec<i16>,
+    /// Whether each value starts a row or continues its list, left empty outside lists.
+    repetition: Vec<i16>,
+}
+
+impl Column {
+    fn required(&mut self, value: &str) {
+        self.strings.push(value.into());
+    }
+
+    fn optional(&mut self, value: Option<&str>) {
+        if let Some(value) = value {
+            self.strings.push(value.into());
+        }
+        self.definition.push(i16::from(value.is_some()));
+    }
+
+    fn integer(&mut self, value: Option<&str>) {
+        let value = value.and_then(|value| value.trim().parse::<i32>().ok());
+        if let Some(value) = value {
+            self.integers.push(value);
+        }
+        self.definition.push(i16::from(value.is_some()));
+    }
+
+    fn list(&mut self, values: &[String]) {
+        if values.is_empty() {
+            self.definition.push(0);
+            self.repetition.push(0);
+        }
+        for (index, value) in values.iter().enumerate() {
+            self.strings.push(value.as_str().into());
+            self.definition.push(1);
+            self.repetition.push(i16::from(index > 0));
+        }
+    }
+}
+
+/// The columns of a row group, named after the schema.
+#[derive(Default)]
+struct Columns {
+    set_code: Column,
+    card_id: Column,
+    print_id: Column,
+    name: Column,
+    pitch: Column,
+    cost: Column,
+    power: Column,
+    defense: Column,
+    health: Column,
+    intelligence: Column,
+    type_text: Column,
+    types: Column,
+    keywords: Column,
+    text: Column,
+    rarity: Column,
+    foiling: Column,
+    edition: Column,
+    image_url: Column,
+    artists: Column,
+    promo: Column,
+}
+
+impl Columns {
+    fn push(&mut self, set_code: &str, card: &Card, printing: Option<&Printing>) {
+        let printing_field = |field: fn(&Printing) -> &Option<String>| {
+            printing.and_then(|printing| field(printing).as_deref())
+        };
+        self.set_code.required(printing_field(|p| &p.set_code).unwrap_or(set_code));
+        self.card_id.required(&card.card_id);
+        self.print_id.optional(printing.map(|printing| printing.print_id.as_str()));
+        self.name.required(&card.name);
+        self.pitch.integer(card.pitch.as_deref());
+        self.cost.integer(card.cost.as_deref());
+        self.power.integer(card.power.as_deref());
+        self.defense.integer(card.defense.as_deref());
+        self.health.integer(card.health.as_deref());
+        self.intelligence.integer(card.intelligence.as_deref());
+        self.type_text.optional(card.type_text.as_deref());
+        self.types.list(&card.types);
+        self.keywords.list(&card.keywords);
+        self.text.optional(card.text.as_deref());
+        self.rarity.optional(printing_field(|p| &p.rarity));
+        self.foiling.optional(printing_field(|p| &p.foiling));
+        self.edition.optional(printing_field(|p| &p.edition));
+        self.image_url.optional(printing_field(|p| &p.image_url));
+        let artists = printing.map(|printing| printing.artists.as_slice());
+        self.artists.list(artists.unwrap_or_default());
+        self.promo.optional(printing.and_then(promo::classify).map(|program| program.name()));
+    }
+
+    /// The columns in schema order.
+    fn into_ordered(self) -> [Column; 20] {
+        [
+            self.set_code,
+            self.card_id,
+            self.print_id,
+            self.name,
+            self.pitch,
+            self.cost,
+            self.power,
+            self.defense,
+            self.health,
+            self.intelligence,
+            self.type_text,
+            self.types,
+            self.keywords,
+            self.text,
+            self.rarity,
+            self.foiling,
+            self.edition,
+            self.image_url,
+            self.artists,
+            self.promo,
+        ]
+    }
+}
+
+/// Renders the printings of one or more sets as a Parquet file, compressed with Snappy.
+///
+/// # Arguments
+/// * `sets` - Pairs of set code and the set's cards; each set is written as a row group.
+///
+/// # Returns
+/// A `Result` containing the file contents, or an error.
+pub fn render<'a>(
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_created_by(format!("fab-scrapper {}", env!("CARGO_PKG_VERSION")))
+        .build();
+    let mut writer = SerializedFileWriter::new(Vec::new(), schema, Arc::new(properties))?;
+
+    for (set_code, set) in sets {
+        if set.results.is_empty() {
+            continue;
+        }
+        let mut columns = Columns::default();
+        for card in &set.results {
+            for printing in set_printings(set_code, card) {
+                columns.push(set_code, card, printing);
+            }
+        }
+
+        let mut row_group = writer.next_row_group()?;
+        for column in columns.into_ordered() {
+            let Some(mut column_writer) = row_group.next_column()? else {
+                return Err("the Parquet schema has fewer columns than the rows".into());
+            };
+            let definition = levels(&column.definition);
+            let repetition = levels(&column.repetition);
+            match column_writer.untyped() {
+                ColumnWriter::ByteArrayColumnWriter(typed) => {
+                    typed.write_batch(&column.strings, definition, repetition)?
+                }
+                ColumnWriter::Int32ColumnWriter(typed) => {
+                    typed.write_batch(&column.integers, definition, repetition)?
+                }
+                _ => return Err("the Parquet schema has a column of an unexpected type".into()),
+            };
+            column_writer.close()?;
+        }
+        row_group.close()?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+/// The levels of a column, or none for a column that stores no levels of that kind.
+fn levels(levels: &[i16]) -> Option<&[i16]> {
+    (!levels.is_empty()).then_some(levels)
+}
+
+/// Writes the printings of one or more sets to a Parquet file, replacing it as a whole.
+///
+/// # Arguments
+/// * `path` - The Parquet file.
+/// * `sets` - Pairs of set code and the set's cards.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+pub fn write<'a>(
+    path: &str,
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+) -> Result<(), Box<dyn Error>> {
+    storage::write_atomically(Path::new(path), &render(sets)?)
+}
@@ -0,0 +1,184 @@
+//! Reprint histories: the set every card was first printed in, and every set it was printed in
+//! since, oldest first.
+//!
+//! A card's printings list the sets it appeared in, so the history of a card also covers sets
+//! that were not fetched. Sets are ordered by their release dates when known, and otherwise by
+//! their position in the release order given (e.g. the set codes file).
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+
+use serde_json::json;
+
+use crate::model::{SetInfo, SetResponse};
+
+/// A set a card was printed in.
+pub struct Appearance {
+    pub set_code: String,
+    pub release_date: Option<String>,
+    pub print_ids: Vec<String>,
+    pub rarities: Vec<String>,
+}
+
+/// Every set a card was printed in, oldest first.
+pub struct CardHistory {
+    pub card_id: String,
+    pub name: String,
+    pub timeline: Vec<Appearance>,
+}
+
+impl CardHistory {
+    /// The set the card was first printed in.
+    pub fn first_printed(&self) -> Option<&Appearance> {
+        self.timeline.first()
+    }
+
+    /// The number of sets the card was reprinted in after its first printing.
+    pub fn reprints(&self) -> usize {
+        self.timeline.len().saturating_sub(1)
+    }
+}
+
+/// Builds the reprint history of every card in the given sets, ordered by name.
+///
+/// # Arguments
+/// * `sets` - The fetched sets to take cards from; a card printed in several of them is listed
+///   once.
+/// * `releases` - Known sets, oldest first, with their release dates when available. Sets
+///   missing from it are placed after all known sets, ordered by code.
+pub fn card_histories<'a>(
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+    releases: &[SetInfo],
+) -> Vec<CardHistory> {
+    let position: HashMap<String, usize> = releases
+        .iter()
+        .enumerate()
+        .map(|(index, set)| (set.code.to_uppercase(), index))
+        .collect();
+    let release_date = |set_code: &str| {
+        position
+            .get(set_code)
+            .and_then(|index| releases[*index].release_date.clone())
+    };
+
+    // Printings of every card, grouped by set: print ids and rarities
+    type Printed = BTreeMap<String, (BTreeSet<String>, BTreeSet<String>)>;
+    let mut cards: BTreeMap<&str, (&str, Printed)> = BTreeMap::new();
+    for (set_code, set) in sets {
+        for card in &set.results {
+            let (_, printed) = cards.entry(&card.card_id).or_insert((&card.name, BTreeMap::new()));
+            for printing in &card.printings {
+                let printing_set = printing.set_code.as_deref().unwrap_or(set_code).to_uppercase();
+                let (print_ids, rarities) = printed.entry(printing_set).or_default();
+                print_ids.insert(printing.print_id.clone());
+                rarities.extend(printing.rarity.clone());
+            }
+        }
+    }
+
+    let mut histories: Vec<CardHistory> = cards
+        .into_iter()
+        .map(|(card_id, (name, printed))| {
+            let mut timeline: Vec<Appearance> = printed
+                .into_iter()
+                .map(|(set_code, (print_ids, rarities))| Appearance {
+                    release_date: release_date(&set_code),
+                    set_code,
+                    print_ids: print_ids.into_iter().collect(),
+                    rarities: rarities.into_iter().collect(),
+                })
+                .collect();
+            // Dated sets by date, then the release order, then unknown sets by code
+            timeline.sort_by(|a, b| {
+                let order = |appearance: &Appearance| {
+                    (
+                        appearance.release_date.is_none(),
+                        appearance.release_date.clone(),
+                        position.get(&appearance.set_code).copied().unwrap_or(usize::MAX),
+                        appearance.set_code.clone(),
+                    )
+                };
+                order(a).cmp(&order(b))
+            });
+            CardHistory { card_id: card_id.to_string(), name: name.to_string(), timeline }
+        })
+        .collect();
+    histories.sort_by(|a, b| a.name.cmp(&b.name).then(a.card_id.cmp(&b.card_id)));
+    histories
+}
+
+/// Renders the histories as CSV: one row per card, with its first printing, number of
+/// reprints and the sets it was printed in, oldest first and separated by `;`.
+pub fn render_csv(histories: &[CardHistory]) -> Result<String, Box<dyn Error>> {
+    let mut writer = ::csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "card_id",
+        "name",
+        "first_set",
+        "first_release_date",
+        "reprints",
+        "timeline",
+    ])?;
+    for history in histories {
+        let first = history.first_printed();
+        let timeline: Vec<String> = history
+            .timeline
+            .iter()
+            .map(|appearance| match &appearance.release_date {
+                Some(date) => format!("{} ({})", appearance.set_code, date),
+                None => appearance.set_code.clone(),
+            })
+            .collect();
+        writer.write_record([
+            history.card_id.as_str(),
+            history.name.as_str(),
+            first.map(|first| first.set_code.as_str()).unwrap_or_default(),
+            first.and_then(|first| first.release_date.as_deref()).unwrap_or_default(),
+            &history.reprints().to_string(),
+            &timeline.join("; "),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Renders the histories as JSON with metadata and the full timeline of every card.
+///
+/// # Arguments
+/// * `histories` - The reprint histories.
+/// * `generated_at` - When the histories were generated.
+/// * `source_sets` - The set codes the cards were taken from.
+pub fn render_json(
+    histories: &[CardHistory],
+    generated_at: &str,
+    source_sets: &[&str],
+) -> Result<String, Box<dyn Error>> {
+    let appearance = |appearance: &Appearance| {
+        json!({
+            "set": appearance.set_code,
+            "release_date": appearance.release_date,
+            "print_ids": appearance.print_ids,
+            "rarities": appearance.rarities,
+        })
+    };
+    let cards: Vec<_> = histories
+        .iter()
+        .map(|history| {
+            json!({
+                "card_id": history.card_id,
+                "name": history.name,
+                "first_printed": history.first_printed().map(|first| json!({
+                    "set": first.set_code,
+                    "release_date": first.release_date,
+                })),
+                "reprints": history.reprints(),
+                "timeline": history.timeline.iter().map(appearance).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    let document = json!({
+        "generated_at": generated_at,
+        "source_sets": source_sets,
+        "cards": cards,
+    });
+    Ok(serde_json::to_string_pretty(&document)?)
+}
@@ -0,0 +1,166 @@
+//! SQLite export keeping a local, queryable mirror of the card catalog.
+//!
+//! The database is updated in place: every set written replaces that set's rows, so runs
+//! upsert instead of duplicating data and the file can be kept around between fetches.
+
+use std::error::Error;
+
+use chrono::Local;
+use rusqlite::{Connection, Transaction, params};
+
+use crate::model::{Card, Printing, SetResponse};
+
+// Tables and indexes, created when missing
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS sets (
+        code       TEXT PRIMARY KEY,
+        card_count INTEGER NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS cards (
+        card_id      TEXT PRIMARY KEY,
+        name         TEXT NOT NULL,
+        pitch        TEXT,
+        cost         TEXT,
+        power        TEXT,
+        defense      TEXT,
+        health       TEXT,
+        intelligence TEXT,
+        types        TEXT NOT NULL,
+        type_text    TEXT,
+        text         TEXT,
+        keywords     TEXT NOT NULL,
+        data         TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS printings (
+        print_id  TEXT PRIMARY KEY,
+        card_id   TEXT NOT NULL REFERENCES cards (card_id),
+        set_code  TEXT NOT NULL REFERENCES sets (code),
+        rarity    TEXT,
+        foiling   TEXT,
+        edition   TEXT,
+        image_url TEXT,
+        artists   TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS cards_name ON cards (name);
+    CREATE INDEX IF NOT EXISTS printings_card_id ON printings (card_id);
+    CREATE INDEX IF NOT EXISTS printings_set_code ON printings (set_code);
+    CREATE INDEX IF NOT EXISTS printings_rarity ON printings (rarity);
+";
+
+/// Writes one or more sets into the database at `path`, creating it if needed.
+///
+/// Cards are upserted by `card_id`. A set's printings are replaced as a whole, so printings
+/// the API no longer lists disappear from the mirror. All sets are written in a single
+/// transaction; on error the database is left as it was.
+///
+/// # Arguments
+/// * `path` - The database file.
+/// * `sets` - Pairs of set code and the set's cards.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+pub fn write<'a>(
+    path: &str,
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+) -> Result<(), Box<dyn Error>> {
+    let mut connection = Connection::open(path)?;
+    connection.execute_batch(SCHEMA)?;
+
+    let transaction = connection.transaction()?;
+    let updated_at = Local::now().to_rfc3339();
+    for (set_code, set) in sets {
+        write_set(&transaction, set_code, set, &updated_at)?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+fn write_set(
+    transaction: &Transaction,
+    set_code: &str,
+    set: &SetResponse,
+    updated_at: &str,
+) -> Result<(), Box<dyn Error>> {
+    transaction.execute(
+        "INSERT INTO sets (code, card_count, updated_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT (code) DO UPDATE SET
+             card_count = excluded.card_count, updated_at = excluded.updated_at",
+        params![set_code, set.results.len() as i64, updated_at],
+    )?;
+    transaction.execute("DELETE FROM printings WHERE set_code = ?1", params![set_code])?;
+
+    for card in &set.results {
+        write_card(transaction, card)?;
+
+        // Reprints listed on the card are written when their own set is
+        let printings = card.printings.iter().filter(|printing| {
+            printing
+                .set_code
+                .as_deref()
+                .is_none_or(|code| code.eq_ignore_ascii_case(set_code))
+        });
+        for printing in printings {
+            write_printing(transaction, set_code, &card.card_id, printing)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_card(transaction: &Transaction, card: &Card) -> Result<(), Box<dyn Error>> {
+    let mut statement = transaction.prepare_cached(
+        "INSERT INTO cards (card_id, name, pitch, cost, power, defense, health, intelligence,
+                            types, type_text, text, keywords, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+         ON CONFLICT (card_id) DO UPDATE SET
+             name = excluded.name, pitch = excluded.pitch, cost = excluded.cost,
+             power = excluded.power, defense = excluded.defense, health = excluded.health,
+             intelligence = excluded.intelligence, types = excluded.types,
+             type_text = excluded.type_text, text = excluded.text,
+             keywords = excluded.keywords, data = excluded.data",
+    )?;
+    statement.execute(params![
+        card.card_id,
+        card.name,
+        card.pitch,
+        card.cost,
+        card.power,
+        card.defense,
+        card.health,
+        card.intelligence,
+        card.types.join(", "),
+        card.type_text,
+        card.text,
+        card.keywords.join(", "),
+        serde_json::to_string(card)?,
+    ])?;
+    Ok(())
+}
+
+fn write_printing(
+    transaction: &Transaction,
+    set_code: &str,
+    card_id: &str,
+    printing: &Printing,
+) -> Result<(), Box<dyn Error>> {
+    let mut statement = transaction.prepare_cached(
+        "INSERT INTO printings (print_id, card_id, set_code, rarity, foiling, edition,
+                                image_url, artists)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT (print_id) DO UPDATE SET
+             card_id = excluded.card_id, set_code = excluded.set_code,
+             rarity = excluded.rarity, foiling = excluded.foiling, edition = excluded.edition,
+             image_url = excluded.image_url, artists = excluded.artists",
+    )?;
+    statement.execute(params![
+        printing.print_id,
+        card_id,
+        set_code,
+        printing.rarity,
+        printing.foiling,
+        printing.edition,
+        printing.image_url,
+        printing.artists.join(", "),
+    ])?;
+    Ok(())
+}
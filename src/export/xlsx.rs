@@ -0,0 +1,35 @@
+//! XLSX workbook export with one sheet per set and one row per printing, in the columns of the
+//! CSV export.
+
+use std::error::Error;
+
+use rust_xlsxwriter::{Format, Workbook};
+
+use super::csv::{HEADERS, card_rows};
+use crate::model::SetResponse;
+
+/// Renders the workbook of the given sets.
+///
+/// # Arguments
+/// * `sets` - Pairs of set code and the set's cards, one sheet each in this order.
+///
+/// # Returns
+/// A `Result` containing the workbook file contents, or an error.
+pub fn render<'a>(
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut workbook = Workbook::new();
+    let header_format = Format::new().set_bold();
+    for (set_code, set) in sets {
+        let sheet = workbook.add_worksheet();
+        sheet.set_name(set_code)?;
+        sheet.write_row_with_format(0, 0, HEADERS, &header_format)?;
+        sheet.set_freeze_panes(1, 0)?;
+        let rows = set.results.iter().flat_map(|card| card_rows(set_code, card));
+        for (index, row) in rows.enumerate() {
+            sheet.write_row(index as u32 + 1, 0, row)?;
+        }
+        sheet.autofit();
+    }
+    Ok(workbook.save_to_buffer()?)
+}
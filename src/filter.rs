@@ -0,0 +1,273 @@
+//! Selecting cards by their attributes for filtered fetches.
+//!
+//! Values given for the same attribute are alternatives; a card has to match every attribute
+//! that was given. All comparisons ignore case.
+
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::model::Card;
+use crate::promo::{self, PromoProgram};
+
+// Field of a card holding its flavor text, kept among the fields the model does not name
+const FLAVOR_TEXT_FIELD: &str = "flavor_text";
+
+/// A range of collector numbers, e.g. `1-30`, or a single number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NumberRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl NumberRange {
+    pub fn contains(&self, number: u32) -> bool {
+        (self.start..=self.end).contains(&number)
+    }
+}
+
+impl FromStr for NumberRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        let number = |part: &str| {
+            part.trim()
+                .parse::<u32>()
+                .map_err(|_| format!("'{}' is not a collector number or range such as 1-30", value))
+        };
+        let (start, end) = match value.split_once('-') {
+            Some((start, end)) => (number(start)?, number(end)?),
+            None => (number(value)?, number(value)?),
+        };
+        if start > end {
+            return Err(format!("the range '{}' ends before it starts", value));
+        }
+        Ok(NumberRange { start, end })
+    }
+}
+
+impl std::fmt::Display for NumberRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+/// Which cards a filtered fetch keeps.
+#[derive(Default)]
+pub struct CardFilter {
+    /// Classes (or any other type, e.g. "Action") the card must have.
+    pub classes: Vec<String>,
+    /// Rarities one of the card's printings must have.
+    pub rarities: Vec<String>,
+    /// Pitch values the card must have.
+    pub pitches: Vec<String>,
+    /// Keywords the card must have.
+    pub keywords: Vec<String>,
+    /// Promo programs one of the card's printings must come from.
+    pub promos: Vec<PromoProgram>,
+    /// Parts of the name of an artist of one of the card's printings.
+    pub artists: Vec<String>,
+    /// Parts of the card's flavor text.
+    pub flavor: Vec<String>,
+    /// Ranges one of the collector numbers of the card's printings must be in.
+    pub numbers: Vec<NumberRange>,
+    /// A raw query string passed to the search API, e.g. `q=dominate`.
+    pub query: Option<String>,
+}
+
+impl CardFilter {
+    /// Whether no filter was given, so whole sets are fetched.
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+            && self.rarities.is_empty()
+            && self.pitches.is_empty()
+            && self.keywords.is_empty()
+            && self.promos.is_empty()
+            && self.artists.is_empty()
+            && self.flavor.is_empty()
+            && self.numbers.is_empty()
+            && self.query.is_none()
+    }
+
+    /// Whether a card passes the attribute filters. The raw query is applied by the API.
+    pub fn matches(&self, card: &Card) -> bool {
+        let any = |values: &[String], wanted: &str| {
+            values.iter().any(|value| value.trim().eq_ignore_ascii_case(wanted))
+        };
+        let wanted = |filter: &[String], test: &dyn Fn(&str) -> bool| {
+            filter.is_empty() || filter.iter().any(|value| test(value.trim()))
+        };
+        let contains = |text: &str, part: &str| text.to_lowercase().contains(&part.to_lowercase());
+        let flavor_text = card.extra.get(FLAVOR_TEXT_FIELD).and_then(Value::as_str);
+        let artists = || card.printings.iter().flat_map(|printing| &printing.artists);
+        let numbers = || {
+            card.printings
+                .iter()
+                .filter_map(|printing| printing.collector_number()?.parse::<u32>().ok())
+        };
+
+        wanted(&self.classes, &|class| any(&card.types, class))
+            && wanted(&self.rarities, &|rarity| {
+                card.printings.iter().any(|printing| {
+                    printing.rarity.as_deref().is_some_and(|r| r.eq_ignore_ascii_case(rarity))
+                })
+            })
+            && wanted(&self.pitches, &|pitch| card.pitch.as_deref() == Some(pitch))
+            && wanted(&self.keywords, &|keyword| any(&card.keywords, keyword))
+            && (self.promos.is_empty() || promo::has_program(card, &self.promos))
+            && wanted(&self.artists, &|artist| artists().any(|name| contains(name, artist)))
+            && wanted(&self.flavor, &|part| flavor_text.is_some_and(|text| contains(text, part)))
+            && (self.numbers.is_empty()
+                || numbers().any(|number| self.numbers.iter().any(|range| range.contains(number))))
+    }
+
+    /// A file name describing the filter, e.g. `class-brute_pitch-3`.
+    pub fn default_name(&self) -> String {
+        let mut parts = Vec::new();
+        for (label, values) in [
+            ("class", &self.classes),
+            ("rarity", &self.rarities),
+            ("pitch", &self.pitches),
+            ("keyword", &self.keywords),
+            ("artist", &self.artists),
+            ("flavor", &self.flavor),
+        ] {
+            if !values.is_empty() {
+                parts.push(format!("{}-{}", label, values.join("-")));
+            }
+        }
+        if !self.promos.is_empty() {
+            let programs: Vec<&str> = self.promos.iter().map(|program| program.name()).collect();
+            parts.push(format!("promo-{}", programs.join("-")));
+        }
+        if !self.numbers.is_empty() {
+            let ranges: Vec<String> = self.numbers.iter().map(NumberRange::to_string).collect();
+            parts.push(format!("number-{}", ranges.join("-")));
+        }
+        if let Some(query) = &self.query {
+            parts.push(format!("query-{}", query));
+        }
+
+        // Keep the name safe to use as a file name on every platform
+        parts
+            .join("_")
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn range(value: &str) -> Result<NumberRange, String> {
+        value.parse()
+    }
+
+    fn card(value: Value) -> Card {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn bravo() -> Card {
+        card(json!({
+            "card_id": "bravo-showstopper",
+            "name": "Bravo, Showstopper",
+            "types": ["Guardian", "Hero"],
+            "flavor_text": "The Crowd Roars for More",
+            "printings": [
+                {"print_id": "WTR038", "rarity": "T", "artists": ["Alexander Mokhov"]},
+                {"print_id": "HER071", "rarity": "P", "artists": ["Riordan Delmundo"]}
+            ]
+        }))
+    }
+
+    #[test]
+    fn parses_collector_numbers_and_ranges() {
+        assert_eq!(range("1-30"), Ok(NumberRange { start: 1, end: 30 }));
+        assert_eq!(range("45"), Ok(NumberRange { start: 45, end: 45 }));
+        assert_eq!(range(" 3 - 7 "), Ok(NumberRange { start: 3, end: 7 }));
+        assert_eq!(range("45").unwrap().to_string(), "45");
+        assert_eq!(range("1-30").unwrap().to_string(), "1-30");
+    }
+
+    #[test]
+    fn rejects_reversed_ranges_and_non_numbers() {
+        assert!(range("30-1").unwrap_err().contains("ends before it starts"));
+        assert!(range("-5").is_err());
+        assert!(range("a").is_err());
+        assert!(range("").is_err());
+    }
+
+    #[test]
+    fn matches_artists_and_flavor_text_ignoring_case() {
+        let bravo = bravo();
+        let by_artist = |artist: &str| {
+            CardFilter { artists: vec![artist.to_string()], ..Default::default() }.matches(&bravo)
+        };
+        assert!(by_artist("mokhov"));
+        assert!(by_artist("RIORDAN"));
+        assert!(!by_artist("Kara"));
+
+        let by_flavor = |part: &str| {
+            CardFilter { flavor: vec![part.to_string()], ..Default::default() }.matches(&bravo)
+        };
+        assert!(by_flavor("crowd roars"));
+        assert!(!by_flavor("silence"));
+        let without_flavor = card(json!({"card_id": "a", "name": "A", "printings": []}));
+        let filter = CardFilter { flavor: vec![String::from("a")], ..Default::default() };
+        assert!(!filter.matches(&without_flavor));
+    }
+
+    #[test]
+    fn matches_a_range_containing_the_number_of_any_printing() {
+        let bravo = bravo();
+        let by_numbers = |ranges: &[&str]| {
+            let numbers = ranges.iter().map(|value| range(value).unwrap()).collect();
+            CardFilter { numbers, ..Default::default() }.matches(&bravo)
+        };
+        assert!(by_numbers(&["1-40"]));
+        assert!(by_numbers(&["71"]));
+        assert!(by_numbers(&["1-10", "60-80"]));
+        assert!(!by_numbers(&["39-70"]));
+    }
+
+    #[test]
+    fn attributes_must_all_match() {
+        let bravo = bravo();
+        let filter = CardFilter {
+            classes: vec![String::from("guardian")],
+            rarities: vec![String::from("t")],
+            ..Default::default()
+        };
+        assert!(filter.matches(&bravo));
+        let filter = CardFilter { pitches: vec![String::from("1")], ..filter };
+        assert!(!filter.matches(&bravo));
+    }
+
+    #[test]
+    fn default_name_is_a_safe_file_name() {
+        let filter = CardFilter {
+            classes: vec![String::from("Brute")],
+            pitches: vec![String::from("3")],
+            ..Default::default()
+        };
+        assert_eq!(filter.default_name(), "class-brute_pitch-3");
+
+        let filter = CardFilter {
+            artists: vec![String::from("Riordan Delmundo")],
+            numbers: vec![range("1-30").unwrap()],
+            query: Some(String::from("q=dominate/../*?")),
+            ..Default::default()
+        };
+        let name = filter.default_name();
+        assert_eq!(name, "artist-riordan-delmundo_number-1-30_query-q-dominate------");
+        assert!(name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+    }
+}
@@ -0,0 +1,354 @@
+//! Recorded API responses, to work against the tool without reaching the API.
+//!
+//! A fixtures directory holds the body of every page requested, one file per page named after
+//! the page's URL relative to the API's base URL: the first page of Welcome to Rathe is
+//! `cards@set_code=WTR.json`, its second page `cards@set_code=WTR&page=2.json` and the sets
+//! listing `sets.json`. A recorded page keeps the `next` link the API sent, which is mapped
+//! back to a file by its path, so a listing recorded from one host replays under any base URL.
+//!
+//! A page may have a `.http` file next to it (`cards@set_code=WTR.http`) telling how it was
+//! served: its `etag` and `last_modified` validators, which answer a conditional request
+//! carrying them with 304 Not Modified, the schema `version` its `api-version` header declared,
+//! and `failures` answered before the page, such as a 429 with `Retry-After`. Recording writes
+//! the validators and the version; failures are written by hand.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::manifest::Validators;
+
+// File name, without extension, of the API's base URL itself
+const ROOT_NAME: &str = "%root";
+
+// Extension of the file telling how a recorded page was served
+const EXCHANGE_EXTENSION: &str = "http";
+
+/// How a recorded page was served, besides its body.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Exchange {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    /// The schema version the page declared, such as `v2`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    /// Failed responses answered before the page, in order, once per run.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    failures: Vec<RecordedFailure>,
+}
+
+/// A failed response answered in place of a recorded page.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RecordedFailure {
+    status: u16,
+    /// Seconds sent in the `Retry-After` header, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
+}
+
+/// A recorded answer to a request.
+pub enum Replayed {
+    /// The page's body, with the validators and the schema version it was served with.
+    Page { body: String, validators: Validators, version: Option<String> },
+    /// 304 Not Modified, for a conditional request carrying the page's validators.
+    NotModified,
+    /// A failed response, with the delay its `Retry-After` header asked for.
+    Failed { status: StatusCode, retry_after: Option<Duration> },
+}
+
+/// Whether a fixtures directory answers requests or keeps the API's answers.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FixtureMode {
+    /// Requests are answered from the directory, without sending any
+    Replay,
+    /// Requests are sent, and every page received is saved to the directory
+    Record,
+}
+
+/// A directory of recorded API responses.
+#[derive(Clone)]
+pub struct Fixtures {
+    dir: PathBuf,
+    mode: FixtureMode,
+    /// Number of requests answered so far for every page with failures, shared by clones.
+    attempts: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl Fixtures {
+    /// Opens a directory of recorded responses to answer requests from.
+    ///
+    /// # Returns
+    /// A `Result` containing the fixtures, or an error if the directory does not exist.
+    pub fn replay(dir: impl Into<PathBuf>) -> Result<Self, Box<dyn Error>> {
+        let dir = dir.into();
+        if !dir.is_dir() {
+            return Err(format!("Error: Fixtures directory '{}' not found", dir.display()).into());
+        }
+        Ok(Fixtures { dir, mode: FixtureMode::Replay, attempts: Default::default() })
+    }
+
+    /// Opens a directory to record the responses of the API in, creating it if needed.
+    pub fn record(dir: impl Into<PathBuf>) -> Result<Self, Box<dyn Error>> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Fixtures { dir, mode: FixtureMode::Record, attempts: Default::default() })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn mode(&self) -> FixtureMode {
+        self.mode
+    }
+
+    /// Reads the recorded response of a page: the next of its recorded failures, 304 Not
+    /// Modified for a conditional request carrying its validators, or else its body.
+    ///
+    /// # Arguments
+    /// * `base_url` - The base URL of the API the client sends requests to.
+    /// * `url` - The URL of the page.
+    /// * `conditional` - The validators sent with the request, if it is conditional.
+    ///
+    /// # Returns
+    /// A `Result` containing the recorded answer, or an error naming the file that is missing
+    /// or invalid.
+    pub fn load(
+        &self,
+        base_url: &str,
+        url: &str,
+        conditional: Option<&Validators>,
+    ) -> Result<Replayed, Box<dyn Error>> {
+        let name = fixture_name(base_url, url)?;
+        let path = self.dir.join(&name);
+        let exchange_path = path.with_extension(EXCHANGE_EXTENSION);
+        let exchange: Exchange = match fs::read_to_string(&exchange_path) {
+            Ok(content) => serde_json::from_str(&content).map_err(|e| {
+                format!("Error: Invalid recorded exchange {}: {}", exchange_path.display(), e)
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Exchange::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        if !exchange.failures.is_empty() {
+            let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+            let attempt = attempts.entry(name).or_default();
+            *attempt += 1;
+            if let Some(failure) = exchange.failures.get(*attempt - 1) {
+                let status = StatusCode::from_u16(failure.status).map_err(|_| {
+                    let path = exchange_path.display();
+                    format!("Error: Invalid status {} in {}", failure.status, path)
+                })?;
+                let retry_after = failure.retry_after.map(Duration::from_secs);
+                return Ok(Replayed::Failed { status, retry_after });
+            }
+        }
+        let recorded = Validators { etag: exchange.etag, last_modified: exchange.last_modified };
+        let not_modified = conditional.is_some_and(|sent| match (&sent.etag, &recorded.etag) {
+            (Some(sent), Some(recorded)) => sent == recorded,
+            _ => sent.last_modified.is_some() && sent.last_modified == recorded.last_modified,
+        });
+        if not_modified {
+            return Ok(Replayed::NotModified);
+        }
+
+        let body = fs::read_to_string(&path).map_err(|e| {
+            format!("No recorded response of {} in {}: {}", url, path.display(), e)
+        })?;
+        Ok(Replayed::Page { body, validators: recorded, version: exchange.version })
+    }
+
+    /// Records the response of a page, replacing an earlier recording.
+    ///
+    /// # Arguments
+    /// * `base_url` - The base URL of the API the client sends requests to.
+    /// * `url` - The URL of the page.
+    /// * `body` - The page's body.
+    /// * `validators` - The validators the page was served with.
+    /// * `version` - The schema version the page declared, if any.
+    ///
+    /// # Returns
+    /// A `Result` indicating success or an error.
+    pub fn store(
+        &self,
+        base_url: &str,
+        url: &str,
+        body: &str,
+        validators: &Validators,
+        version: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = self.dir.join(fixture_name(base_url, url)?);
+        crate::storage::write_atomically(&path, body.as_bytes())?;
+        let exchange_path = path.with_extension(EXCHANGE_EXTENSION);
+        if validators.is_empty() && version.is_none() {
+            match fs::remove_file(&exchange_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            }
+        } else {
+            let exchange = Exchange {
+                etag: validators.etag.clone(),
+                last_modified: validators.last_modified.clone(),
+                version: version.map(str::to_string),
+                failures: Vec::new(),
+            };
+            let content = serde_json::to_string_pretty(&exchange)?;
+            crate::storage::write_atomically(&exchange_path, content.as_bytes())
+        }
+    }
+}
+
+/// Names the fixture file of a page: its path relative to the API's base URL with `/` turned
+/// into `_`, followed by `@` and the query if it has one.
+///
+/// Every other character that is not a letter, a digit or one of `.-=&+,` is percent-encoded,
+/// as are `_` in the path and `%` anywhere, so two different URLs never share a file. A trailing
+/// `/` is left out, as the API serves the same page with and without it; the base URL itself is
+/// named `%root.json`.
+///
+/// # Arguments
+/// * `base_url` - The base URL of the API.
+/// * `url` - The URL of the page; a URL on another host is taken relative to the base URL's
+///   path, as the `next` links of recorded pages point at the API they were recorded from.
+///
+/// # Returns
+/// A `Result` containing the file name, or an error if the URL is not below the base URL.
+pub fn fixture_name(base_url: &str, url: &str) -> Result<String, Box<dyn Error>> {
+    let base_url = base_url.trim_end_matches('/');
+    let relative = match url.strip_prefix(base_url) {
+        Some(relative) => relative.to_string(),
+        None => {
+            let base = reqwest::Url::parse(base_url)?;
+            let parsed = reqwest::Url::parse(url)?;
+            let path = parsed
+                .path()
+                .strip_prefix(base.path().trim_end_matches('/'))
+                .ok_or_else(|| format!("{} is not an API URL below {}", url, base_url))?;
+            match parsed.query() {
+                Some(query) => format!("{}?{}", path, query),
+                None => path.to_string(),
+            }
+        }
+    };
+    if !relative.is_empty() && !relative.starts_with(['/', '?']) {
+        return Err(format!("{} is not an API URL below {}", url, base_url).into());
+    }
+    let (path, query) = match relative.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (relative.as_str(), None),
+    };
+    let path = path.strip_prefix('/').unwrap_or(path);
+    let path = path.strip_suffix('/').unwrap_or(path);
+    let mut name: String =
+        path.split('/').map(|segment| escape(segment, "")).collect::<Vec<_>>().join("_");
+    if let Some(query) = query {
+        name.push('@');
+        name.push_str(&escape(query, "_@"));
+    }
+    if name.is_empty() {
+        name = String::from(ROOT_NAME);
+    }
+    Ok(format!("{}.json", name))
+}
+
+/// Percent-encodes every byte of a part of a URL but letters, digits, `.-=&+,` and `kept`.
+fn escape(part: &str, kept: &str) -> String {
+    let mut escaped = String::new();
+    for c in part.chars() {
+        if c.is_ascii_alphanumeric() || ".-=&+,".contains(c) || kept.contains(c) {
+            escaped.push(c);
+        } else {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                escaped.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE_URL: &str = "https://cards.fabtcg.com/api/search/v1";
+
+    fn name(url: &str) -> String {
+        fixture_name(BASE_URL, url).unwrap()
+    }
+
+    #[test]
+    fn names_pages_after_their_path_and_query() {
+        assert_eq!(name(&format!("{}/sets", BASE_URL)), "sets.json");
+        assert_eq!(name(&format!("{}/cards/?set_code=WTR", BASE_URL)), "cards@set_code=WTR.json");
+        assert_eq!(
+            name("http://localhost:8765/api/search/v1/cards?set_code=WTR&page=2"),
+            "cards@set_code=WTR&page=2.json"
+        );
+        assert_eq!(name(BASE_URL), "%root.json");
+        assert_eq!(name(&format!("{}/", BASE_URL)), "%root.json");
+        assert!(fixture_name(BASE_URL, "https://cards.fabtcg.com/api/search/v1x").is_err());
+    }
+
+    #[test]
+    fn different_urls_get_different_names() {
+        let urls = [
+            "/cards",
+            "/cards/search",
+            "/cards_search",
+            "/cards%2Fsearch",
+            "/cards@q",
+            "/cards?q",
+            "/cards?",
+            "/cards?q=a/b",
+            "/cards?q=a_b",
+            "/cards?q=a b",
+            "/cards?q=a%20b",
+            "/cards?q=a:b",
+            "/cards?q=a*b",
+            "/cards?q=%C3%A9",
+            "/cards?q=\u{e9}",
+            "/%root",
+            "",
+            "?",
+        ];
+        let mut names = std::collections::HashMap::new();
+        for url in urls {
+            let name = name(&format!("{}{}", BASE_URL, url));
+            if let Some(other) = names.insert(name.clone(), url) {
+                panic!("{:?} and {:?} are both named {}", other, url, name);
+            }
+        }
+    }
+    #[test]
+    fn replays_the_recorded_validators_and_version() {
+        let dir = std::env::temp_dir().join(format!("fab-scrapper-record-{}", std::process::id()));
+        let fixtures = Fixtures::record(&dir).unwrap();
+        let url = format!("{}/cards?set_code=WTR", BASE_URL);
+        let validators = Validators { etag: Some(String::from("\"v1\"")), last_modified: None };
+        fixtures.store(BASE_URL, &url, "{}", &validators, Some("v2")).unwrap();
+
+        let fixtures = Fixtures::replay(&dir).unwrap();
+        match fixtures.load(BASE_URL, &url, None).unwrap() {
+            Replayed::Page { body, validators, version } => {
+                assert_eq!(body, "{}");
+                assert_eq!(validators.etag.as_deref(), Some("\"v1\""));
+                assert_eq!(version.as_deref(), Some("v2"));
+            }
+            _ => panic!("the recorded page was not replayed"),
+        }
+        let conditional = fixtures.load(BASE_URL, &url, Some(&validators)).unwrap();
+        assert!(matches!(conditional, Replayed::NotModified));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
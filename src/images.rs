@@ -0,0 +1,335 @@
+//! Card image downloads into the `images/` folder of the output directory.
+//!
+//! Each card gets one image per set, taken from its first printing in that set. Images that
+//! are already on disk with the size and checksum recorded in the manifest are not downloaded
+//! again, and downloads are written to a temporary file first so an interrupted run leaves no
+//! partial images behind.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use reqwest::header::CONTENT_LENGTH;
+use tracing::{debug, error, warn};
+
+use crate::events::{Event, EventLog};
+use crate::model::{Card, SetResponse};
+use crate::rate_limit::RateLimiter;
+use crate::storage::{self, manifest::{self, CorruptEntry, ImageEntry, Manifest}};
+
+// Folder images are written to, inside the output directory
+pub const IMAGES_DIR: &str = "images";
+
+// Number of times a failing image download is attempted before giving up
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 3;
+
+// Pause before retrying a failed download, multiplied by the attempt number
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+// Extensions kept from the image URL; anything else is saved as PNG
+const IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "webp"];
+
+/// How many images of a set were downloaded, already present or failed.
+#[derive(Default)]
+pub struct ImageSummary {
+    pub downloaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Images left out because they stayed corrupt on an earlier run.
+    pub corrupt: usize,
+}
+
+impl ImageSummary {
+    pub fn add(&mut self, other: &ImageSummary) {
+        self.downloaded += other.downloaded;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+        self.corrupt += other.corrupt;
+    }
+}
+
+/// A download that arrived in full, but whose data is not the image it should be.
+#[derive(Debug)]
+struct CorruptImage(String);
+
+impl fmt::Display for CorruptImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for CorruptImage {}
+
+/// Downloads the image of every card in a set that is not on disk yet.
+///
+/// An image whose data is still not a valid image after every attempt is recorded in the
+/// manifest as corrupt, and is not downloaded again while its URL stays the same.
+///
+/// # Arguments
+/// * `client` - The HTTP client to send the requests with.
+/// * `limiter` - The rate limiter every download waits on.
+/// * `base_output_dir` - The root output directory.
+/// * `set_code` - The set code (e.g., "WTR").
+/// * `set` - The set's cards.
+/// * `manifest` - The manifest of the output directory; updated with every image written and
+///   every download given up on.
+/// * `events` - Receives an event for every image downloaded.
+///
+/// # Returns
+/// The number of images downloaded, skipped, failed and left out as corrupt.
+pub fn download_set_images(
+    client: &reqwest::blocking::Client,
+    limiter: &RateLimiter,
+    base_output_dir: &str,
+    set_code: &str,
+    set: &SetResponse,
+    manifest: &mut Manifest,
+    events: &EventLog,
+) -> ImageSummary {
+    let Manifest { images: recorded, corrupt_images: corrupt, .. } = manifest;
+    let mut summary = ImageSummary::default();
+    let set_dir = format!("{}/{}/{}", base_output_dir, IMAGES_DIR, set_code);
+    if let Err(e) = fs::create_dir_all(&set_dir) {
+        error!("Could not create image directory {}: {}", set_dir, e);
+        summary.failed = set.results.len();
+        return summary;
+    }
+
+    let mut seen = HashSet::new();
+    for card in &set.results {
+        let Some(url) = image_url(card, set_code) else {
+            continue;
+        };
+        // A card listed twice in a set only needs its image once
+        if !seen.insert(card.card_id.as_str()) {
+            continue;
+        }
+
+        let relative_path = relative_image_path(card, set_code, url);
+        let path = format!("{}/{}", base_output_dir, relative_path);
+        match existing_image(&path, url, recorded.get(&relative_path)) {
+            Ok(Some(entry)) => {
+                recorded.insert(relative_path, entry);
+                summary.skipped += 1;
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Could not check existing image {}: {}. Downloading it again...", path, e),
+        }
+        if corrupt.get(&relative_path).is_some_and(|entry| entry.source == url) {
+            summary.corrupt += 1;
+            continue;
+        }
+
+        match download_with_retries(client, limiter, url, &path) {
+            Ok(entry) => {
+                debug!("Saved image {}", path);
+                events.record(Event::ImageDownloaded { path: &path, url });
+                corrupt.remove(&relative_path);
+                recorded.insert(relative_path, entry);
+                summary.downloaded += 1;
+            }
+            Err(e) => {
+                error!("Could not download image {} for {}: {}", url, card.card_id, e);
+                if e.is::<CorruptImage>() {
+                    let entry = CorruptEntry {
+                        source: url.to_string(),
+                        reason: e.to_string(),
+                        detected_at: chrono::Local::now().to_rfc3339(),
+                    };
+                    corrupt.insert(relative_path, entry);
+                }
+                summary.failed += 1;
+            }
+        }
+    }
+    if summary.corrupt > 0 {
+        warn!(
+            "{} image(s) of set {} stayed corrupt on an earlier run and are not downloaded again; \
+            remove them from corrupt_images in the manifest to retry.",
+            summary.corrupt, set_code
+        );
+    }
+    summary
+}
+
+/// The path of a card's image in a set, relative to the output directory.
+///
+/// # Arguments
+/// * `card` - The card.
+/// * `set_code` - The set code (e.g., "WTR").
+/// * `url` - The URL the image is downloaded from, which gives its extension.
+fn relative_image_path(card: &Card, set_code: &str, url: &str) -> String {
+    format!(
+        "{}/{}/{}.{}",
+        IMAGES_DIR,
+        set_code,
+        card.card_id.replace(['/', '\\'], "_"),
+        image_extension(url)
+    )
+}
+
+/// Whether the image of a card in a set has been downloaded into the output directory.
+///
+/// # Returns
+/// `None` if the card has no image URL in the set, so there is nothing to download.
+pub fn image_on_disk(base_output_dir: &str, set_code: &str, card: &Card) -> Option<bool> {
+    let url = image_url(card, set_code)?;
+    let path = format!("{}/{}", base_output_dir, relative_image_path(card, set_code, url));
+    Some(fs::metadata(path).is_ok())
+}
+
+/// Picks the image URL of a card's first printing in the given set.
+fn image_url<'a>(card: &'a Card, set_code: &str) -> Option<&'a str> {
+    card.printings
+        .iter()
+        .filter(|printing| {
+            printing
+                .set_code
+                .as_deref()
+                .is_none_or(|code| code.eq_ignore_ascii_case(set_code))
+        })
+        .find_map(|printing| printing.image_url.as_deref())
+}
+
+/// Returns the file extension to save an image under, based on its URL.
+fn image_extension(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .filter(|extension| IMAGE_EXTENSIONS.contains(&extension.as_str()))
+        .unwrap_or_else(|| String::from("png"))
+}
+
+/// Checks whether an image already on disk can be kept.
+///
+/// An image recorded in the manifest is kept while its URL, size and SHA-256 still match, so a
+/// truncated or damaged file is downloaded again. One that is on disk but not recorded (e.g.
+/// copied in by hand) is kept and recorded as it is, if it holds an image of its extension.
+///
+/// # Returns
+/// A `Result` containing the manifest entry of an image to keep, `None` if it has to be
+/// downloaded, or an error if the file could not be read.
+fn existing_image(
+    path: &str,
+    url: &str,
+    recorded: Option<&ImageEntry>,
+) -> Result<Option<ImageEntry>, Box<dyn Error>> {
+    let Ok(metadata) = fs::metadata(path) else {
+        return Ok(None);
+    };
+    match recorded {
+        Some(entry) if entry.url == url && entry.size == metadata.len() => {
+            let sha256 = manifest::sha256_hex(&fs::read(path)?);
+            if sha256 != entry.sha256 {
+                warn!(
+                    "Image {} does not match its recorded checksum. Downloading it again...",
+                    path
+                );
+                return Ok(None);
+            }
+            Ok(Some(entry.clone()))
+        }
+        Some(_) => Ok(None),
+        None => {
+            let data = fs::read(path)?;
+            if check_image_format(&data, path).is_err() {
+                return Ok(None);
+            }
+            Ok(Some(ImageEntry {
+                url: url.to_string(),
+                size: data.len() as u64,
+                sha256: manifest::sha256_hex(&data),
+            }))
+        }
+    }
+}
+
+/// Downloads an image, retrying failed attempts with an increasing pause.
+fn download_with_retries(
+    client: &reqwest::blocking::Client,
+    limiter: &RateLimiter,
+    url: &str,
+    path: &str,
+) -> Result<ImageEntry, Box<dyn Error>> {
+    let mut attempt = 1;
+    loop {
+        match download_image(client, limiter, url, path) {
+            Ok(entry) => return Ok(entry),
+            Err(e) if attempt < MAX_DOWNLOAD_ATTEMPTS => {
+                warn!(
+                    "Download of {} failed ({}). Retrying (attempt {} of {})...",
+                    url,
+                    e,
+                    attempt + 1,
+                    MAX_DOWNLOAD_ATTEMPTS
+                );
+                std::thread::sleep(RETRY_BACKOFF * attempt);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Checks that image data starts with the signature of the format its file extension names,
+/// so that an error page served with a success status is not saved as an image.
+fn check_image_format(data: &[u8], path: &str) -> Result<(), Box<dyn Error>> {
+    let extension = path.rsplit_once('.').map_or("", |(_, extension)| extension);
+    let valid = match extension {
+        "png" => data.starts_with(b"\x89PNG\r\n\x1a\n"),
+        "jpg" | "jpeg" => data.starts_with(&[0xFF, 0xD8, 0xFF]),
+        "webp" => data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP".as_slice()),
+        _ => true,
+    };
+    if valid {
+        return Ok(());
+    }
+    let looks_like_html = data.trim_ascii_start().first() == Some(&b'<');
+    Err(Box::new(CorruptImage(format!(
+        "the data is not a {} image{}",
+        extension.to_uppercase(),
+        if looks_like_html { " but an HTML page" } else { "" }
+    ))))
+}
+
+/// Downloads an image once, checking it arrived complete and holds an image of the format its
+/// extension names before moving it into place.
+fn download_image(
+    client: &reqwest::blocking::Client,
+    limiter: &RateLimiter,
+    url: &str,
+    path: &str,
+) -> Result<ImageEntry, Box<dyn Error>> {
+    limiter.acquire();
+    let response = client.get(url).send()?;
+    if !response.status().is_success() {
+        return Err(format!("request failed with status: {}", response.status()).into());
+    }
+
+    let expected_size = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let data = response.bytes()?;
+    if data.is_empty() {
+        return Err(Box::new(CorruptImage(String::from("the server returned an empty image"))));
+    }
+    if let Some(expected) = expected_size
+        && expected != data.len() as u64
+    {
+        return Err(format!("expected {} bytes, received {}", expected, data.len()).into());
+    }
+    check_image_format(&data, path)?;
+
+    storage::write_atomically(Path::new(path), &data)?;
+    Ok(ImageEntry {
+        url: url.to_string(),
+        size: data.len() as u64,
+        sha256: manifest::sha256_hex(&data),
+    })
+}
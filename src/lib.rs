@@ -0,0 +1,32 @@
+//! Fetching, parsing and storing Flesh and Blood card data from the cards.fabtcg.com API.
+//!
+//! [`FabClient`] downloads sets into the typed [`model`], and [`storage`] writes them to an
+//! output directory in the formats the `fab-scrapper` command line tool produces.
+
+pub mod api;
+pub mod completeness;
+pub mod diff;
+pub mod events;
+pub mod export;
+pub mod filter;
+pub mod fixtures;
+pub mod images;
+pub mod mirror;
+pub mod model;
+pub mod notify;
+pub mod plan;
+pub mod policy;
+pub mod prices;
+pub mod promo;
+pub mod rate_limit;
+pub mod reminder;
+pub mod report;
+pub mod retry;
+pub mod schema;
+pub mod stats;
+pub mod storage;
+pub mod suggest;
+pub mod template;
+pub mod validate;
+
+pub use api::FabClient;
@@ -0,0 +1,263 @@
+//! Log output and progress bars.
+//!
+//! Everything the tool reports goes through `tracing` to stderr, either as plain lines or as
+//! one JSON object per line for systemd and cron. Progress bars are only drawn on a terminal
+//! and in the plain format; log lines are printed above them instead of through them.
+//!
+//! With `--log-file`, log lines go to a file instead, for long-running `watch` processes. The
+//! file is rotated once it grows past `LOG_FILE_MAX_BYTES`: it becomes `<file>.1`, older files
+//! move up by one, and only the last `LOG_FILES_KEPT` are kept.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use chrono::Local;
+use clap::ValueEnum;
+use fab_scrapper::api::Progress;
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::{self, FormatEvent, FormatFields};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::{FmtContext, MakeWriter};
+use tracing_subscriber::registry::LookupSpan;
+
+// How often progress bars are redrawn while nothing else changes
+const TICK_INTERVAL: Duration = Duration::from_millis(200);
+
+// Size past which the log file is rotated
+const LOG_FILE_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+// Number of rotated log files kept next to the current one
+const LOG_FILES_KEPT: usize = 5;
+
+// Every progress bar of the run, so log lines can be printed around them
+static BARS: LazyLock<MultiProgress> = LazyLock::new(MultiProgress::new);
+
+// Whether progress bars are drawn at all, decided once logging is set up
+static SHOW_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+/// How log lines are written.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Plain lines, with warnings and errors labelled
+    Text,
+    /// One JSON object per line, with the level, time and span fields
+    Json,
+}
+
+/// Installs the global logger.
+///
+/// # Arguments
+/// * `level` - The most detailed level printed.
+/// * `format` - How log lines are written.
+/// * `log_file` - File to write log lines to instead of stderr, if any.
+///
+/// # Returns
+/// A `Result` indicating success or an error if the log file cannot be opened.
+pub fn init(level: Level, format: LogFormat, log_file: Option<&str>) -> io::Result<()> {
+    let writer = match log_file {
+        Some(path) => BoxMakeWriter::new(Mutex::new(RotatingFile::open(path)?)),
+        None => BoxMakeWriter::new(StderrAboveBars),
+    };
+    let builder = tracing_subscriber::fmt().with_max_level(level).with_writer(writer);
+    match format {
+        LogFormat::Text => builder.event_format(Plain { timestamps: log_file.is_some() }).init(),
+        LogFormat::Json => builder.json().with_current_span(true).init(),
+    }
+    let show = format == LogFormat::Text
+        && level >= Level::INFO
+        && log_file.is_none()
+        && io::stderr().is_terminal();
+    SHOW_PROGRESS.store(show, Ordering::Relaxed);
+    Ok(())
+}
+
+/// A progress bar counting `len` items, hidden when progress bars are off.
+///
+/// # Arguments
+/// * `len` - The number of items, e.g. sets.
+/// * `unit` - What the items are, shown after the count.
+pub fn progress_bar(len: u64, unit: &str) -> ProgressBar {
+    if !SHOW_PROGRESS.load(Ordering::Relaxed) {
+        return ProgressBar::hidden();
+    }
+    let template = format!("{{bar:30}} {{pos}}/{{len}} {} ({{eta}} left) {{msg}}", unit);
+    let style = ProgressStyle::with_template(&template).unwrap_or_else(|_| ProgressStyle::default_bar());
+    let bar = BARS.add(ProgressBar::new(len).with_style(style));
+    bar.enable_steady_tick(TICK_INTERVAL);
+    bar
+}
+
+/// Shows the sets a fetch has finished, the pages and bytes a `FabClient` downloads, and how
+/// full the write queue is, on a progress bar.
+pub struct DownloadProgress {
+    bar: OnceLock<ProgressBar>,
+    bytes: AtomicU64,
+    /// The latest page downloaded, as shown on the bar.
+    page: Mutex<String>,
+    /// Downloaded sets waiting to be written, and how many may wait at most.
+    write_queue: Mutex<(usize, usize)>,
+}
+
+impl DownloadProgress {
+    pub fn new() -> Self {
+        DownloadProgress {
+            bar: OnceLock::new(),
+            bytes: AtomicU64::new(0),
+            page: Mutex::new(String::new()),
+            write_queue: Mutex::new((0, 0)),
+        }
+    }
+
+    /// Shows how many downloaded sets wait to be written, and whether that makes the fetch
+    /// disk-bound (the queue is full) or network-bound (the writer has nothing to do).
+    pub fn write_queue(&self, queued: usize, capacity: usize) {
+        *self.write_queue.lock().unwrap_or_else(|e| e.into_inner()) = (queued, capacity);
+        self.redraw();
+    }
+
+    fn redraw(&self) {
+        let Some(bar) = self.bar.get() else {
+            return;
+        };
+        let mut message = self.page.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let (queued, capacity) = *self.write_queue.lock().unwrap_or_else(|e| e.into_inner());
+        if capacity > 0 {
+            let bound = match queued {
+                0 => " (network-bound)",
+                queued if queued >= capacity => " (disk-bound)",
+                _ => "",
+            };
+            if !message.is_empty() {
+                message.push_str(", ");
+            }
+            message.push_str(&format!("write queue {}/{}{}", queued, capacity, bound));
+        }
+        bar.set_message(message);
+    }
+
+    /// Shows the bar once the number of sets is known; downloads before are not shown.
+    ///
+    /// # Arguments
+    /// * `sets` - The number of sets the fetch processes.
+    pub fn start(&self, sets: u64) -> ProgressBar {
+        self.bar.get_or_init(|| progress_bar(sets, "sets")).clone()
+    }
+}
+
+impl Progress for DownloadProgress {
+    fn page_fetched(&self, label: &str, page: usize, pages: Option<usize>, bytes: usize) {
+        let total = self.bytes.fetch_add(bytes as u64, Ordering::Relaxed) + bytes as u64;
+        let pages = pages.map_or_else(|| String::from("?"), |pages| pages.to_string());
+        *self.page.lock().unwrap_or_else(|e| e.into_inner()) =
+            format!("{}: page {}/{}, {} downloaded", label, page, pages, HumanBytes(total));
+        self.redraw();
+    }
+}
+
+/// Formats events as their message and fields alone, labelling warnings and errors.
+struct Plain {
+    /// Whether lines start with the local time, for log files read after the fact.
+    timestamps: bool,
+}
+
+impl<S, N> FormatEvent<S, N> for Plain
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        if self.timestamps {
+            write!(writer, "{} ", Local::now().format("%Y-%m-%d %H:%M:%S"))?;
+        }
+        match *event.metadata().level() {
+            Level::ERROR => write!(writer, "Error: ")?,
+            Level::WARN => write!(writer, "Warning: ")?,
+            _ => {}
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Writes log lines to stderr, clearing the progress bars while doing so.
+struct StderrAboveBars;
+
+impl Write for StderrAboveBars {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        BARS.suspend(|| io::stderr().write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for StderrAboveBars {
+    type Writer = StderrAboveBars;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        StderrAboveBars
+    }
+}
+
+/// A log file that is rotated once it grows past `LOG_FILE_MAX_BYTES`.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Opens a log file for appending.
+    fn open(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile { path: PathBuf::from(path), file, size })
+    }
+
+    /// The path of the `index`-th rotated file, e.g. `fab-scrapper.log.2`.
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    /// Moves the current file to `<file>.1`, shifting older files up and dropping the oldest.
+    fn rotate(&mut self) -> io::Result<()> {
+        for index in (1..LOG_FILES_KEPT).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > LOG_FILE_MAX_BYTES {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
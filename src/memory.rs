@@ -0,0 +1,183 @@
+//! Memory usage reporting for `--profile-memory`.
+//!
+//! Peak RSS is read from the operating system. Per-stage allocation statistics need the
+//! counting allocator, which is only installed when built with the `memory-profiling`
+//! feature so normal builds keep the system allocator untouched.
+
+use std::fs;
+use std::sync::Mutex;
+
+#[cfg(feature = "memory-profiling")]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    pub static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+    pub static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+    pub static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+    pub static PEAK_LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+    /// Wraps the system allocator and counts every allocation it serves.
+    pub struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc(layout) };
+            if !ptr.is_null() {
+                ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+                ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+                let live = LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+                PEAK_LIVE_BYTES.fetch_max(live, Ordering::Relaxed);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) };
+            LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+}
+
+/// Allocation counters captured at the start of a stage.
+#[derive(Clone, Copy)]
+pub struct StageStart {
+    #[cfg(feature = "memory-profiling")]
+    allocations: usize,
+    #[cfg(feature = "memory-profiling")]
+    allocated_bytes: usize,
+}
+
+/// Allocation totals for every run of one named stage.
+struct StageStats {
+    name: String,
+    runs: usize,
+    allocations: usize,
+    allocated_bytes: usize,
+    peak_live_bytes: usize,
+}
+
+/// Collects per-stage memory statistics over a run.
+///
+/// The allocation counters are process-wide, so stages running at the same time on
+/// different threads see each other's allocations.
+pub struct MemoryProfiler {
+    enabled: bool,
+    stages: Mutex<Vec<StageStats>>,
+}
+
+impl MemoryProfiler {
+    pub fn new(enabled: bool) -> Self {
+        MemoryProfiler {
+            enabled,
+            stages: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Marks the beginning of a stage; pass the result to `finish`.
+    pub fn start(&self) -> StageStart {
+        #[cfg(feature = "memory-profiling")]
+        {
+            use std::sync::atomic::Ordering;
+            counting::PEAK_LIVE_BYTES.store(
+                counting::LIVE_BYTES.load(Ordering::Relaxed),
+                Ordering::Relaxed,
+            );
+            StageStart {
+                allocations: counting::ALLOCATIONS.load(Ordering::Relaxed),
+                allocated_bytes: counting::ALLOCATED_BYTES.load(Ordering::Relaxed),
+            }
+        }
+        #[cfg(not(feature = "memory-profiling"))]
+        StageStart {}
+    }
+
+    /// Records the allocations made since `start` under the given stage name.
+    ///
+    /// Stages that run repeatedly (such as fetching each set) are accumulated, keeping the
+    /// highest peak seen.
+    pub fn finish(&self, name: &str, start: StageStart) {
+        if !self.enabled {
+            return;
+        }
+
+        #[cfg(feature = "memory-profiling")]
+        let (allocations, allocated_bytes, peak_live_bytes) = {
+            use std::sync::atomic::Ordering;
+            (
+                counting::ALLOCATIONS.load(Ordering::Relaxed) - start.allocations,
+                counting::ALLOCATED_BYTES.load(Ordering::Relaxed) - start.allocated_bytes,
+                counting::PEAK_LIVE_BYTES.load(Ordering::Relaxed),
+            )
+        };
+        #[cfg(not(feature = "memory-profiling"))]
+        let (allocations, allocated_bytes, peak_live_bytes) = {
+            let _ = start;
+            (0, 0, 0)
+        };
+
+        let mut stages = self.stages.lock().unwrap_or_else(|e| e.into_inner());
+        match stages.iter_mut().find(|stage| stage.name == name) {
+            Some(stage) => {
+                stage.runs += 1;
+                stage.allocations += allocations;
+                stage.allocated_bytes += allocated_bytes;
+                stage.peak_live_bytes = stage.peak_live_bytes.max(peak_live_bytes);
+            }
+            None => stages.push(StageStats {
+                name: name.to_string(),
+                runs: 1,
+                allocations,
+                allocated_bytes,
+                peak_live_bytes,
+            }),
+        }
+    }
+
+    /// Builds the end-of-run report, or `None` when profiling is disabled.
+    pub fn report(&self) -> Option<String> {
+        if !self.enabled {
+            return None;
+        }
+
+        let mut report = match peak_rss_bytes() {
+            Some(bytes) => format!("Peak RSS: {:.1} MiB\n", mib(bytes)),
+            None => String::from("Peak RSS: unavailable on this platform\n"),
+        };
+
+        if cfg!(feature = "memory-profiling") {
+            report.push_str("Allocations per stage:\n");
+            let stages = self.stages.lock().unwrap_or_else(|e| e.into_inner());
+            for stage in stages.iter() {
+                report.push_str(&format!(
+                    "- {} ({} run(s)): {} allocations, {:.1} MiB allocated, peak live {:.1} MiB\n",
+                    stage.name,
+                    stage.runs,
+                    stage.allocations,
+                    mib(stage.allocated_bytes as u64),
+                    mib(stage.peak_live_bytes as u64)
+                ));
+            }
+        } else {
+            report.push_str(
+                "Per-stage allocation stats need a build with `--features memory-profiling`.\n",
+            );
+        }
+        Some(report)
+    }
+}
+
+fn mib(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+/// Reads the process's peak resident set size from `/proc/self/status` (Linux only).
+fn peak_rss_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
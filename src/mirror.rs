@@ -0,0 +1,81 @@
+//! Official data besides the cards: sealed products, their contents and the legality list.
+//!
+//! Each endpoint is mirrored to `<endpoint>/<endpoint>.json` in the output directory, holding
+//! every page of its listing merged into one, the way set files hold every page of a set.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::api::FabClient;
+use crate::model::Page;
+use crate::storage;
+
+/// An API endpoint mirrored besides the card search.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Endpoint {
+    /// Sealed products: booster packs, boxes and decks
+    Products,
+    /// What every sealed product contains
+    Sealed,
+    /// The banned, restricted, suspended and Living Legend cards of every format
+    Legality,
+}
+
+impl Endpoint {
+    // Every endpoint, in the order they are mirrored
+    pub const ALL: [Endpoint; 3] = [Endpoint::Products, Endpoint::Sealed, Endpoint::Legality];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Endpoint::Products => "products",
+            Endpoint::Sealed => "sealed",
+            Endpoint::Legality => "legality",
+        }
+    }
+
+    /// Returns the path of the endpoint's file: `{base}/{endpoint}/{endpoint}.json`.
+    pub fn filename(self, base_output_dir: &str) -> String {
+        format!("{}/{}/{}.json", base_output_dir, self.name(), self.name())
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Downloads the whole listing of an endpoint and writes it into the output directory,
+/// replacing the previous copy.
+///
+/// # Arguments
+/// * `client` - The client to send the requests with.
+/// * `endpoint` - The endpoint to mirror.
+/// * `base_output_dir` - The root output directory.
+///
+/// # Returns
+/// A `Result` containing the path written and the number of entries, or an error if the
+/// listing could not be fetched or written.
+pub fn mirror(
+    client: &FabClient,
+    endpoint: Endpoint,
+    base_output_dir: &str,
+) -> Result<(String, usize), Box<dyn Error>> {
+    fs::create_dir_all(Path::new(base_output_dir).join(endpoint.name()))?;
+    let filename = endpoint.filename(base_output_dir);
+    let count = match endpoint {
+        Endpoint::Products => write_listing(&filename, &client.products()?)?,
+        Endpoint::Sealed => write_listing(&filename, &client.sealed_contents()?)?,
+        Endpoint::Legality => write_listing(&filename, &client.legality()?)?,
+    };
+    Ok((filename, count))
+}
+
+fn write_listing<T: Serialize>(filename: &str, page: &Page<T>) -> Result<usize, Box<dyn Error>> {
+    storage::write_atomically(Path::new(filename), serde_json::to_string_pretty(page)?.as_bytes())?;
+    Ok(page.results.len())
+}
@@ -0,0 +1,190 @@
+//! Typed representation of the cards.fabtcg.com search API responses.
+//!
+//! Only the fields the tool works with are modelled explicitly. Everything else the API
+//! returns is kept in the `extra` maps so re-serializing a response loses no data.
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+/// One page of a paginated API listing, or all pages once merged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    #[serde(default)]
+    pub count: Option<u64>,
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub previous: Option<String>,
+    pub results: Vec<T>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// The cards of a set, as returned by the card search API.
+pub type SetResponse = Page<Card>;
+
+/// A set known to the API, as listed by the sets endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetInfo {
+    #[serde(alias = "set_code")]
+    pub code: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, alias = "released", skip_serializing_if = "Option::is_none")]
+    pub release_date: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl SetInfo {
+    /// The day the set is released, from a release date such as `2025-01-31` or
+    /// `2025-01-31T00:00:00Z`.
+    pub fn release_day(&self) -> Option<NaiveDate> {
+        let date = self.release_date.as_deref()?.trim();
+        NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok()
+    }
+}
+
+/// A sealed product (booster pack, booster box, deck...), as listed by the products endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Product {
+    #[serde(alias = "id")]
+    pub product_id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub set_code: Option<String>,
+    #[serde(default, alias = "type", skip_serializing_if = "Option::is_none")]
+    pub product_type: Option<String>,
+    #[serde(default, alias = "released", skip_serializing_if = "Option::is_none")]
+    pub release_date: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// What a sealed product contains, as listed by the sealed endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedContents {
+    #[serde(alias = "id")]
+    pub product_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub contents: Vec<SealedItem>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// One slot of a sealed product: another product (e.g. the packs of a box) or cards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedItem {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub print_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rarity: Option<String>,
+    #[serde(default = "one")]
+    pub quantity: u32,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A card's entry on the banned-and-restricted and Living Legend list of a format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegalityEntry {
+    pub card_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub format: String,
+    /// e.g. "banned", "restricted", "suspended" or "living_legend"
+    pub status: String,
+    #[serde(default, alias = "effective", skip_serializing_if = "Option::is_none")]
+    pub effective_date: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A unique card, with every printing of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Card {
+    pub card_id: String,
+    pub name: String,
+    #[serde(default, deserialize_with = "deserialize_stat", skip_serializing_if = "Option::is_none")]
+    pub pitch: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_stat", skip_serializing_if = "Option::is_none")]
+    pub cost: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_stat", skip_serializing_if = "Option::is_none")]
+    pub power: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_stat", skip_serializing_if = "Option::is_none")]
+    pub defense: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_stat", skip_serializing_if = "Option::is_none")]
+    pub health: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_stat", skip_serializing_if = "Option::is_none")]
+    pub intelligence: Option<String>,
+    #[serde(default)]
+    pub types: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub type_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub printings: Vec<Printing>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A single printing of a card in a given set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Printing {
+    pub print_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub set_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rarity: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foiling: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edition: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    #[serde(default)]
+    pub artists: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Printing {
+    /// The collector number of the printing: the digits ending its print id (e.g. "001" for
+    /// WTR001), if there are any.
+    pub fn collector_number(&self) -> Option<&str> {
+        let start = self.print_id.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+        Some(&self.print_id[start..]).filter(|number| !number.is_empty())
+    }
+}
+
+fn one() -> u32 {
+    1
+}
+
+/// Accepts a card stat given as a string, a number or null.
+///
+/// Stats are kept as strings because some are not numeric (e.g. "X" or "*"); empty strings
+/// are treated as the stat being absent.
+fn deserialize_stat<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::Null => Ok(None),
+        Value::String(s) if s.trim().is_empty() => Ok(None),
+        Value::String(s) => Ok(Some(s)),
+        Value::Number(n) => Ok(Some(n.to_string())),
+        other => Err(serde::de::Error::custom(format!(
+            "expected a string or number for a card stat, found {}",
+            other
+        ))),
+    }
+}
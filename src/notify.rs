@@ -0,0 +1,207 @@
+//! Webhook notifications of what a fetch changed: new sets, new cards and printings, errata and
+//! removals.
+//!
+//! A generic webhook receives one JSON POST per run that changed anything:
+//!
+//! ```json
+//! {"event": "cards_changed", "generated_at": "...", "summary": "MST (new set): 3 new card(s)",
+//!  "new_sets": ["MST"], "sets": [...]}
+//! ```
+//!
+//! where `sets` holds the changes of every changed set, laid out like the `changelog.json` of
+//! `fab-scrapper diff`. A Discord webhook receives the same changes as chat messages, split to
+//! stay within Discord's limit on the length of a message.
+
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+
+use crate::diff::{self, Changelog, SetChanges};
+
+// Most characters Discord accepts in the content of a message
+const DISCORD_MESSAGE_LIMIT: usize = 2000;
+
+// Longest wait honoured when a webhook answers 429 Too Many Requests, in seconds
+const MAX_RETRY_AFTER_SECS: u64 = 10;
+
+// Name the Discord messages are posted under
+const DISCORD_USERNAME: &str = "fab-scrapper";
+
+/// The payload a webhook expects.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum WebhookKind {
+    /// The changelog as JSON
+    Json,
+    /// Discord-compatible chat messages
+    Discord,
+}
+
+/// A URL notifications are posted to.
+#[derive(Clone)]
+pub struct Webhook {
+    pub url: String,
+    pub kind: WebhookKind,
+}
+
+impl Webhook {
+    /// The webhook's URL without its path, which often holds the webhook's secret token.
+    pub fn describe(&self) -> String {
+        match reqwest::Url::parse(&self.url) {
+            Ok(url) => format!("{}/...", url.origin().ascii_serialization()),
+            Err(_) => String::from("(invalid URL)"),
+        }
+    }
+}
+
+/// Posts the changes of a run to webhooks.
+pub struct Notifier {
+    http: reqwest::blocking::Client,
+    webhooks: Vec<Webhook>,
+}
+
+impl Notifier {
+    pub fn new(http: reqwest::blocking::Client, webhooks: Vec<Webhook>) -> Self {
+        Notifier { http, webhooks }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.webhooks.is_empty()
+    }
+
+    /// The number of webhooks notified.
+    pub fn len(&self) -> usize {
+        self.webhooks.len()
+    }
+
+    /// Posts a changelog to every webhook, unless no set changed.
+    ///
+    /// # Arguments
+    /// * `changelog` - What the run changed.
+    ///
+    /// # Returns
+    /// One message per webhook that could not be notified.
+    pub fn notify(&self, changelog: &Changelog) -> Vec<String> {
+        if changed_sets(changelog).is_empty() {
+            return Vec::new();
+        }
+        let mut failures = Vec::new();
+        for webhook in &self.webhooks {
+            let payloads = match webhook.kind {
+                WebhookKind::Json => vec![json_payload(changelog)],
+                WebhookKind::Discord => discord_payloads(changelog),
+            };
+            for payload in payloads {
+                if let Err(e) = self.post(&webhook.url, &payload) {
+                    failures.push(format!("Could not notify {}: {}", webhook.describe(), e));
+                    break;
+                }
+            }
+        }
+        failures
+    }
+
+    /// Posts one payload, waiting and trying once more if the webhook is rate limited.
+    fn post(&self, url: &str, payload: &Value) -> Result<(), Box<dyn Error>> {
+        let mut response = self.http.post(url).json(payload).send()?;
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<f64>().ok())
+                .unwrap_or(1.0);
+            let wait = retry_after.clamp(0.0, MAX_RETRY_AFTER_SECS as f64);
+            thread::sleep(Duration::from_secs_f64(wait));
+            response = self.http.post(url).json(payload).send()?;
+        }
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("the webhook answered {}", status).into());
+        }
+        Ok(())
+    }
+}
+
+/// A line per changed set counting its changes, e.g. `WTR: 2 new card(s), 1 changed card(s)`.
+pub fn summary(changelog: &Changelog) -> String {
+    let lines: Vec<String> = changed_sets(changelog)
+        .into_iter()
+        .map(|set| {
+            let mut parts = Vec::new();
+            let mut count = |count: usize, what: &str| {
+                if count > 0 {
+                    parts.push(format!("{} {}", count, what));
+                }
+            };
+            count(set.added.len(), "new card(s)");
+            count(set.new_printings.len(), "card(s) with new printings");
+            count(set.changed.len(), "changed card(s)");
+            count(set.removed.len(), "removed card(s)");
+            let new_set = if set.new_set { " (new set)" } else { "" };
+            format!("{}{}: {}", set.set_code, new_set, parts.join(", "))
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// The changed sets of a changelog.
+fn changed_sets(changelog: &Changelog) -> Vec<&SetChanges> {
+    changelog.sets.iter().filter(|set| !set.is_empty()).collect()
+}
+
+/// The payload of a generic webhook.
+pub fn json_payload(changelog: &Changelog) -> Value {
+    let sets = changed_sets(changelog);
+    let new_sets: Vec<&str> =
+        sets.iter().filter(|set| set.new_set).map(|set| set.set_code.as_str()).collect();
+    json!({
+        "event": "cards_changed",
+        "generated_at": changelog.generated_at,
+        "summary": summary(changelog),
+        "new_sets": new_sets,
+        "sets": sets,
+    })
+}
+
+/// The messages of a Discord webhook: a heading with the summary, then the changes of every set
+/// in code blocks, split into as many messages as Discord's length limit needs.
+pub fn discord_payloads(changelog: &Changelog) -> Vec<Value> {
+    let sets = changed_sets(changelog);
+    let heading =
+        format!("**Card data changed in {} set(s)**\n{}", sets.len(), summary(changelog));
+    let mut messages = vec![truncate(&heading, DISCORD_MESSAGE_LIMIT)];
+
+    // Each chunk is wrapped in a code block, which keeps the indentation of the changelog
+    const FENCE: &str = "```";
+    let limit = DISCORD_MESSAGE_LIMIT - 2 * FENCE.len() - 2;
+    let mut chunk = String::new();
+    for line in diff::render_text(changelog).lines() {
+        let line = truncate(line, limit - 1);
+        if !chunk.is_empty() && chunk.chars().count() + line.chars().count() + 1 > limit {
+            messages.push(format!("{}\n{}\n{}", FENCE, chunk.trim_end(), FENCE));
+            chunk.clear();
+        }
+        chunk.push_str(&line);
+        chunk.push('\n');
+    }
+    if !chunk.trim().is_empty() {
+        messages.push(format!("{}\n{}\n{}", FENCE, chunk.trim_end(), FENCE));
+    }
+    messages
+        .into_iter()
+        .map(|content| json!({ "username": DISCORD_USERNAME, "content": content }))
+        .collect()
+}
+
+/// Cuts a text to at most `limit` characters, ending it with an ellipsis when it is cut.
+fn truncate(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(limit.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
@@ -0,0 +1,595 @@
+//! The fetch pipeline: worker threads download sets while a single writer saves them.
+//!
+//! Sets are handed out to the workers in order, and every downloaded set goes through a
+//! bounded write queue to the writer, which saves it in every requested format and verifies
+//! the written files. When the disk is slower than the network the workers wait on the full
+//! queue instead of piling downloaded sets up in memory.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use tracing::{debug, info, info_span, warn};
+use fab_scrapper::FabClient;
+use fab_scrapper::api::SetTiming;
+use fab_scrapper::diff::{self, SetChanges};
+use fab_scrapper::events::{Event, EventLog};
+use fab_scrapper::model::{Card, SetResponse};
+use fab_scrapper::policy::{FailurePolicy, FailureStage};
+use fab_scrapper::prices::{self, PriceClient};
+use fab_scrapper::promo;
+use fab_scrapper::reminder::{self, ReminderText};
+use fab_scrapper::storage::compression;
+use fab_scrapper::storage::manifest::{self, CorruptEntry, SetEntry};
+use fab_scrapper::storage::run_state::RunState;
+use fab_scrapper::storage::snapshot;
+use fab_scrapper::storage::{self, OutputFormat};
+use fab_scrapper::template::CardTemplate;
+
+use crate::cli::OutputArgs;
+use crate::logging::DownloadProgress;
+use crate::memory::MemoryProfiler;
+
+// Number of times a set is re-fetched when its written files fail validation
+const MAX_CORRUPT_REFETCHES: u32 = 2;
+
+/// What happened to a single set during a fetch run.
+pub enum SetOutcome {
+    /// The set was saved in at least one format; holds its JSON for the combined file.
+    Saved(String),
+    /// The set has not changed since the last run and its files were left untouched;
+    /// holds its JSON for the combined file.
+    Unchanged(String),
+    /// The saved files stayed corrupt after every re-fetch, or the set is unchanged since they
+    /// did on an earlier run; holds the record to keep in the manifest, if it is a new one.
+    Corrupt(Option<CorruptEntry>),
+    /// The set could not be fetched or saved.
+    Failed,
+}
+
+/// The outcome and timing breakdown of one processed set.
+pub struct ProcessedSet {
+    pub outcome: SetOutcome,
+    pub timing: SetTiming,
+    /// The manifest entry to record for the set, if it changed.
+    pub manifest_entry: Option<SetEntry>,
+    /// What went wrong, for the failure policy to decide on.
+    pub failures: Vec<(FailureStage, String)>,
+    /// What changed since the set's previous output, if the set was written.
+    pub changes: Option<SetChanges>,
+}
+
+/// What every set of a fetch run shares.
+pub struct FetchContext<'a> {
+    /// The API client to send the requests with.
+    pub client: &'a FabClient,
+    /// The output directory and formats of the run.
+    pub output: &'a OutputArgs,
+    /// Whether unchanged sets should be skipped.
+    pub incremental: bool,
+    /// What to do with the reminder text of every card.
+    pub reminder_text: ReminderText,
+    /// Where to take the prices added to every printing, with `--prices-storage inline`.
+    pub inline_prices: Option<&'a PriceClient>,
+    /// Records allocations of each stage when profiling is enabled.
+    pub memory_profiler: &'a MemoryProfiler,
+    /// Receives every change made to the dataset.
+    pub events: &'a EventLog,
+    /// The template of every card in the `txt` files.
+    pub txt_template: &'a CardTemplate,
+    /// Sets whose files stayed corrupt on an earlier run, which are not written again while
+    /// their data is the same.
+    pub corrupt_sets: &'a BTreeMap<String, CorruptEntry>,
+}
+
+/// Records which cards were added to or removed from a set since its previous output.
+///
+/// # Arguments
+/// * `events` - The event log to record to.
+/// * `set_code` - The set code (e.g., "WTR").
+/// * `previous` - The cards of the set's previous output; empty if it had none.
+/// * `set` - The set's newly fetched cards.
+fn record_card_changes(events: &EventLog, set_code: &str, previous: &[Card], set: &SetResponse) {
+    let previous_ids: HashSet<&str> = previous.iter().map(|card| card.card_id.as_str()).collect();
+    let current_ids: HashSet<&str> = set.results.iter().map(|card| card.card_id.as_str()).collect();
+
+    for card in set.results.iter().filter(|card| !previous_ids.contains(card.card_id.as_str())) {
+        events.record(Event::CardAdded { set_code, card_id: &card.card_id, name: &card.name });
+    }
+    for card in previous.iter().filter(|card| !current_ids.contains(card.card_id.as_str())) {
+        events.record(Event::CardRemoved { set_code, card_id: &card.card_id });
+    }
+}
+
+/// A set downloaded by a fetch worker, waiting in the write queue to be saved.
+struct FetchedSet {
+    set_code: String,
+    json_content: String,
+    page: SetResponse,
+    manifest_entry: SetEntry,
+    timing: SetTiming,
+    failures: Vec<(FailureStage, String)>,
+    /// Number of re-fetches so far after the written files turned out corrupt.
+    attempt: u32,
+}
+
+/// What downloading a set led to.
+enum Downloaded {
+    /// The set changed and has to be written.
+    Fetched(FetchedSet),
+    /// The set is done without writing anything: it is unchanged or could not be fetched.
+    Done(ProcessedSet),
+}
+
+/// Time the two halves of the fetch pipeline spent waiting for each other.
+#[derive(Default)]
+pub struct PipelineWaits {
+    /// Time fetch workers were blocked on a full write queue.
+    disk: Duration,
+    /// Time the writer was idle on an empty write queue.
+    network: Duration,
+}
+
+impl PipelineWaits {
+    /// A line telling whether the run was held up by the disk or by the network.
+    pub fn describe(&self) -> String {
+        let bound = if self.disk > self.network { "disk" } else { "network" };
+        format!(
+            "Pipeline: {}-bound (fetches waited {:.2}s for the disk, \
+            writes waited {:.2}s for the network)\n",
+            bound,
+            self.disk.as_secs_f64(),
+            self.network.as_secs_f64()
+        )
+    }
+}
+
+/// Downloads a single set, leaving the writing to `write_set`.
+///
+/// In incremental mode a set whose previous outputs still exist is requested conditionally
+/// and left untouched when the API reports it as not modified or its content hash matches
+/// the manifest.
+///
+/// # Arguments
+/// * `set_code` - The set code (e.g., "WTR").
+/// * `previous` - What the manifest recorded for the set on the previous run.
+/// * `conditional` - Whether the request may be conditional; re-fetches never are.
+/// * `timing` - The timings of the set so far.
+/// * `context` - The client, output settings and logs shared by the run.
+///
+/// # Returns
+/// The downloaded set, or its final outcome if there is nothing to write.
+fn download_set(
+    set_code: &str,
+    previous: Option<&SetEntry>,
+    conditional: bool,
+    mut timing: SetTiming,
+    context: &FetchContext,
+) -> Downloaded {
+    let FetchContext {
+        client,
+        output,
+        incremental,
+        reminder_text,
+        inline_prices,
+        memory_profiler,
+        events,
+        corrupt_sets,
+        ..
+    } = *context;
+    let _span = info_span!("set", set_code).entered();
+    info!("Processing set: {}", set_code);
+
+    let previous = previous.filter(|_| incremental && outputs_exist(output, set_code));
+    let mut conditional =
+        previous.map(|entry| &entry.validators).filter(|v| conditional && !v.is_empty());
+
+    loop {
+        let fetch_stage = memory_profiler.start();
+        let mut failures = Vec::new();
+        let fetched = client.fetch_set(set_code, conditional.take(), &mut timing)
+            .and_then(|listing| {
+                Ok(match listing {
+                    Some(mut listing) => {
+                        reminder::apply(reminder_text, &mut listing.page);
+                        promo::apply(&mut listing.page);
+                        if let Some(prices) = inline_prices {
+                            match prices.set_prices(set_code) {
+                                Ok(snapshot) => {
+                                    let priced = prices::apply(&mut listing.page, &snapshot);
+                                    debug!("Added prices to {} printing(s).", priced);
+                                }
+                                Err(e) => failures.push((
+                                    FailureStage::Prices,
+                                    format!("Could not fetch prices of set {}: {}", set_code, e),
+                                )),
+                            }
+                        }
+                        Some((serde_json::to_string(&listing.page)?, listing))
+                    }
+                    None => None,
+                })
+            });
+        memory_profiler.finish("fetch", fetch_stage);
+        let (json_content, listing) = match fetched {
+            Ok(Some(fetched)) => fetched,
+            Ok(None) => match storage::read_existing_set(&output.out, set_code) {
+                Ok(json_content) => {
+                    events.record(Event::SetUnchanged { set_code });
+                    return Downloaded::Done(ProcessedSet {
+                        outcome: SetOutcome::Unchanged(json_content),
+                        timing,
+                        manifest_entry: None,
+                        failures: Vec::new(),
+                        changes: None,
+                    });
+                }
+                Err(e) => {
+                    warn!(
+                        "Could not read previous output of set {} ({}). Fetching it again...",
+                        set_code, e
+                    );
+                    continue;
+                }
+            },
+            Err(e) => {
+                return Downloaded::Done(ProcessedSet {
+                    outcome: SetOutcome::Failed,
+                    timing,
+                    manifest_entry: None,
+                    failures: vec![(
+                        FailureStage::Fetch,
+                        format!("Could not fetch JSON data for set {}: {}", set_code, e),
+                    )],
+                    changes: None,
+                });
+            }
+        };
+
+        let manifest_entry = SetEntry {
+            validators: listing.validators,
+            sha256: manifest::sha256_hex(json_content.as_bytes()),
+            card_count: listing.page.results.len(),
+            pages: Some(listing.pages),
+            fetched_at: Local::now().to_rfc3339(),
+            preview_until: None,
+        };
+        if let Some(corrupt) = corrupt_sets.get(set_code)
+            && corrupt.source == manifest_entry.sha256
+        {
+            let message = format!(
+                "Set {} is unchanged since its files stayed corrupt on {}; skipping it. Remove it \
+                from corrupt_sets in the manifest to retry.",
+                set_code, corrupt.detected_at
+            );
+            return Downloaded::Done(ProcessedSet {
+                outcome: SetOutcome::Corrupt(None),
+                timing,
+                manifest_entry: None,
+                failures: vec![(FailureStage::Verify, message)],
+                changes: None,
+            });
+        }
+        if previous.is_some_and(|entry| entry.sha256 == manifest_entry.sha256) {
+            info!("Set {} is unchanged since the last run; keeping existing files.", set_code);
+            events.record(Event::SetUnchanged { set_code });
+            return Downloaded::Done(ProcessedSet {
+                outcome: SetOutcome::Unchanged(json_content),
+                timing,
+                manifest_entry: Some(manifest_entry),
+                failures,
+                changes: None,
+            });
+        }
+        return Downloaded::Fetched(FetchedSet {
+            set_code: set_code.to_string(),
+            json_content,
+            page: listing.page,
+            manifest_entry,
+            timing,
+            failures,
+            attempt: 0,
+        });
+    }
+}
+
+/// Saves a downloaded set in every requested format and verifies the written files,
+/// re-fetching the set when they turn out corrupt.
+///
+/// # Arguments
+/// * `fetched` - The downloaded set.
+/// * `previous` - What the manifest recorded for the set on the previous run.
+/// * `context` - The client, output settings and logs shared by the run.
+///
+/// # Returns
+/// The outcome of the set together with its timings.
+fn write_set(
+    mut fetched: FetchedSet,
+    previous: Option<&SetEntry>,
+    context: &FetchContext,
+) -> ProcessedSet {
+    let FetchContext { output, memory_profiler, events, txt_template, .. } = *context;
+
+    // Read the previous output once, before any attempt overwrites it
+    let previous_json = storage::read_existing_set(&output.out, &fetched.set_code).ok();
+    let previous_set = previous_json
+        .as_deref()
+        .and_then(|json| serde_json::from_str::<SetResponse>(json).ok());
+    loop {
+        let FetchedSet {
+            set_code,
+            json_content,
+            page,
+            manifest_entry,
+            mut timing,
+            mut failures,
+            attempt,
+        } = fetched;
+        let set_code = set_code.as_str();
+        let _span = info_span!("set", set_code).entered();
+        let write_start = Instant::now();
+        let write_stage = memory_profiler.start();
+
+        // Compare with the previous output before it is overwritten
+        if events.is_enabled() {
+            events.record(Event::SetFetched {
+                set_code,
+                card_count: manifest_entry.card_count,
+                sha256: &manifest_entry.sha256,
+            });
+            let previous_cards = previous_set.as_ref().map(|set| set.results.as_slice());
+            record_card_changes(events, set_code, previous_cards.unwrap_or_default(), &page);
+        }
+        let changes = match &previous_set {
+            Some(previous) => diff::compare_set(set_code, previous, &page),
+            None => {
+                let empty = SetResponse {
+                    count: None,
+                    next: None,
+                    previous: None,
+                    results: Vec::new(),
+                    extra: Default::default(),
+                };
+                diff::compare_set(set_code, &empty, &page)
+            }
+        };
+
+        // Keep the version being replaced for `fab-scrapper diff`
+        if output.formats.iter().any(|format| format.per_set_files() && format.holds_json())
+            && previous_json.as_deref() != Some(json_content.as_str())
+            && let Err(e) = snapshot::record(&output.out, set_code, previous_json.as_deref())
+        {
+            warn!("Could not keep the previous version of set {}: {}", set_code, e);
+        }
+
+        // Save one file per requested format; the others are written with the combined files
+        let mut saved_files = Vec::new();
+        let per_set_formats: Vec<OutputFormat> =
+            output.formats.iter().copied().filter(|format| format.per_set_files()).collect();
+        for format in &per_set_formats {
+            let filename = storage::set_filename(&output.out, *format, set_code);
+            let saved = storage::render_set(*format, set_code, &json_content, &page, txt_template)
+                .and_then(|content| {
+                    compression::write(&filename, content.as_bytes(), output.compress)
+                });
+            match saved {
+                Err(e) => {
+                    let message = format!("Could not save {} file {}: {}", format, filename, e);
+                    failures.push((FailureStage::Write, message));
+                }
+                Ok(written) => {
+                    info!("Successfully saved {}", written);
+                    events.record(Event::FileWritten { path: &written });
+                    saved_files.push((*format, written));
+                }
+            }
+        }
+
+        // Re-read what was written to catch truncated or corrupt downloads
+        let mut corruption = None;
+        for (_, filename) in saved_files.iter().filter(|(format, _)| format.holds_json()) {
+            if let Err(e) = storage::verify_json_file(filename, &manifest_entry.sha256) {
+                corruption = Some(format!("{}: {}", filename, e));
+            }
+        }
+        timing.write += write_start.elapsed();
+        memory_profiler.finish("write", write_stage);
+
+        let outcome = match corruption {
+            Some(reason) if attempt < MAX_CORRUPT_REFETCHES => {
+                warn!(
+                    "Corrupt download for set {} ({}). Re-fetching (attempt {} of {})...",
+                    set_code,
+                    reason,
+                    attempt + 1,
+                    MAX_CORRUPT_REFETCHES
+                );
+                match download_set(set_code, previous, false, timing, context) {
+                    Downloaded::Fetched(refetched) => {
+                        fetched = FetchedSet { attempt: attempt + 1, ..refetched };
+                        continue;
+                    }
+                    Downloaded::Done(processed) => return processed,
+                }
+            }
+            Some(reason) => {
+                let message = format!(
+                    "Set {} is still corrupt after {} re-fetches ({}); skipping it",
+                    set_code, MAX_CORRUPT_REFETCHES, reason
+                );
+                failures.push((FailureStage::Verify, message));
+                let corrupt = CorruptEntry {
+                    source: manifest_entry.sha256,
+                    reason,
+                    detected_at: Local::now().to_rfc3339(),
+                };
+                return ProcessedSet {
+                    outcome: SetOutcome::Corrupt(Some(corrupt)),
+                    timing,
+                    manifest_entry: None,
+                    failures,
+                    changes: None,
+                };
+            }
+            // Store the data for the combined file if at least one save was successful
+            None if !saved_files.is_empty() || per_set_formats.is_empty() => {
+                SetOutcome::Saved(json_content)
+            }
+            None => SetOutcome::Failed,
+        };
+        let saved = matches!(outcome, SetOutcome::Saved(_));
+        let manifest_entry = saved.then_some(manifest_entry);
+        let changes = saved.then_some(changes);
+        return ProcessedSet { outcome, timing, manifest_entry, failures, changes };
+    }
+}
+
+/// Whether every output of a set from the previous run is still there, compressed the way this
+/// run asks for, which makes skipping the set safe.
+pub fn outputs_exist(output: &OutputArgs, set_code: &str) -> bool {
+    output.formats.iter().all(|format| {
+        let filename = if format.per_set_files() {
+            storage::set_filename(&output.out, *format, set_code)
+        } else {
+            storage::combined_filename(&output.out, *format)
+        };
+        let compress = output.compress.filter(|_| format.compressible());
+        Path::new(&compression::compressed_name(&filename, compress)).exists()
+    })
+}
+
+/// How the sets of a fetch run are downloaded and written.
+pub struct Pipeline<'a> {
+    /// Number of sets downloaded at the same time.
+    pub concurrency: usize,
+    /// Number of downloaded sets that may wait to be written.
+    pub write_queue: usize,
+    /// Whether a set that could not be saved stops the workers from starting further sets.
+    pub fail_fast: bool,
+    /// Decides which failures stop the workers from starting further sets.
+    pub policy: &'a FailurePolicy,
+    /// Shows the downloads and the write queue.
+    pub progress: &'a DownloadProgress,
+    /// The output directory the run state is saved in.
+    pub out: &'a str,
+}
+
+/// What the pipeline did with the sets of a run.
+pub struct PipelineResults {
+    /// Every set in the order it was given; `None` for sets the workers never started.
+    pub processed: Vec<Option<ProcessedSet>>,
+    /// Time the workers and the writer spent waiting for each other.
+    pub waits: PipelineWaits,
+}
+
+impl Pipeline<'_> {
+    /// Downloads and writes sets, marking every set saved or unchanged as completed in the
+    /// run state.
+    ///
+    /// A fail-closed failure, or with `fail_fast` any set that could not be saved, stops the
+    /// workers from starting any further sets.
+    ///
+    /// # Arguments
+    /// * `to_fetch` - The set codes to fetch, in order.
+    /// * `previous` - What the manifest recorded for every set on the previous run.
+    /// * `context_of` - The client, output settings and logs of a set.
+    /// * `run_state` - The run state to record completed sets in.
+    ///
+    /// # Returns
+    /// The outcome of every set, and how long both halves of the pipeline waited.
+    pub fn run<'c>(
+        &self,
+        to_fetch: &[String],
+        previous: &BTreeMap<String, SetEntry>,
+        context_of: impl Fn(&str) -> &'c FetchContext<'c> + Sync,
+        run_state: &mut RunState,
+    ) -> PipelineResults {
+        let Pipeline { concurrency, write_queue, fail_fast, policy, progress, out } = *self;
+        let next_set = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        let run_state = Mutex::new(run_state);
+        let results: Mutex<Vec<Option<ProcessedSet>>> =
+            Mutex::new(to_fetch.iter().map(|_| None).collect());
+        let workers = concurrency.clamp(1, to_fetch.len().max(1));
+        let bar = progress.start(to_fetch.len() as u64);
+        let finish = |index: usize, processed: ProcessedSet| {
+            let completed =
+                matches!(processed.outcome, SetOutcome::Saved(_) | SetOutcome::Unchanged(_));
+            if processed.failures.iter().any(|(stage, _)| policy.is_fail_closed(*stage))
+                || (fail_fast && !completed)
+            {
+                stop.store(true, Ordering::Relaxed);
+            }
+            if completed {
+                let mut state = run_state.lock().unwrap_or_else(|e| e.into_inner());
+                state.completed.insert(processed.timing.set_code.clone());
+                if let Err(e) = state.save(out) {
+                    warn!("Could not save run state in {}: {}", out, e);
+                }
+            }
+            bar.inc(1);
+            results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(processed);
+        };
+
+        let write_queue = write_queue.max(1);
+        let queued = AtomicUsize::new(0);
+        let waits = Mutex::new(PipelineWaits::default());
+        let (sender, receiver) = mpsc::sync_channel::<(usize, FetchedSet)>(write_queue);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let receiver = receiver;
+                loop {
+                    let wait_start = Instant::now();
+                    let Ok((index, fetched)) = receiver.recv() else {
+                        break;
+                    };
+                    waits.lock().unwrap_or_else(|e| e.into_inner()).network +=
+                        wait_start.elapsed();
+                    let in_queue = queued.fetch_sub(1, Ordering::Relaxed) - 1;
+                    progress.write_queue(in_queue, write_queue);
+                    let previous = previous.get(&fetched.set_code);
+                    let context = context_of(&fetched.set_code);
+                    finish(index, write_set(fetched, previous, context));
+                }
+            });
+            for _ in 0..workers {
+                let sender = sender.clone();
+                scope.spawn(|| {
+                    let sender = sender;
+                    while !stop.load(Ordering::Relaxed) {
+                        let index = next_set.fetch_add(1, Ordering::Relaxed);
+                        let Some(set_code) = to_fetch.get(index) else {
+                            break;
+                        };
+                        let previous = previous.get(set_code);
+                        let timing = SetTiming::new(set_code);
+                        let context = context_of(set_code);
+                        match download_set(set_code, previous, true, timing, context) {
+                            Downloaded::Done(processed) => finish(index, processed),
+                            Downloaded::Fetched(fetched) => {
+                                let in_queue = queued.fetch_add(1, Ordering::Relaxed) + 1;
+                                progress.write_queue(in_queue.min(write_queue), write_queue);
+                                let wait_start = Instant::now();
+                                if sender.send((index, fetched)).is_err() {
+                                    break;
+                                }
+                                waits.lock().unwrap_or_else(|e| e.into_inner()).disk +=
+                                    wait_start.elapsed();
+                            }
+                        }
+                    }
+                });
+            }
+            drop(sender);
+        });
+        bar.finish_and_clear();
+
+        PipelineResults {
+            processed: results.into_inner().unwrap_or_else(|e| e.into_inner()),
+            waits: waits.into_inner().unwrap_or_else(|e| e.into_inner()),
+        }
+    }
+}
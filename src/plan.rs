@@ -0,0 +1,150 @@
+//! Dry-run plans of a fetch: what would be requested for every set, and roughly how much
+//! would be downloaded.
+//!
+//! Plans are worked out from what earlier runs left in the output directory — the manifest,
+//! the HTTP cache and the files on disk — without requesting any card data. Page counts come
+//! from the cache, or else from the last time a set was downloaded; sets never fetched into
+//! the directory have unknown sizes.
+
+use crate::api::CachedPages;
+use crate::storage::manifest::SetEntry;
+
+/// What a fetch would do about a set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlannedAction {
+    /// Saved by the interrupted run being resumed, so not requested again
+    Resumed,
+    /// Every page is fresh in the HTTP cache, so no request is sent
+    Cached,
+    /// Requested conditionally, and only downloaded if the API reports a change
+    Revalidate,
+    /// Downloaded again in full
+    Refetch,
+    /// Never fetched into the output directory before
+    New,
+}
+
+impl PlannedAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            PlannedAction::Resumed => "resumed",
+            PlannedAction::Cached => "cached",
+            PlannedAction::Revalidate => "revalidate",
+            PlannedAction::Refetch => "refetch",
+            PlannedAction::New => "new",
+        }
+    }
+}
+
+impl std::fmt::Display for PlannedAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What is known about a set before a fetch.
+pub struct SetState<'a> {
+    pub set_code: &'a str,
+    /// Whether the interrupted run being resumed already saved it.
+    pub resumed: bool,
+    /// Its manifest entry, if it was fetched into the output directory before.
+    pub previous: Option<&'a SetEntry>,
+    /// Whether it would be requested conditionally, as with `--incremental`.
+    pub conditional: bool,
+    /// What the HTTP cache holds of it.
+    pub cached: Option<CachedPages>,
+    /// Size of its JSON data from the last fetch, in bytes.
+    pub previous_size: Option<u64>,
+    /// Card images of it not on disk yet, with `--images`, when its cards are known.
+    pub missing_images: Option<usize>,
+}
+
+/// What a fetch would do about a set, and what it would cost.
+#[derive(Debug, Clone)]
+pub struct SetPlan {
+    pub set_code: String,
+    pub action: PlannedAction,
+    /// Pages of the set's listing, when known.
+    pub pages: Option<usize>,
+    /// The fewest requests the set can take.
+    pub min_requests: usize,
+    /// The most requests the set can take, when its page count is known.
+    pub max_requests: Option<usize>,
+    /// The most card data downloaded for the set, in bytes, when known.
+    pub download_bytes: Option<u64>,
+    /// Card images to download with `--images`, when known.
+    pub missing_images: Option<usize>,
+}
+
+impl SetPlan {
+    /// Plans a set from what is known about it.
+    pub fn new(state: SetState) -> Self {
+        let complete = state.cached.as_ref().filter(|cached| cached.complete);
+        let pages = complete
+            .map(|cached| cached.pages)
+            .or(state.previous.and_then(|entry| entry.pages));
+        let fresh = state.cached.as_ref().map_or(0, |cached| cached.fresh);
+        // Pages that are not fresh in the cache need a request each
+        let to_request = pages.map(|pages| pages.saturating_sub(fresh));
+        let (action, min_requests, max_requests) = if state.resumed {
+            (PlannedAction::Resumed, 0, Some(0))
+        } else if state.conditional {
+            // The first page is always asked for, the others only if it changed
+            (PlannedAction::Revalidate, 1, to_request.map(|requests| requests.max(1)))
+        } else if to_request == Some(0) && complete.is_some() {
+            (PlannedAction::Cached, 0, Some(0))
+        } else {
+            let action =
+                if state.previous.is_some() { PlannedAction::Refetch } else { PlannedAction::New };
+            (action, to_request.unwrap_or(1).max(1), to_request.map(|requests| requests.max(1)))
+        };
+        let download_bytes = match max_requests {
+            Some(0) => Some(0),
+            _ => state.previous_size.or(complete.map(|cached| cached.bytes)),
+        };
+        SetPlan {
+            set_code: state.set_code.to_string(),
+            action,
+            pages,
+            min_requests,
+            max_requests,
+            download_bytes,
+            missing_images: state.missing_images,
+        }
+    }
+}
+
+/// The plan of a whole fetch.
+#[derive(Debug, Clone, Default)]
+pub struct FetchPlan {
+    pub sets: Vec<SetPlan>,
+    /// Average size of the images already downloaded, to estimate the missing ones with.
+    pub average_image_size: Option<u64>,
+}
+
+impl FetchPlan {
+    /// The fewest requests for card data the fetch can take.
+    pub fn min_requests(&self) -> usize {
+        self.sets.iter().map(|set| set.min_requests).sum()
+    }
+
+    /// The most requests for card data the fetch can take, unless a set's size is unknown.
+    pub fn max_requests(&self) -> Option<usize> {
+        self.sets.iter().map(|set| set.max_requests).sum()
+    }
+
+    /// The most card data downloaded by the sets whose size is known, in bytes.
+    pub fn download_bytes(&self) -> u64 {
+        self.sets.iter().filter_map(|set| set.download_bytes).sum()
+    }
+
+    /// The number of sets that may download card data of an unknown size.
+    pub fn unknown_sizes(&self) -> usize {
+        self.sets.iter().filter(|set| set.download_bytes.is_none()).count()
+    }
+
+    /// The number of card images to download, among the sets whose cards are known.
+    pub fn missing_images(&self) -> usize {
+        self.sets.iter().filter_map(|set| set.missing_images).sum()
+    }
+}
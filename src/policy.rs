@@ -0,0 +1,111 @@
+//! Whether failures of each stage of a run are fatal or only reported.
+//!
+//! Every stage is fail-open by default: its failures are printed as warnings and the run
+//! carries on with what it has. Fail-closed stages turn their failures into an error that
+//! stops the run before any combined output is published.
+
+use std::error::Error;
+use std::sync::Mutex;
+
+use tracing::warn;
+
+/// A step of a run whose failures can be made fatal.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum FailureStage {
+    /// A set could not be downloaded or parsed
+    Fetch,
+    /// A set's output file could not be saved
+    Write,
+    /// A set's saved files stayed corrupt after every re-fetch
+    Verify,
+    /// A set's cards break the invariants checked by `validate`
+    Validate,
+    /// A combined file could not be written, or a set's data was invalid and left out of it
+    Combine,
+    /// Card images could not be downloaded
+    Images,
+    /// Prices could not be fetched or saved
+    Prices,
+    /// A deck-builder or spreadsheet export could not be written
+    Export,
+    /// The manifest could not be saved
+    Manifest,
+    /// The run archive could not be written
+    Archive,
+    /// The metadata file could not be saved
+    Metadata,
+    /// A webhook could not be notified of the changes
+    Notify,
+    /// The output directory could not be uploaded to `--upload`'s destination
+    Upload,
+}
+
+impl FailureStage {
+    pub fn name(self) -> &'static str {
+        match self {
+            FailureStage::Fetch => "fetch",
+            FailureStage::Write => "write",
+            FailureStage::Verify => "verify",
+            FailureStage::Validate => "validate",
+            FailureStage::Combine => "combine",
+            FailureStage::Images => "images",
+            FailureStage::Prices => "prices",
+            FailureStage::Export => "export",
+            FailureStage::Manifest => "manifest",
+            FailureStage::Archive => "archive",
+            FailureStage::Metadata => "metadata",
+            FailureStage::Notify => "notify",
+            FailureStage::Upload => "upload",
+        }
+    }
+}
+
+impl std::fmt::Display for FailureStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The fail-open/fail-closed choice of every stage of a run, and the failures it handled.
+pub struct FailurePolicy {
+    fail_closed: Vec<FailureStage>,
+    strict: bool,
+    failures: Mutex<Vec<(FailureStage, String)>>,
+}
+
+impl FailurePolicy {
+    pub fn new(fail_closed: &[FailureStage], strict: bool) -> Self {
+        let failures = Mutex::new(Vec::new());
+        FailurePolicy { fail_closed: fail_closed.to_vec(), strict, failures }
+    }
+
+    /// Every failure handled so far, fatal or not, in order.
+    pub fn failures(&self) -> Vec<(FailureStage, String)> {
+        self.failures.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Whether failures of `stage` stop the run.
+    pub fn is_fail_closed(&self, stage: FailureStage) -> bool {
+        self.strict || self.fail_closed.contains(&stage)
+    }
+
+    /// Handles a failure of `stage`.
+    ///
+    /// # Arguments
+    /// * `stage` - The stage that failed.
+    /// * `message` - What went wrong.
+    ///
+    /// # Returns
+    /// An error when the stage is fail-closed; otherwise the failure is logged as a warning
+    /// and `Ok` is returned so the run can continue.
+    pub fn check(&self, stage: FailureStage, message: &str) -> Result<(), Box<dyn Error>> {
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        failures.push((stage, message.to_string()));
+        if self.is_fail_closed(stage) {
+            return Err(format!("Error: {} ({} failures are fail-closed)", message, stage).into());
+        }
+        warn!("{} (continuing; {} failures are fail-open)", message, stage);
+        Ok(())
+    }
+}
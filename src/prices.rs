@@ -0,0 +1,206 @@
+//! Market prices of every printing, from a pricing API.
+//!
+//! Neither TCGplayer nor Cardmarket offers a per-set price endpoint, so prices are taken from a
+//! service of the user's own that translates the provider's API (a proxy). It is asked per set
+//! as `GET <base URL>/sets/<SET>/prices`, authenticated with the provider's API key as a bearer
+//! token, and answers with the prices of the set's printings keyed by their print id:
+//!
+//! ```json
+//! {"results": [{"print_id": "WTR001", "market": 1.25, "low": 0.9, "high": 2.0}]}
+//! ```
+//!
+//! A fetch either adds them to each printing of the card data (`inline`), or keeps them in
+//! `prices/<SET>.json` snapshots beside it, refreshed on every run even for unchanged sets.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use chrono::Local;
+use reqwest::header::AUTHORIZATION;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::model::SetResponse;
+use crate::storage;
+
+// Folder price snapshots are written to, inside the output directory
+pub const PRICES_DIR: &str = "prices";
+
+// Field of a printing holding its prices with `--prices-storage inline`
+pub const PRICES_FIELD: &str = "prices";
+
+/// A pricing API prices are taken from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PriceProvider {
+    /// TCGplayer market prices, in US dollars
+    Tcgplayer,
+    /// Cardmarket trend prices, in euros
+    Cardmarket,
+}
+
+impl PriceProvider {
+    pub fn name(self) -> &'static str {
+        match self {
+            PriceProvider::Tcgplayer => "tcgplayer",
+            PriceProvider::Cardmarket => "cardmarket",
+        }
+    }
+
+    /// The currency the provider quotes prices in.
+    pub fn currency(self) -> &'static str {
+        match self {
+            PriceProvider::Tcgplayer => "USD",
+            PriceProvider::Cardmarket => "EUR",
+        }
+    }
+}
+
+impl std::fmt::Display for PriceProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Where a fetch stores the prices of a set.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PriceStorage {
+    /// In a `prices` field of every printing in the card data
+    Inline,
+    /// In `prices/<SET>.json` snapshots next to the card data
+    #[default]
+    Snapshot,
+}
+
+/// The prices of one printing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Price {
+    pub print_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub market: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub high: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct PriceList {
+    results: Vec<Price>,
+}
+
+/// The prices of a set's printings at one point in time, as stored in `prices/<SET>.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceSnapshot {
+    pub provider: String,
+    pub currency: String,
+    pub fetched_at: String,
+    pub prices: Vec<Price>,
+}
+
+/// Requests prices from a pricing provider.
+pub struct PriceClient {
+    http: reqwest::blocking::Client,
+    provider: PriceProvider,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl PriceClient {
+    /// A client for the pricing proxy at `base_url` serving the provider's prices, without an
+    /// API key.
+    pub fn new(http: reqwest::blocking::Client, provider: PriceProvider, base_url: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        PriceClient { http, provider, base_url, api_key: None }
+    }
+
+    /// Authenticates every request with this API key.
+    pub fn api_key(mut self, api_key: Option<String>) -> Self {
+        self.api_key = api_key;
+        self
+    }
+
+    pub fn provider(&self) -> PriceProvider {
+        self.provider
+    }
+
+    /// Fetches the current prices of a set's printings.
+    ///
+    /// # Arguments
+    /// * `set_code` - The set code (e.g., "WTR").
+    ///
+    /// # Returns
+    /// A `Result` containing the prices, or an error if the API cannot be reached or
+    /// answers with an error or an unexpected body.
+    pub fn set_prices(&self, set_code: &str) -> Result<PriceSnapshot, Box<dyn Error>> {
+        let url = format!("{}/sets/{}/prices", self.base_url, set_code);
+        let mut request = self.http.get(&url);
+        if let Some(api_key) = &self.api_key {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", api_key));
+        }
+        let response = request.send()?.error_for_status()?;
+        let list: PriceList = response
+            .json()
+            .map_err(|e| format!("Invalid prices from {}: {}", url, e))?;
+        Ok(PriceSnapshot {
+            provider: self.provider.name().to_string(),
+            currency: self.provider.currency().to_string(),
+            fetched_at: Local::now().to_rfc3339(),
+            prices: list.results,
+        })
+    }
+}
+
+/// Adds prices to the printings of a set, in the `prices` field of each priced printing.
+///
+/// # Arguments
+/// * `set` - The set to update in place.
+/// * `snapshot` - The prices of the set.
+///
+/// # Returns
+/// The number of printings that got a price.
+pub fn apply(set: &mut SetResponse, snapshot: &PriceSnapshot) -> usize {
+    let by_print_id: HashMap<&str, &Price> =
+        snapshot.prices.iter().map(|price| (price.print_id.as_str(), price)).collect();
+    let mut priced = 0;
+    for printing in set.results.iter_mut().flat_map(|card| &mut card.printings) {
+        let Some(price) = by_print_id.get(printing.print_id.as_str()) else {
+            continue;
+        };
+        let value = json!({
+            "provider": snapshot.provider,
+            "currency": snapshot.currency,
+            "fetched_at": snapshot.fetched_at,
+            "market": price.market,
+            "low": price.low,
+            "high": price.high,
+        });
+        printing.extra.insert(PRICES_FIELD.to_string(), value);
+        priced += 1;
+    }
+    priced
+}
+
+/// Returns the path of a set's price snapshot: `{base}/prices/{SET}.json`.
+pub fn snapshot_filename(base_output_dir: &str, set_code: &str) -> String {
+    format!("{}/{}/{}.json", base_output_dir, PRICES_DIR, set_code)
+}
+
+/// Writes a set's price snapshot, replacing the previous one.
+///
+/// # Returns
+/// A `Result` containing the path written, or an error.
+pub fn write_snapshot(
+    base_output_dir: &str,
+    set_code: &str,
+    snapshot: &PriceSnapshot,
+) -> Result<String, Box<dyn Error>> {
+    fs::create_dir_all(Path::new(base_output_dir).join(PRICES_DIR))?;
+    let filename = snapshot_filename(base_output_dir, set_code);
+    let content = serde_json::to_string_pretty(snapshot)?;
+    storage::save_data_to_file(&filename, &content)?;
+    Ok(filename)
+}
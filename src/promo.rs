@@ -0,0 +1,167 @@
+//! Promo printings, classified by the promo program they were given out through.
+//!
+//! The API does not tell promo programs apart, so they are derived where possible: from a
+//! promo, product or source description on the printing (e.g. "Armory Kit", "Judge
+//! promo"), and otherwise from the code prefix of promo print ids (e.g. `JDG001`). Fetches
+//! record the program of every promo printing in its `promo` field.
+
+use serde_json::Value;
+
+use crate::model::{Card, Printing, SetResponse};
+
+// Field of a printing holding its promo program
+pub const PROMO_FIELD: &str = "promo";
+
+// Fields of a printing that may describe how it was distributed, when the API provides them
+const DESCRIPTION_FIELDS: [&str; 4] = ["promo_type", "product", "source", "treatment"];
+
+// Code prefixes of promo print ids, and the program they belong to
+const PROMO_PREFIXES: [(&str, PromoProgram); 7] = [
+    ("ARM", PromoProgram::Armory),
+    ("LGS", PromoProgram::Armory),
+    ("JDG", PromoProgram::Judge),
+    ("HER", PromoProgram::OrganizedPlay),
+    ("OP", PromoProgram::OrganizedPlay),
+    ("FAB", PromoProgram::Marketing),
+    ("LSS", PromoProgram::Marketing),
+];
+
+// Words of a description naming a program, checked in order
+const PROMO_WORDS: [(&str, PromoProgram); 9] = [
+    ("armory", PromoProgram::Armory),
+    ("judge", PromoProgram::Judge),
+    ("prize", PromoProgram::OrganizedPlay),
+    ("proquest", PromoProgram::OrganizedPlay),
+    ("calling", PromoProgram::OrganizedPlay),
+    ("battle hardened", PromoProgram::OrganizedPlay),
+    ("national", PromoProgram::OrganizedPlay),
+    ("marketing", PromoProgram::Marketing),
+    ("promo", PromoProgram::Marketing),
+];
+
+/// A program promo printings are given out through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PromoProgram {
+    /// Armory kits and other store-level play promos
+    Armory,
+    /// Promos for judges
+    Judge,
+    /// Prizes of organized play (ProQuest, Calling, Battle Hardened, Nationals, ...)
+    OrganizedPlay,
+    /// Marketing and general promos
+    Marketing,
+}
+
+impl PromoProgram {
+    pub fn name(self) -> &'static str {
+        match self {
+            PromoProgram::Armory => "armory",
+            PromoProgram::Judge => "judge",
+            PromoProgram::OrganizedPlay => "organized-play",
+            PromoProgram::Marketing => "marketing",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            PromoProgram::Armory,
+            PromoProgram::Judge,
+            PromoProgram::OrganizedPlay,
+            PromoProgram::Marketing,
+        ]
+        .into_iter()
+        .find(|program| program.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+impl std::fmt::Display for PromoProgram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Which printings exports and stats keep, by whether they are promos.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PromoSelection {
+    /// Every printing
+    #[default]
+    All,
+    /// Every printing except promos
+    Exclude,
+    /// Only promo printings
+    Only,
+}
+
+/// The promo program of a printing, if it is a promo whose program can be derived.
+///
+/// # Arguments
+/// * `printing` - The printing to classify; a program already recorded in its `promo` field
+///   is kept.
+pub fn classify(printing: &Printing) -> Option<PromoProgram> {
+    if let Some(Value::String(recorded)) = printing.extra.get(PROMO_FIELD)
+        && let Some(program) = PromoProgram::from_name(recorded)
+    {
+        return Some(program);
+    }
+
+    let descriptions = DESCRIPTION_FIELDS
+        .iter()
+        .chain([&PROMO_FIELD])
+        .filter_map(|field| printing.extra.get(*field))
+        .filter_map(Value::as_str)
+        .map(str::to_lowercase);
+    for description in descriptions {
+        if let Some((_, program)) = PROMO_WORDS.iter().find(|(word, _)| description.contains(word))
+        {
+            return Some(*program);
+        }
+    }
+
+    // Promo print ids start with the code of their promo series, e.g. JDG001
+    let code = printing.set_code.as_deref().unwrap_or(&printing.print_id);
+    let prefix: String = code.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+    PROMO_PREFIXES
+        .iter()
+        .find(|(promo_prefix, _)| prefix.eq_ignore_ascii_case(promo_prefix))
+        .map(|(_, program)| *program)
+}
+
+/// Records the promo program of every promo printing of a set in its `promo` field.
+///
+/// # Returns
+/// The number of promo printings found.
+pub fn apply(set: &mut SetResponse) -> usize {
+    let mut promos = 0;
+    for printing in set.results.iter_mut().flat_map(|card| &mut card.printings) {
+        if let Some(program) = classify(printing) {
+            printing.extra.insert(PROMO_FIELD.to_string(), Value::from(program.name()));
+            promos += 1;
+        }
+    }
+    promos
+}
+
+/// Keeps the printings of a set that the selection asks for, dropping the cards left without
+/// any printing. With `PromoSelection::All`, the set is returned unchanged.
+pub fn select(set: &SetResponse, selection: PromoSelection) -> SetResponse {
+    let mut selected = set.clone();
+    if selection == PromoSelection::All {
+        return selected;
+    }
+    let only = selection == PromoSelection::Only;
+    selected.results.retain_mut(|card| {
+        card.printings.retain(|printing| classify(printing).is_some() == only);
+        !card.printings.is_empty()
+    });
+    selected
+}
+
+/// Whether a card has a printing from any of the given programs.
+pub fn has_program(card: &Card, programs: &[PromoProgram]) -> bool {
+    card.printings
+        .iter()
+        .filter_map(classify)
+        .any(|program| programs.contains(&program))
+}
@@ -0,0 +1,98 @@
+//! Token-bucket rate limiting shared by every request of a run.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Limits how often requests may start, across all worker threads.
+///
+/// One token is added every `interval`, up to `burst` tokens; each request takes one token
+/// and waits when none is left. A zero interval disables limiting.
+pub struct RateLimiter {
+    interval: Duration,
+    burst: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        RateLimiter {
+            interval,
+            burst,
+            state: Mutex::new(BucketState {
+                tokens: burst,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Blocks until a request may be sent.
+    pub fn acquire(&self) {
+        if self.interval.is_zero() {
+            return;
+        }
+
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+                let now = Instant::now();
+                let refilled = now.duration_since(state.last_refill).as_secs_f64()
+                    / self.interval.as_secs_f64();
+                state.tokens = (state.tokens + refilled).min(self.burst);
+                state.last_refill = now;
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+                self.interval.mul_f64(1.0 - state.tokens)
+            };
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_a_burst_then_one_request_per_interval_across_threads() {
+        let interval = Duration::from_millis(50);
+        let limiter = RateLimiter::new(interval, 3);
+        let started = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire();
+        }
+        assert!(started.elapsed() < interval / 2, "the burst waited {:?}", started.elapsed());
+
+        // Eight more requests from four threads take a token each, one per interval
+        let started = Instant::now();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    limiter.acquire();
+                    limiter.acquire();
+                });
+            }
+        });
+        let elapsed = started.elapsed();
+        assert!(elapsed >= interval.mul_f64(7.9), "eight requests took {:?}", elapsed);
+        assert!(elapsed < interval * 16, "eight requests took {:?}", elapsed);
+    }
+
+    #[test]
+    fn zero_interval_never_waits() {
+        let limiter = RateLimiter::new(Duration::ZERO, 1);
+        let started = Instant::now();
+        for _ in 0..1000 {
+            limiter.acquire();
+        }
+        assert!(started.elapsed() < Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,163 @@
+//! Reminder text: the italic parentheticals in card text that explain a keyword, e.g.
+//! `*(If this hits, draw a card.)*`.
+//!
+//! Reminder text is reworded far more often than the rules text itself, so fetches can
+//! strip it, or move it into a separate `reminder_text` field, to keep searches and text
+//! diffs about the rules that actually changed.
+
+use serde_json::Value;
+
+use crate::model::SetResponse;
+
+// Field the reminder texts of a card are moved to with `--reminder-text separate`
+pub const REMINDER_TEXT_FIELD: &str = "reminder_text";
+
+// Markers opening and closing an italic parenthetical in the card text markup
+const MARKERS: [(&str, &str); 2] = [("*(", ")*"), ("_(", ")_")];
+
+/// What fetches do with the reminder text of every card.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ReminderText {
+    /// Leave the card text as returned by the API
+    #[default]
+    Keep,
+    /// Remove reminder text from the card text
+    Strip,
+    /// Remove reminder text from the card text and list it in a `reminder_text` field
+    Separate,
+}
+
+/// Splits card text into the text without its reminders and the reminders themselves.
+///
+/// # Arguments
+/// * `text` - The card text, in the API's markup.
+///
+/// # Returns
+/// The text with every reminder removed and its whitespace tidied up, and the content of
+/// each reminder without its parentheses, in order.
+pub fn split(text: &str) -> (String, Vec<String>) {
+    let mut rest = text;
+    let mut stripped = String::new();
+    let mut reminders = Vec::new();
+    while let Some((start, open, close)) = MARKERS
+        .iter()
+        .filter_map(|(open, close)| rest.find(open).map(|start| (start, *open, *close)))
+        .min_by_key(|(start, _, _)| *start)
+    {
+        let inner_start = start + open.len();
+        let Some(length) = rest[inner_start..].find(close) else {
+            break;
+        };
+        stripped.push_str(&rest[..start]);
+        reminders.push(rest[inner_start..inner_start + length].trim().to_string());
+        rest = &rest[inner_start + length + close.len()..];
+    }
+    stripped.push_str(rest);
+
+    // Drop the spaces and blank lines the reminders leave behind, keeping paragraph breaks
+    let mut lines: Vec<String> = Vec::new();
+    for line in stripped.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !line.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+            lines.push(line);
+        }
+    }
+    while lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
+    (lines.join("\n"), reminders)
+}
+
+/// Applies a reminder text mode to every card of a set.
+///
+/// # Arguments
+/// * `mode` - What to do with the reminder text.
+/// * `set` - The set to update in place.
+pub fn apply(mode: ReminderText, set: &mut SetResponse) {
+    if mode == ReminderText::Keep {
+        return;
+    }
+    for card in &mut set.results {
+        let Some(text) = &card.text else {
+            continue;
+        };
+        let (stripped, reminders) = split(text);
+        if reminders.is_empty() {
+            continue;
+        }
+        card.text = (!stripped.is_empty()).then_some(stripped);
+        if mode == ReminderText::Separate {
+            let reminders = reminders.into_iter().map(Value::String).collect();
+            card.extra.insert(REMINDER_TEXT_FIELD.to_string(), Value::Array(reminders));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn set(texts: &[&str]) -> SetResponse {
+        let cards: Vec<Value> = texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| json!({"card_id": i.to_string(), "name": "Card", "text": text}))
+            .collect();
+        serde_json::from_value(json!({ "results": cards })).unwrap()
+    }
+
+    #[test]
+    fn splits_both_markers() {
+        let source = "**Go again** *(Gain 1 action point.)* If this hits, _( draw a card. )_ Pay.";
+        let (text, reminders) = split(source);
+        assert_eq!(text, "**Go again** If this hits, Pay.");
+        assert_eq!(reminders, ["Gain 1 action point.", "draw a card."]);
+    }
+
+    #[test]
+    fn keeps_an_unclosed_marker() {
+        let (text, reminders) = split("**Dominate** *(The defending hero can't block");
+        assert_eq!(text, "**Dominate** *(The defending hero can't block");
+        assert!(reminders.is_empty());
+
+        let (text, reminders) = split("*(Closed.)* Rest _(open");
+        assert_eq!((text.as_str(), reminders), ("Rest _(open", vec![String::from("Closed.")]));
+    }
+
+    #[test]
+    fn keeps_paragraphs_and_drops_the_blank_lines_left_behind() {
+        let source = "*(Only a reminder.)*\n\nFirst paragraph. *(Reminder.)*\n\n*(Reminder.)*\n\n\
+                      Second  paragraph.\n*(Trailing reminder.)*\n";
+        let (text, reminders) = split(source);
+        assert_eq!(text, "First paragraph.\n\nSecond paragraph.");
+        assert_eq!(reminders.len(), 4);
+    }
+
+    #[test]
+    fn strip_removes_text_that_was_only_reminders() {
+        let mut set = set(&["*(Only a reminder.)*", "Rules. *(Reminder.)*", "No reminder."]);
+        apply(ReminderText::Strip, &mut set);
+        let texts: Vec<Option<&str>> =
+            set.results.iter().map(|card| card.text.as_deref()).collect();
+        assert_eq!(texts, [None, Some("Rules."), Some("No reminder.")]);
+        assert!(set.results.iter().all(|card| !card.extra.contains_key(REMINDER_TEXT_FIELD)));
+    }
+
+    #[test]
+    fn separate_lists_the_reminders_on_the_card() {
+        let mut set = set(&["Rules. *(First.)* _(Second.)_", "No reminder."]);
+        apply(ReminderText::Separate, &mut set);
+        assert_eq!(set.results[0].text.as_deref(), Some("Rules."));
+        assert_eq!(set.results[0].extra[REMINDER_TEXT_FIELD], json!(["First.", "Second."]));
+        assert!(!set.results[1].extra.contains_key(REMINDER_TEXT_FIELD));
+    }
+
+    #[test]
+    fn keep_leaves_the_text_alone() {
+        let mut set = set(&["Rules. *(Reminder.)*"]);
+        apply(ReminderText::Keep, &mut set);
+        assert_eq!(set.results[0].text.as_deref(), Some("Rules. *(Reminder.)*"));
+    }
+}
@@ -0,0 +1,197 @@
+//! Machine-readable report of a fetch, written to `run_report.json` in the output directory.
+//!
+//! The report lists what happened to every requested set, with its card and page counts, the
+//! retries and HTTP errors of its requests and where its time went, followed by every failure
+//! of the run, so jobs can tell a partial mirror from a complete one without parsing logs.
+
+use std::error::Error;
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::api::SetTiming;
+use crate::policy::FailureStage;
+use crate::storage;
+
+// Name of the report inside the output directory
+pub const RUN_REPORT_FILENAME: &str = "run_report.json";
+
+/// What a fetch did with a set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SetStatus {
+    /// Downloaded and written
+    Saved,
+    /// Not modified since the last run, so its files were kept
+    Unchanged,
+    /// Already fetched by the interrupted run this one resumed
+    Resumed,
+    /// Downloaded, but its files stayed corrupt after every re-fetch
+    Corrupt,
+    /// Could not be downloaded or written
+    Failed,
+    /// Not attempted, as the run stopped before reaching it
+    Skipped,
+}
+
+impl SetStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            SetStatus::Saved => "saved",
+            SetStatus::Unchanged => "unchanged",
+            SetStatus::Resumed => "resumed",
+            SetStatus::Corrupt => "corrupt",
+            SetStatus::Failed => "failed",
+            SetStatus::Skipped => "skipped",
+        }
+    }
+}
+
+impl std::fmt::Display for SetStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How a fetch ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    /// Every stage succeeded
+    Succeeded,
+    /// The run completed, but some stages failed and were only reported
+    Partial,
+    /// The run stopped with an error
+    Failed,
+}
+
+/// A failure handled by the failure policy.
+#[derive(Debug, Serialize)]
+pub struct StageFailure {
+    pub stage: &'static str,
+    pub message: String,
+}
+
+impl StageFailure {
+    pub fn new(stage: FailureStage, message: &str) -> Self {
+        StageFailure { stage: stage.name(), message: message.to_string() }
+    }
+}
+
+/// Time spent on a set, in seconds.
+#[derive(Debug, Serialize)]
+pub struct Durations {
+    pub request: f64,
+    pub body: f64,
+    pub parse: f64,
+    pub write: f64,
+    pub total: f64,
+}
+
+/// What happened to one set.
+#[derive(Debug, Serialize)]
+pub struct SetReport {
+    pub set_code: String,
+    pub status: SetStatus,
+    pub cards: Option<usize>,
+    /// Cards that were not in the set's previous output, if the set was written.
+    pub new_cards: Option<usize>,
+    /// Cards with changed fields or new printings, if the set was written.
+    pub changed_cards: Option<usize>,
+    /// Images downloaded, with `--images`.
+    pub images: Option<usize>,
+    pub pages: usize,
+    pub retries: u32,
+    pub http_errors: Vec<String>,
+    pub errors: Vec<StageFailure>,
+    pub durations: Durations,
+}
+
+impl SetReport {
+    /// Reports a set from its timings and the failures of its stages.
+    pub fn new(
+        timing: &SetTiming,
+        status: SetStatus,
+        cards: Option<usize>,
+        failures: &[(FailureStage, String)],
+    ) -> Self {
+        SetReport {
+            set_code: timing.set_code.clone(),
+            status,
+            cards,
+            new_cards: None,
+            changed_cards: None,
+            images: None,
+            pages: timing.pages,
+            retries: timing.retries,
+            http_errors: timing.http_errors.clone(),
+            errors: stage_failures(failures),
+            durations: Durations {
+                request: timing.request.as_secs_f64(),
+                body: timing.body.as_secs_f64(),
+                parse: timing.parse.as_secs_f64(),
+                write: timing.write.as_secs_f64(),
+                total: timing.total().as_secs_f64(),
+            },
+        }
+    }
+
+    /// Reports a set that was not requested from the API this run.
+    pub fn untouched(set_code: &str, status: SetStatus, cards: Option<usize>) -> Self {
+        SetReport::new(&SetTiming::new(set_code), status, cards, &[])
+    }
+}
+
+/// The report of a whole fetch.
+#[derive(Debug, Serialize)]
+pub struct RunReport {
+    pub started_at: String,
+    pub finished_at: String,
+    pub status: RunStatus,
+    /// The error that stopped the run, if it did not complete.
+    pub error: Option<String>,
+    pub sets: Vec<SetReport>,
+    /// Every failure of the run, including those of the sets, in order.
+    pub failures: Vec<StageFailure>,
+}
+
+impl RunReport {
+    /// Reports a finished fetch.
+    ///
+    /// # Arguments
+    /// * `started_at` - When the run started.
+    /// * `sets` - What happened to every set.
+    /// * `failures` - Every failure the failure policy handled.
+    /// * `error` - The error that stopped the run, if any.
+    pub fn new(
+        started_at: DateTime<Local>,
+        sets: Vec<SetReport>,
+        failures: &[(FailureStage, String)],
+        error: Option<&dyn Error>,
+    ) -> Self {
+        let status = match error {
+            Some(_) => RunStatus::Failed,
+            None if failures.is_empty() => RunStatus::Succeeded,
+            None => RunStatus::Partial,
+        };
+        RunReport {
+            started_at: started_at.to_rfc3339(),
+            finished_at: Local::now().to_rfc3339(),
+            status,
+            error: error.map(|e| e.to_string()),
+            sets,
+            failures: stage_failures(failures),
+        }
+    }
+
+    /// Writes the report into the output directory, replacing the one of the previous run.
+    pub fn write(&self, base_output_dir: &str) -> Result<(), Box<dyn Error>> {
+        let path = Path::new(base_output_dir).join(RUN_REPORT_FILENAME);
+        storage::write_atomically(&path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+}
+
+fn stage_failures(failures: &[(FailureStage, String)]) -> Vec<StageFailure> {
+    failures.iter().map(|(stage, message)| StageFailure::new(*stage, message)).collect()
+}
@@ -0,0 +1,107 @@
+//! Retrying transient request failures with exponential backoff.
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+// Longest pause between two attempts, however many retries came before
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// How often and how patiently failed requests are retried.
+///
+/// The pause before retry `n` is `base_delay * 2^(n - 1)`, capped at `MAX_RETRY_DELAY`, of
+/// which a random half is kept so workers that failed together do not retry together.
+pub struct RetryPolicy {
+    max_retries: u32,
+    base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32, base_delay: Duration) -> Self {
+        RetryPolicy { max_retries, base_delay }
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Returns the pause before the given retry (starting at 1).
+    ///
+    /// # Arguments
+    /// * `retry` - The number of the retry about to be made.
+    /// * `retry_after` - The delay the server asked for, which is waited at least.
+    pub fn delay(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(MAX_RETRY_DELAY);
+        let jittered = backoff / 2 + backoff.mul_f64(fastrand::f64() / 2.0);
+        retry_after.map_or(jittered, |requested| jittered.max(requested))
+    }
+}
+
+/// Whether a response status is worth retrying: rate limiting and server errors.
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Whether a request error is a transient network problem worth retrying.
+pub fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_timeout() || error.is_connect() || error.is_request() || error.is_body()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Delays drawn per retry, so every assertion holds for the whole jitter range
+    const SAMPLES: usize = 200;
+
+    fn delays(policy: &RetryPolicy, retry: u32, retry_after: Option<Duration>) -> Vec<Duration> {
+        (0..SAMPLES).map(|_| policy.delay(retry, retry_after)).collect()
+    }
+
+    #[test]
+    fn backoff_doubles_and_keeps_a_random_half() {
+        let policy = RetryPolicy::new(10, Duration::from_secs(1));
+        for retry in 1..=6 {
+            let backoff = Duration::from_secs(1 << (retry - 1));
+            let delays = delays(&policy, retry, None);
+            assert!(delays.iter().all(|delay| *delay >= backoff / 2 && *delay <= backoff));
+            // The jitter spreads over the whole range instead of always halving the backoff
+            assert!(delays.iter().any(|delay| *delay > backoff.mul_f64(0.75)), "retry {}", retry);
+            assert!(delays.iter().any(|delay| *delay < backoff.mul_f64(0.75)), "retry {}", retry);
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = RetryPolicy::new(200, Duration::from_secs(1));
+        for retry in [7, 8, 32, 100, u32::MAX] {
+            let delays = delays(&policy, retry, None);
+            assert!(delays.iter().all(|delay| {
+                *delay >= MAX_RETRY_DELAY / 2 && *delay <= MAX_RETRY_DELAY
+            }));
+        }
+    }
+
+    #[test]
+    fn retry_after_is_waited_at_least() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(100));
+        let requested = Duration::from_secs(30);
+        assert!(delays(&policy, 1, Some(requested)).iter().all(|delay| *delay == requested));
+        // A shorter request than the backoff leaves the backoff as it is
+        let delays = delays(&policy, 3, Some(Duration::from_millis(1)));
+        let range = Duration::from_millis(200)..=Duration::from_millis(400);
+        assert!(delays.iter().all(|delay| range.contains(delay)));
+    }
+
+    #[test]
+    fn retries_rate_limiting_and_server_errors() {
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!is_retryable_status(StatusCode::NOT_FOUND));
+        assert!(!is_retryable_status(StatusCode::FORBIDDEN));
+    }
+}
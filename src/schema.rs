@@ -0,0 +1,118 @@
+//! Versioned parsing of API listing pages.
+//!
+//! The search API is served under `/api/search/v1/`. To survive a migration to a new version
+//! without breaking, every page is parsed by the schema of the version it declares in its
+//! `API-Version` (or `X-API-Version`) header, and pages without a header are tried against
+//! every known schema, newest last. Unknown versions are reported once and parsed the same way.
+
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::model::Page;
+
+// Response headers the API may declare its version in, checked in order
+pub const VERSION_HEADERS: [&str; 2] = ["api-version", "x-api-version"];
+
+/// A version of the API's response schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiVersion {
+    /// `{"count", "next", "previous", "results"}` pages, as served today.
+    V1,
+    /// `{"data", "links": {"next", "prev"}, "meta": {"count"}}` pages.
+    V2,
+    /// A version this tool does not know yet, as declared by the API.
+    Unknown(String),
+}
+
+impl ApiVersion {
+    /// Reads a declared version such as `1`, `v2` or `2.0`.
+    pub fn parse(declared: &str) -> ApiVersion {
+        let declared = declared.trim();
+        let number = declared.trim_start_matches(['v', 'V']);
+        match number.split('.').next() {
+            Some("1") => ApiVersion::V1,
+            Some("2") => ApiVersion::V2,
+            _ => ApiVersion::Unknown(declared.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiVersion::V1 => f.write_str("v1"),
+            ApiVersion::V2 => f.write_str("v2"),
+            ApiVersion::Unknown(declared) => f.write_str(declared),
+        }
+    }
+}
+
+/// A page in the v2 envelope.
+#[derive(Deserialize)]
+struct V2Page<T> {
+    data: Vec<T>,
+    #[serde(default)]
+    links: V2Links,
+    #[serde(default)]
+    meta: V2Meta,
+}
+
+#[derive(Default, Deserialize)]
+struct V2Links {
+    #[serde(default)]
+    next: Option<String>,
+    #[serde(default)]
+    prev: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct V2Meta {
+    #[serde(default, alias = "total")]
+    count: Option<u64>,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+impl<T> From<V2Page<T>> for Page<T> {
+    fn from(page: V2Page<T>) -> Self {
+        Page {
+            count: page.meta.count,
+            next: page.links.next,
+            previous: page.links.prev,
+            results: page.data,
+            extra: page.meta.extra,
+        }
+    }
+}
+
+/// Parses a page with the schema of one version.
+fn parse_as<T: DeserializeOwned>(body: &str, version: &ApiVersion) -> serde_json::Result<Page<T>> {
+    match version {
+        ApiVersion::V2 => serde_json::from_str::<V2Page<T>>(body).map(Page::from),
+        _ => serde_json::from_str(body),
+    }
+}
+
+/// Parses a listing page into the typed model, whatever schema version it uses.
+///
+/// # Arguments
+/// * `body` - The raw JSON body of the page.
+/// * `declared` - The version the response declared, if any.
+///
+/// # Returns
+/// A `Result` containing the page and the version whose schema parsed it, or the error of
+/// the declared version's schema (v1 when none or an unknown one was declared).
+pub fn parse_page<T: DeserializeOwned>(
+    body: &str,
+    declared: Option<&ApiVersion>,
+) -> serde_json::Result<(Page<T>, ApiVersion)> {
+    if let Some(version @ (ApiVersion::V1 | ApiVersion::V2)) = declared {
+        return parse_as(body, version).map(|page| (page, version.clone()));
+    }
+    let v1_error = match parse_as(body, &ApiVersion::V1) {
+        Ok(page) => return Ok((page, ApiVersion::V1)),
+        Err(e) => e,
+    };
+    parse_as(body, &ApiVersion::V2).map(|page| (page, ApiVersion::V2)).map_err(|_| v1_error)
+}
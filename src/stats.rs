@@ -0,0 +1,135 @@
+//! Distributions of the fetched cards: how often every keyword, type, rarity and pitch occurs.
+//!
+//! A card printed in several sets is counted once, and every printing once. Cards and
+//! printings are counted in parallel, each thread into its own tallies that are merged at the
+//! end, so counting the full dataset scales with the number of cores.
+
+use std::collections::{BTreeMap, HashSet};
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::model::{Card, Printing, SetResponse};
+
+// Key that cards without a pitch and printings without a rarity are counted under
+const NONE_KEY: &str = "none";
+
+/// How often every keyword, type, rarity and pitch occurs in a dataset.
+#[derive(Default, Serialize)]
+pub struct Stats {
+    /// Distinct cards.
+    pub cards: usize,
+    /// Distinct printings.
+    pub printings: usize,
+    /// Cards with every keyword.
+    pub keywords: BTreeMap<String, usize>,
+    /// Cards with every type.
+    pub types: BTreeMap<String, usize>,
+    /// Printings of every rarity.
+    pub rarities: BTreeMap<String, usize>,
+    /// Cards of every pitch.
+    pub pitches: BTreeMap<String, usize>,
+}
+
+impl Stats {
+    /// Counts one card into the tallies.
+    fn add_card(mut self, card: &Card) -> Self {
+        self.cards += 1;
+        for keyword in &card.keywords {
+            *self.keywords.entry(keyword.trim().to_string()).or_default() += 1;
+        }
+        for card_type in &card.types {
+            *self.types.entry(card_type.trim().to_string()).or_default() += 1;
+        }
+        let pitch = card.pitch.as_deref().map(str::trim).filter(|pitch| !pitch.is_empty());
+        *self.pitches.entry(pitch.unwrap_or(NONE_KEY).to_string()).or_default() += 1;
+        self
+    }
+
+    /// Counts one printing into the tallies.
+    fn add_printing(mut self, printing: &Printing) -> Self {
+        self.printings += 1;
+        let rarity = printing.rarity.as_deref().map(str::trim).filter(|rarity| !rarity.is_empty());
+        *self.rarities.entry(rarity.unwrap_or(NONE_KEY).to_string()).or_default() += 1;
+        self
+    }
+
+    /// Adds the tallies of another part of the dataset.
+    fn merge(mut self, other: Stats) -> Self {
+        self.cards += other.cards;
+        self.printings += other.printings;
+        for (mine, theirs) in [
+            (&mut self.keywords, other.keywords),
+            (&mut self.types, other.types),
+            (&mut self.rarities, other.rarities),
+            (&mut self.pitches, other.pitches),
+        ] {
+            for (key, count) in theirs {
+                *mine.entry(key).or_default() += count;
+            }
+        }
+        self
+    }
+}
+
+/// Counts the keywords, types, rarities and pitches of several sets.
+///
+/// # Arguments
+/// * `sets` - The set codes and cards of the sets to count.
+///
+/// # Returns
+/// The tallies of every distinct card and printing of the sets.
+pub fn compute(sets: &[(&str, &SetResponse)]) -> Stats {
+    // Reprints list the same card in several sets; only its first listing is counted
+    let mut seen_cards = HashSet::new();
+    let cards: Vec<&Card> = sets
+        .iter()
+        .flat_map(|(_, set)| &set.results)
+        .filter(|card| seen_cards.insert(card.card_id.as_str()))
+        .collect();
+    let mut seen_printings = HashSet::new();
+    let printings: Vec<&Printing> = sets
+        .iter()
+        .flat_map(|(_, set)| &set.results)
+        .flat_map(|card| &card.printings)
+        .filter(|printing| seen_printings.insert(printing.print_id.as_str()))
+        .collect();
+
+    let card_stats = cards
+        .par_iter()
+        .fold(Stats::default, |stats, card| stats.add_card(card))
+        .reduce(Stats::default, Stats::merge);
+    let printing_stats = printings
+        .par_iter()
+        .fold(Stats::default, |stats, printing| stats.add_printing(printing))
+        .reduce(Stats::default, Stats::merge);
+    card_stats.merge(printing_stats)
+}
+
+/// Renders the tallies as tables, most frequent first.
+///
+/// # Arguments
+/// * `stats` - The tallies.
+/// * `top` - How many keywords and types to list; every rarity and pitch is listed.
+pub fn render_text(stats: &Stats, top: usize) -> String {
+    let mut out = format!("{} card(s), {} printing(s)\n", stats.cards, stats.printings);
+    let tables = [
+        ("Keyword", &stats.keywords, stats.cards, top),
+        ("Type", &stats.types, stats.cards, top),
+        ("Rarity", &stats.rarities, stats.printings, usize::MAX),
+        ("Pitch", &stats.pitches, stats.cards, usize::MAX),
+    ];
+    for (title, counts, total, limit) in tables {
+        let mut sorted: Vec<(&String, &usize)> = counts.iter().collect();
+        sorted.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        out.push_str(&format!("\n{:<24} {:>8} {:>7}\n", title, "Count", "Share"));
+        for (key, count) in sorted.iter().take(limit) {
+            let share = if total == 0 { 0.0 } else { **count as f64 * 100.0 / total as f64 };
+            out.push_str(&format!("{:<24} {:>8} {:>6.1}%\n", key, count, share));
+        }
+        if sorted.len() > limit {
+            out.push_str(&format!("... and {} more\n", sorted.len() - limit));
+        }
+    }
+    out
+}
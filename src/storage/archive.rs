@@ -0,0 +1,94 @@
+//! Timestamped `.tar.zst` archives of a whole output directory, for keeping historical
+//! snapshots of the dataset.
+//!
+//! An archive holds every file of the output directory, images included, under a top folder
+//! named like the archive. The lock, the HTTP cache, partially written files and earlier
+//! archives are left out.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+
+// Folder archives are written to by default, inside the output directory
+pub const ARCHIVE_DIR: &str = "archives";
+
+/// Returns the name of the archive of a run, without its directory:
+/// `fab-scrapper-<YYYYMMDD-HHMMSS>.tar.zst`.
+pub fn archive_name(created_at: DateTime<Local>) -> String {
+    format!("fab-scrapper-{}.tar.zst", created_at.format("%Y%m%d-%H%M%S"))
+}
+
+/// Writes an archive of an output directory.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory to archive.
+/// * `archive_dir` - The folder to write the archive to, created if needed.
+/// * `created_at` - When the archived run started, which names the archive.
+///
+/// # Returns
+/// A `Result` containing the path of the archive and the number of files in it, or an error.
+#[cfg(feature = "compression")]
+pub fn write(
+    base_output_dir: &str,
+    archive_dir: &Path,
+    created_at: DateTime<Local>,
+) -> Result<(PathBuf, usize), Box<dyn Error>> {
+    use std::fs::{self, File};
+
+    use super::compression::ZSTD_LEVEL;
+
+    fs::create_dir_all(archive_dir)?;
+    let name = archive_name(created_at);
+    let path = archive_dir.join(&name);
+    let mut partial_path = path.as_os_str().to_os_string();
+    partial_path.push(super::PARTIAL_SUFFIX);
+    let top = name.trim_end_matches(".tar.zst");
+
+    let encoder = zstd::Encoder::new(File::create(&partial_path)?, ZSTD_LEVEL)?;
+    let mut builder = tar::Builder::new(encoder);
+    let skipped = fs::canonicalize(archive_dir)?;
+    let mut files = 0;
+    let mut pending = vec![PathBuf::from(base_output_dir)];
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>, _>>()?;
+        entries.sort();
+        for entry in entries {
+            let relative = entry.strip_prefix(base_output_dir)?;
+            if is_excluded(&relative.to_string_lossy()) {
+                continue;
+            }
+            if entry.is_dir() {
+                if fs::canonicalize(&entry)? != skipped {
+                    pending.push(entry);
+                }
+                continue;
+            }
+            builder.append_path_with_name(&entry, Path::new(top).join(relative))?;
+            files += 1;
+        }
+    }
+    let file = builder.into_inner()?.finish()?;
+    file.sync_all()?;
+    fs::rename(&partial_path, &path)?;
+    Ok((path, files))
+}
+
+#[cfg(not(feature = "compression"))]
+pub fn write(
+    _base_output_dir: &str,
+    _archive_dir: &Path,
+    _created_at: DateTime<Local>,
+) -> Result<(PathBuf, usize), Box<dyn Error>> {
+    Err("Archives need a build with the `compression` feature".into())
+}
+
+/// Whether a path relative to the output directory is left out of archives.
+#[cfg(feature = "compression")]
+fn is_excluded(relative: &str) -> bool {
+    relative == super::lock::LOCK_FILENAME
+        || relative == super::http_cache::HTTP_CACHE_DIR
+        || relative.ends_with(super::PARTIAL_SUFFIX)
+}
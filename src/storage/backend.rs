@@ -0,0 +1,313 @@
+//! Storage backends the rendered card data can be written to instead of, or besides, the
+//! output directory.
+//!
+//! A [`Storage`] holds files by key, a `/`-separated path relative to its root such as
+//! `json/WTR_cards.json`, so the same layout is used whether the files end up in a directory
+//! ([`FileStorage`]), in memory ([`MemoryStorage`]) or in an S3-compatible bucket
+//! ([`S3Storage`](super::s3::S3Storage)):
+//!
+//! ```no_run
+//! use fab_scrapper::FabClient;
+//! use fab_scrapper::storage::backend::{MemoryStorage, Storage};
+//! use fab_scrapper::storage::{self, OutputFormat};
+//! use fab_scrapper::template::CardTemplate;
+//!
+//! let client = FabClient::new()?;
+//! let wtr = client.set_cards("WTR")?;
+//! let memory = MemoryStorage::new();
+//! let formats = [OutputFormat::Json, OutputFormat::Csv];
+//! storage::store_sets(&memory, &[("WTR", &wtr)], &formats, &CardTemplate::default(), false)?;
+//! let csv = memory.get("csv/WTR_cards.csv")?.expect("the CSV file was stored");
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::manifest::{MANIFEST_FILENAME, Manifest};
+use super::s3::{S3_SCHEME, S3Storage};
+
+/// A place files are stored by key.
+pub trait Storage: Send + Sync {
+    /// Stores a file, replacing any file with the same key.
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Box<dyn Error>>;
+
+    /// Reads a file, or `None` if there is no file with this key.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>>;
+
+    /// Removes a file; a key without a file is not an error.
+    fn delete(&self, key: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Lists the keys starting with `prefix`, in lexicographic order.
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Box<dyn Error>>;
+
+    /// Where the files are stored, for log messages.
+    fn describe(&self) -> String;
+}
+
+/// Files in a directory, each key being a path relative to it.
+pub struct FileStorage {
+    root: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FileStorage { root: root.into() }
+    }
+
+    /// The path of a key, refusing keys that would leave the root directory.
+    fn path(&self, key: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let relative = Path::new(key);
+        if relative.is_absolute() || key.split('/').any(|part| part == "..") {
+            return Err(format!("Error: Invalid storage key '{}'", key).into());
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+impl Storage for FileStorage {
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        super::write_atomically(&path, data)
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        match fs::read(self.path(key)?) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        match fs::remove_file(self.path(key)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut keys = Vec::new();
+        let mut pending = vec![self.root.clone()];
+        while let Some(dir) = pending.pop() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for entry in entries {
+                let path = entry?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+                let key = path.strip_prefix(&self.root)?.to_string_lossy().replace('\\', "/");
+                if key.starts_with(prefix) {
+                    keys.push(key);
+                }
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn describe(&self) -> String {
+        self.root.display().to_string()
+    }
+}
+
+/// Files kept in memory, for library users who want the rendered data without writing it.
+#[derive(Default)]
+pub struct MemoryStorage {
+    files: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        MemoryStorage::default()
+    }
+
+    /// Takes every file out of the storage, keyed and ordered by key.
+    pub fn into_files(self) -> BTreeMap<String, Vec<u8>> {
+        self.files.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        files.insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(files.get(key).cloned())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        let mut files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        files.remove(key);
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(files.keys().filter(|key| key.starts_with(prefix)).cloned().collect())
+    }
+
+    fn describe(&self) -> String {
+        String::from("memory")
+    }
+}
+
+/// Opens the storage a destination names: `s3://<bucket>/<prefix>` for an S3-compatible bucket
+/// (see [`S3Storage::from_env`]), anything else for a directory.
+///
+/// # Arguments
+/// * `destination` - The bucket URL or directory.
+/// * `http` - The HTTP client to send requests to the bucket with.
+///
+/// # Returns
+/// A `Result` containing the storage, or an error if the bucket URL or its credentials are
+/// invalid.
+pub fn open(
+    destination: &str,
+    http: &reqwest::blocking::Client,
+) -> Result<Box<dyn Storage>, Box<dyn Error>> {
+    if destination.starts_with(S3_SCHEME) {
+        Ok(Box::new(S3Storage::from_env(destination, http.clone())?))
+    } else {
+        Ok(Box::new(FileStorage::new(destination)))
+    }
+}
+
+/// What an upload of an output directory did.
+pub struct UploadSummary {
+    /// Files copied to the storage, the manifest included.
+    pub uploaded: usize,
+    /// Files the storage already held in the same version.
+    pub unchanged: usize,
+    /// Files of an earlier upload that the output directory no longer has.
+    pub deleted: usize,
+}
+
+/// Mirrors an output directory to a storage: copies the files its manifest lists and the
+/// manifest itself, and removes the files an earlier upload copied that the manifest no longer
+/// lists.
+///
+/// The manifest is copied last, and the next upload compares against it: a file whose SHA-256
+/// matches the copied manifest's is not sent again, so only what a run changed is uploaded.
+/// Only files the copied manifest lists are ever removed, so other files under the storage's
+/// root are left alone. Every file to send is checked to exist before the first one is sent.
+///
+/// # Arguments
+/// * `storage` - Where the files are copied to.
+/// * `base_output_dir` - The root output directory, whose manifest is up to date.
+///
+/// # Returns
+/// A `Result` containing what was copied and removed, or the first error.
+pub fn upload(
+    storage: &dyn Storage,
+    base_output_dir: &str,
+) -> Result<UploadSummary, Box<dyn Error>> {
+    let manifest = Manifest::load(base_output_dir)?;
+    // A missing or unreadable remote manifest only means every file is sent
+    let remote: Manifest = storage
+        .get(MANIFEST_FILENAME)?
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default();
+
+    let files = manifest.files.iter().map(|(path, entry)| {
+        let stored = remote.files.get(path).map(|stored| stored.sha256.as_str());
+        (path, entry.sha256.as_str(), stored)
+    });
+    let images = manifest.images.iter().map(|(path, entry)| {
+        let stored = remote.images.get(path).map(|stored| stored.sha256.as_str());
+        (path, entry.sha256.as_str(), stored)
+    });
+    let (unchanged, changed): (Vec<_>, Vec<_>) =
+        files.chain(images).partition(|(_, sha256, stored)| *stored == Some(*sha256));
+    let root = Path::new(base_output_dir);
+    if let Some((path, _, _)) = changed.iter().find(|(path, _, _)| !root.join(path).is_file()) {
+        return Err(format!(
+            "Error: '{}' is listed in the manifest of '{}' but missing; nothing was uploaded. \
+             Run 'fab-scrapper combine' or fetch again to update the manifest.",
+            path, base_output_dir
+        )
+        .into());
+    }
+
+    let mut summary = UploadSummary { uploaded: 0, unchanged: unchanged.len(), deleted: 0 };
+    for (path, _, _) in changed {
+        storage.put(path, &fs::read(root.join(path))?)?;
+        summary.uploaded += 1;
+    }
+    // Removed before the manifest is replaced, so files left by a failed upload are retried
+    let stale_files = remote.files.keys().filter(|path| !manifest.files.contains_key(*path));
+    let stale_images = remote.images.keys().filter(|path| !manifest.images.contains_key(*path));
+    for path in stale_files.chain(stale_images) {
+        storage.delete(path)?;
+        summary.deleted += 1;
+    }
+    storage.put(MANIFEST_FILENAME, &fs::read(root.join(MANIFEST_FILENAME))?)?;
+    summary.uploaded += 1;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the files of an output directory and a manifest listing them.
+    fn write_output(dir: &Path, files: &[(&str, &str)]) {
+        let _ = fs::remove_dir_all(dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let mut manifest = Manifest::default();
+        manifest.record_files(dir.to_str().unwrap()).unwrap();
+        manifest.save(dir.to_str().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn upload_sends_changes_and_removes_files_no_longer_listed() {
+        let dir = std::env::temp_dir().join(format!("fab-scrapper-upload-{}", std::process::id()));
+        let out = dir.to_str().unwrap();
+        let storage = MemoryStorage::new();
+        storage.put("notes.txt", b"not from the output directory").unwrap();
+
+        write_output(&dir, &[("json/WTR_cards.json", "{}"), ("json/ARC_cards.json", "{}")]);
+        let summary = upload(&storage, out).unwrap();
+        assert_eq!((summary.uploaded, summary.unchanged, summary.deleted), (3, 0, 0));
+
+        write_output(&dir, &[("json/WTR_cards.json", "{}"), ("txt/WTR_cards.txt", "WTR")]);
+        let summary = upload(&storage, out).unwrap();
+        assert_eq!((summary.uploaded, summary.unchanged, summary.deleted), (2, 1, 1));
+        let keys = storage.list("").unwrap();
+        let expected = ["json/WTR_cards.json", "manifest.json", "notes.txt", "txt/WTR_cards.txt"];
+        assert_eq!(keys, expected);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn upload_sends_nothing_when_a_listed_file_is_missing() {
+        let dir = std::env::temp_dir().join(format!("fab-scrapper-missing-{}", std::process::id()));
+        write_output(&dir, &[("json/ARC_cards.json", "{}"), ("json/WTR_cards.json", "{}")]);
+        fs::remove_file(dir.join("json/WTR_cards.json")).unwrap();
+
+        let storage = MemoryStorage::new();
+        let error = upload(&storage, dir.to_str().unwrap()).err().unwrap();
+        assert!(error.to_string().contains("json/WTR_cards.json"), "{}", error);
+        assert!(storage.into_files().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,265 @@
+//! Compressed output files.
+//!
+//! With `--compress`, every per-set and combined file is written as `<name>.gz` or
+//! `<name>.zst` in place of `<name>`, and the other variants of the file are removed. Readers
+//! of the output directory go through [`read_to_string`], which finds whichever variant is on
+//! disk, so compressed and plain runs can follow each other in the same directory.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How output files are compressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Compression {
+    /// `.gz` files, readable everywhere
+    Gzip,
+    /// `.zst` files, smaller and faster to read back
+    Zstd,
+}
+
+impl Compression {
+    // Every compression, in the order variants of a file are looked up
+    pub const ALL: [Compression; 2] = [Compression::Gzip, Compression::Zstd];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// The extension appended to the names of compressed files.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// The compression of a file, judging by its extension.
+    pub fn of_path(path: &Path) -> Option<Compression> {
+        let extension = path.extension()?.to_str()?;
+        Compression::ALL.into_iter().find(|compression| compression.extension() == extension)
+    }
+
+    /// Compresses the contents of a file.
+    #[cfg(feature = "compression")]
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        use std::io::Write;
+
+        match self {
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            Compression::Zstd => Ok(zstd::encode_all(data, ZSTD_LEVEL)?),
+        }
+    }
+
+    #[cfg(not(feature = "compression"))]
+    pub fn compress(self, _data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        Err(FEATURE_MISSING.into())
+    }
+
+    /// Decompresses the contents of a file.
+    #[cfg(feature = "compression")]
+    pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        use std::io::Read;
+
+        match self {
+            Compression::Gzip => {
+                let mut decompressed = Vec::new();
+                flate2::read::GzDecoder::new(data).read_to_end(&mut decompressed)?;
+                Ok(decompressed)
+            }
+            Compression::Zstd => Ok(zstd::decode_all(data)?),
+        }
+    }
+
+    #[cfg(not(feature = "compression"))]
+    pub fn decompress(self, _data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        Err(FEATURE_MISSING.into())
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// zstd level of compressed files and archives; higher levels get slow on multi-GB archives
+#[cfg(feature = "compression")]
+pub const ZSTD_LEVEL: i32 = 9;
+
+#[cfg(not(feature = "compression"))]
+const FEATURE_MISSING: &str = "Compressed files need a build with the `compression` feature";
+
+/// Appends a compression's extension to a file name.
+pub fn compressed_name(filename: &str, compression: Option<Compression>) -> String {
+    match compression {
+        Some(compression) => format!("{}.{}", filename, compression.extension()),
+        None => filename.to_string(),
+    }
+}
+
+/// Removes the compression extension, if any, from a file name.
+pub fn strip_extension(file_name: &str) -> &str {
+    Compression::ALL
+        .into_iter()
+        .find_map(|compression| file_name.strip_suffix(&format!(".{}", compression.extension())))
+        .unwrap_or(file_name)
+}
+
+/// Finds the variant of a file that is on disk: the file itself, or it compressed.
+///
+/// # Arguments
+/// * `filename` - The path of the uncompressed file.
+///
+/// # Returns
+/// The path of the first variant that exists, or `None` if there is none.
+pub fn find(filename: &str) -> Option<PathBuf> {
+    std::iter::once(None)
+        .chain(Compression::ALL.map(Some))
+        .map(|compression| PathBuf::from(compressed_name(filename, compression)))
+        .find(|path| path.is_file())
+}
+
+/// Writes a file, compressed or not, replacing every other variant of it.
+///
+/// # Arguments
+/// * `filename` - The path of the uncompressed file.
+/// * `data` - The uncompressed contents.
+/// * `compression` - How to compress the file, if at all.
+///
+/// # Returns
+/// A `Result` containing the path written, or an error.
+pub fn write(
+    filename: &str,
+    data: &[u8],
+    compression: Option<Compression>,
+) -> Result<String, Box<dyn Error>> {
+    let written = compressed_name(filename, compression);
+    match compression {
+        Some(compression) => {
+            super::write_atomically(Path::new(&written), &compression.compress(data)?)?
+        }
+        None => super::write_atomically(Path::new(&written), data)?,
+    }
+    for other in std::iter::once(None).chain(Compression::ALL.map(Some)) {
+        let path = compressed_name(filename, other);
+        if path != written && Path::new(&path).exists() {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(written)
+}
+
+/// Reads a text file, decompressing it if needed.
+///
+/// # Arguments
+/// * `filename` - The path of a compressed file, or of an uncompressed file whose compressed
+///   variants are tried when it does not exist.
+///
+/// # Returns
+/// A `Result` containing the uncompressed text, or an error if no variant exists or it cannot
+/// be read.
+pub fn read_to_string(filename: &str) -> Result<String, Box<dyn Error>> {
+    let path = match Compression::of_path(Path::new(filename)) {
+        Some(_) => PathBuf::from(filename),
+        None => find(filename).ok_or_else(|| format!("{} not found", filename))?,
+    };
+    let content = fs::read(&path)?;
+    let content = match Compression::of_path(&path) {
+        Some(compression) => compression.decompress(&content)?,
+        None => content,
+    };
+    Ok(String::from_utf8(content)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for one test, removed before the test writes to it.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = format!("fab-scrapper-{}-{}", name, std::process::id());
+        let dir = std::env::temp_dir().join(dir);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn names_and_strips_compressed_variants() {
+        let name = |compression| compressed_name("WTR_cards.json", compression);
+        assert_eq!(name(Some(Compression::Gzip)), "WTR_cards.json.gz");
+        assert_eq!(name(Some(Compression::Zstd)), "WTR_cards.json.zst");
+        assert_eq!(name(None), "WTR_cards.json");
+        assert_eq!(strip_extension("WTR_cards.json.gz"), "WTR_cards.json");
+        assert_eq!(strip_extension("WTR_cards.json.zst"), "WTR_cards.json");
+        assert_eq!(strip_extension("WTR_cards.json"), "WTR_cards.json");
+        assert_eq!(Compression::of_path(Path::new("a.csv.zst")), Some(Compression::Zstd));
+        assert_eq!(Compression::of_path(Path::new("a.csv")), None);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn round_trips_every_compression() {
+        let dir = test_dir("compression");
+        let filename = dir.join("WTR_cards.json");
+        let filename = filename.to_str().unwrap();
+        let data = "{\"results\": [\"Snatch\"]}".repeat(100);
+        for compression in [Some(Compression::Gzip), Some(Compression::Zstd), None] {
+            let written = write(filename, data.as_bytes(), compression).unwrap();
+            assert_eq!(written, compressed_name(filename, compression));
+            if compression.is_some() {
+                assert!(fs::metadata(&written).unwrap().len() < data.len() as u64);
+            }
+            assert_eq!(find(filename), Some(PathBuf::from(&written)));
+            assert_eq!(strip_extension(&written), filename);
+            assert_eq!(read_to_string(filename).unwrap(), data);
+            assert_eq!(read_to_string(&written).unwrap(), data);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn write_removes_the_other_variants() {
+        let dir = test_dir("variants");
+        let filename = dir.join("WTR_cards.csv");
+        let filename = filename.to_str().unwrap();
+        for compression in [None, Some(Compression::Gzip), Some(Compression::Zstd)] {
+            fs::write(compressed_name(filename, compression), "stale").unwrap();
+        }
+
+        let written = write(filename, b"fresh", Some(Compression::Zstd)).unwrap();
+        let mut files: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["WTR_cards.csv.zst"]);
+        assert_eq!(read_to_string(&written).unwrap(), "fresh");
+
+        write(filename, b"plain", None).unwrap();
+        assert_eq!(find(filename), Some(PathBuf::from(filename)));
+        assert!(!Path::new(&written).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn find_reports_missing_files() {
+        let dir = test_dir("missing");
+        let filename = dir.join("ARC_cards.json");
+        let filename = filename.to_str().unwrap();
+        assert_eq!(find(filename), None);
+        assert!(read_to_string(filename).unwrap_err().to_string().contains("not found"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+//! On-disk cache of API responses, revalidated with their `ETag` and `Last-Modified` headers.
+//!
+//! Every cached page is a JSON file named after the SHA-256 hash of its URL, holding the body
+//! with the validators and `Cache-Control` lifetime it was sent with. A page still within its
+//! `max-age` is reused without a request; otherwise it is requested with `If-None-Match` and
+//! `If-Modified-Since`, and a 304 Not Modified answer reuses the cached body, so unchanged
+//! pages are never downloaded twice. Responses marked `no-store` are not cached.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::manifest::{self, Validators};
+
+// Folder of the cache inside the output directory, unless another one is given
+pub const HTTP_CACHE_DIR: &str = "http_cache";
+
+/// A response stored in the cache.
+#[derive(Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub url: String,
+    pub body: String,
+    #[serde(default)]
+    pub validators: Validators,
+    /// The API version the response declared in its headers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// When the response was received or last revalidated, in seconds since the Unix epoch.
+    pub stored_at: u64,
+    /// How long the response may be reused without revalidating it, from `Cache-Control`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+}
+
+impl CachedResponse {
+    /// Whether the response may still be reused without asking the server.
+    pub fn is_fresh(&self) -> bool {
+        self.max_age.is_some_and(|max_age| now() < self.stored_at.saturating_add(max_age))
+    }
+}
+
+/// What a `Cache-Control` header allows.
+pub struct CacheControl {
+    /// Whether the response may be stored at all (`no-store` forbids it).
+    pub store: bool,
+    /// How long the response may be reused without revalidating it; `no-cache` means never.
+    pub max_age: Option<u64>,
+}
+
+impl CacheControl {
+    /// Reads a `Cache-Control` header; without one, responses are stored and always
+    /// revalidated.
+    pub fn parse(header: Option<&str>) -> Self {
+        let mut control = CacheControl { store: true, max_age: None };
+        let mut no_cache = false;
+        for directive in header.unwrap_or_default().split(',') {
+            let directive = directive.trim().to_ascii_lowercase();
+            match directive.split_once('=') {
+                Some(("max-age", seconds)) => {
+                    control.max_age = seconds.trim_matches('"').parse().ok();
+                }
+                _ if directive == "no-store" => control.store = false,
+                _ if directive == "no-cache" => no_cache = true,
+                _ => {}
+            }
+        }
+        if no_cache {
+            control.max_age = None;
+        }
+        control
+    }
+}
+
+/// A cache directory, with counts of the responses it saved downloading or stored this run.
+pub struct HttpCache {
+    dir: PathBuf,
+    reused: AtomicUsize,
+    stored: AtomicUsize,
+}
+
+impl HttpCache {
+    /// Opens a cache directory, creating it if needed.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(HttpCache { dir, reused: AtomicUsize::new(0), stored: AtomicUsize::new(0) })
+    }
+
+    /// The cached response of a URL, if any. An unreadable entry counts as missing.
+    pub fn get(&self, url: &str) -> Option<CachedResponse> {
+        let content = fs::read(self.path(url)).ok()?;
+        serde_json::from_slice::<CachedResponse>(&content)
+            .ok()
+            .filter(|cached| cached.url == url)
+    }
+
+    /// Stores a response, replacing the previous one of its URL.
+    pub fn store(&self, response: &CachedResponse) -> Result<(), Box<dyn Error>> {
+        super::write_atomically(&self.path(&response.url), &serde_json::to_vec(response)?)?;
+        self.stored.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Counts a response served from the cache instead of being downloaded.
+    pub fn record_reuse(&self) {
+        self.reused.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Describes how often the cache was used, e.g. `HTTP cache: 12 page(s) reused, 3 stored`.
+    pub fn describe(&self) -> String {
+        format!(
+            "HTTP cache: {} page(s) reused, {} stored in {}",
+            self.reused.load(Ordering::Relaxed),
+            self.stored.load(Ordering::Relaxed),
+            self.dir.display()
+        )
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", manifest::sha256_hex(url.as_bytes())))
+    }
+}
+
+/// The current time, in seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(header: Option<&str>) -> (bool, Option<u64>) {
+        let control = CacheControl::parse(header);
+        (control.store, control.max_age)
+    }
+
+    fn response(url: &str, stored_at: u64, max_age: Option<u64>) -> CachedResponse {
+        CachedResponse {
+            url: url.to_string(),
+            body: String::from("{}"),
+            validators: Validators::default(),
+            version: None,
+            stored_at,
+            max_age,
+        }
+    }
+
+    #[test]
+    fn parses_cache_control() {
+        assert_eq!(parse(Some("max-age=60")), (true, Some(60)));
+        assert_eq!(parse(Some("public, MAX-AGE=\"60\"")), (true, Some(60)));
+        assert_eq!(parse(Some("no-cache, max-age=60")), (true, None));
+        assert_eq!(parse(Some("max-age=60, no-store")), (false, Some(60)));
+        assert_eq!(parse(Some("max-age=soon")), (true, None));
+        assert_eq!(parse(None), (true, None));
+    }
+
+    #[test]
+    fn is_fresh_until_max_age_has_passed() {
+        let now = now();
+        assert!(response("u", now, Some(60)).is_fresh());
+        // The response expires the second its max-age has passed
+        assert!(!response("u", now - 60, Some(60)).is_fresh());
+        assert!(!response("u", now - 61, Some(60)).is_fresh());
+        assert!(!response("u", now, Some(0)).is_fresh());
+        assert!(!response("u", now, None).is_fresh());
+        assert!(response("u", u64::MAX, Some(60)).is_fresh());
+    }
+
+    #[test]
+    fn get_only_returns_the_entry_of_the_same_url() {
+        let dir = std::env::temp_dir().join(format!("fab-scrapper-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = HttpCache::open(&dir).unwrap();
+        let wtr = "https://cards.fabtcg.com/api/search/v1/cards?set_code=WTR";
+        let arc = "https://cards.fabtcg.com/api/search/v1/cards?set_code=ARC";
+        cache.store(&response(wtr, 1, Some(60))).unwrap();
+        assert_eq!(cache.get(wtr).map(|cached| cached.url).as_deref(), Some(wtr));
+        assert!(cache.get(arc).is_none());
+
+        // An entry found under another URL's name, e.g. copied by hand, is not used
+        fs::copy(cache.path(wtr), cache.path(arc)).unwrap();
+        assert!(cache.get(arc).is_none());
+        fs::write(cache.path(wtr), "not json").unwrap();
+        assert!(cache.get(wtr).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,52 @@
+//! Exclusive lock on an output directory, so two runs never write to it at once.
+//!
+//! The lock is an advisory lock on a file inside the output directory, held for as long as
+//! the `OutputLock` lives. The operating system releases it when the process exits, so a
+//! crashed run never leaves the directory locked.
+
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use fs2::FileExt;
+
+// Name of the lock file inside the output directory
+pub const LOCK_FILENAME: &str = ".fab-scrapper.lock";
+
+/// Holds the lock on an output directory until dropped.
+pub struct OutputLock {
+    _file: File,
+}
+
+impl OutputLock {
+    /// Locks an output directory, creating it if needed.
+    ///
+    /// # Arguments
+    /// * `base_output_dir` - The root output directory.
+    ///
+    /// # Returns
+    /// A `Result` containing the lock, or an error if another run holds it.
+    pub fn acquire(base_output_dir: &str) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(base_output_dir)?;
+        let path = Path::new(base_output_dir).join(LOCK_FILENAME);
+        let mut file = OpenOptions::new().create(true).truncate(false).write(true).open(&path)?;
+        if FileExt::try_lock_exclusive(&file).is_err() {
+            let holder = fs::read_to_string(&path).unwrap_or_default();
+            let holder = match holder.trim() {
+                "" => String::new(),
+                pid => format!(" (process {})", pid),
+            };
+            return Err(format!(
+                "Error: Another run is writing to '{}'{}. Try again once it has finished.",
+                base_output_dir, holder
+            )
+            .into());
+        }
+
+        // The process id only tells users who holds the lock; the lock itself is on the file
+        file.set_len(0)?;
+        write!(file, "{}", std::process::id())?;
+        Ok(OutputLock { _file: file })
+    }
+}
@@ -0,0 +1,205 @@
+//! The output directory manifest, recording what each run fetched.
+//!
+//! Incremental runs use the stored HTTP validators and content hashes to skip sets whose
+//! data has not changed since the previous run. The manifest also lists every output file
+//! with its size and SHA-256, so the output directory can be checked for corruption.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::PARTIAL_SUFFIX;
+use super::archive::ARCHIVE_DIR;
+use super::http_cache::HTTP_CACHE_DIR;
+use super::lock::LOCK_FILENAME;
+use super::run_state::RUN_STATE_FILENAME;
+use super::search_index::SEARCH_INDEX_FILENAME;
+use crate::completeness;
+use crate::events::EVENT_LOG_FILENAME;
+use crate::images::IMAGES_DIR;
+use crate::report::RUN_REPORT_FILENAME;
+use crate::validate::VALIDATION_REPORT_FILENAME;
+
+// Name of the manifest file inside the output directory
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+
+/// HTTP validators returned by the API, sent back on conditional requests.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// What the manifest records about one set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetEntry {
+    #[serde(flatten)]
+    pub validators: Validators,
+    /// SHA-256 of the set's JSON data, as written to disk.
+    pub sha256: String,
+    pub card_count: usize,
+    /// Number of pages the set's listing took when it was last downloaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pages: Option<usize>,
+    /// When the set's data was last downloaded.
+    pub fetched_at: String,
+    /// Release date of a set fetched before its release, whose files are kept in `previews/`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview_until: Option<String>,
+}
+
+/// What the manifest records about one downloaded card image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageEntry {
+    pub url: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// What the manifest records about a download that stayed corrupt after every re-download.
+///
+/// It is not downloaded again while its source is the same, until the entry is removed from
+/// the manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorruptEntry {
+    /// What was downloaded: the URL of an image, or the SHA-256 of a set's JSON data.
+    pub source: String,
+    /// Why the last download was rejected.
+    pub reason: String,
+    /// When the download was given up on.
+    pub detected_at: String,
+}
+
+/// What the manifest records about one output file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub size: u64,
+    pub sha256: String,
+    /// When the file was last written, from its modification time.
+    pub written_at: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub sets: BTreeMap<String, SetEntry>,
+    /// Downloaded images, keyed by their path relative to the output directory.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub images: BTreeMap<String, ImageEntry>,
+    /// Every other output file, keyed by its path relative to the output directory.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, FileEntry>,
+    /// Sets whose written files stayed corrupt, keyed by set code.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub corrupt_sets: BTreeMap<String, CorruptEntry>,
+    /// Images that stayed corrupt, keyed by their path relative to the output directory.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub corrupt_images: BTreeMap<String, CorruptEntry>,
+}
+
+impl Manifest {
+    /// Loads the manifest of an output directory, or an empty one if there is none yet.
+    pub fn load(base_output_dir: &str) -> Result<Self, Box<dyn Error>> {
+        let path = Path::new(base_output_dir).join(MANIFEST_FILENAME);
+        if !path.exists() {
+            return Ok(Manifest::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Error: Invalid manifest {}: {}", path.display(), e).into())
+    }
+
+    /// Writes the manifest into the output directory.
+    pub fn save(&self, base_output_dir: &str) -> Result<(), Box<dyn Error>> {
+        let path = Path::new(base_output_dir).join(MANIFEST_FILENAME);
+        super::write_atomically(&path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    /// Lists every file of the output directory with its size and hash, replacing the
+    /// previous list. Files whose size and modification time are unchanged keep their hash
+    /// instead of being read again.
+    ///
+    /// Images (listed separately), unfinished `.part` files, and the files rewritten after
+    /// every run — this manifest, the run state, the event log, the completeness history and
+    /// the lock file — are left out.
+    ///
+    /// # Arguments
+    /// * `base_output_dir` - The root output directory.
+    ///
+    /// # Returns
+    /// A `Result` indicating success or an error if a file cannot be read.
+    pub fn record_files(&mut self, base_output_dir: &str) -> Result<(), Box<dyn Error>> {
+        let mut files = BTreeMap::new();
+        let mut pending = vec![Path::new(base_output_dir).to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                let relative = path
+                    .strip_prefix(base_output_dir)?
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                if is_untracked(&relative) {
+                    continue;
+                }
+                let metadata = fs::metadata(&path)?;
+                if metadata.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+                let written_at = DateTime::<Local>::from(metadata.modified()?).to_rfc3339();
+                let size = metadata.len();
+                let entry = match self.files.remove(&relative) {
+                    Some(entry) if entry.size == size && entry.written_at == written_at => entry,
+                    _ => FileEntry {
+                        size,
+                        sha256: sha256_hex(&fs::read(&path)?),
+                        written_at,
+                    },
+                };
+                files.insert(relative, entry);
+            }
+        }
+        self.files = files;
+        Ok(())
+    }
+}
+
+/// Whether a path relative to the output directory is left out of the manifest's file list.
+fn is_untracked(relative: &str) -> bool {
+    let bookkeeping = [
+        MANIFEST_FILENAME,
+        RUN_STATE_FILENAME,
+        EVENT_LOG_FILENAME,
+        completeness::HISTORY_FILENAME,
+        LOCK_FILENAME,
+        SEARCH_INDEX_FILENAME,
+        RUN_REPORT_FILENAME,
+        VALIDATION_REPORT_FILENAME,
+    ];
+    relative == IMAGES_DIR
+        || relative == HTTP_CACHE_DIR
+        || relative == ARCHIVE_DIR
+        || relative.ends_with(PARTIAL_SUFFIX)
+        || bookkeeping.contains(&relative)
+}
+
+/// Returns the lowercase hex SHA-256 digest of `data`.
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
@@ -0,0 +1,129 @@
+//! The master card index: every card once, with all of its printings across sets.
+//!
+//! A reprinted card appears in the file of every set it was printed in, each time listing
+//! some or all of its printings. `master_index.json` merges them into a single entry per card
+//! id, ordered by id, so the full printing history of a card can be read in one place:
+//!
+//! ```json
+//! {"wtr-card-1": {"name": "...", "sets": ["WTR", "1HP"], "printings": [
+//!   {"print_id": "WTR001", "set_code": "WTR", "number": "001", "rarity": "C", ...}]}}
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::model::{Printing, SetResponse};
+
+// Name of the index file inside the output directory
+pub const MASTER_INDEX_FILENAME: &str = "master_index.json";
+
+/// A card of the master index, with every distinct printing of it.
+#[derive(Debug, Clone, Serialize)]
+pub struct MasterCard {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch: Option<String>,
+    pub types: Vec<String>,
+    /// Codes of the sets the card was printed in, ordered by code.
+    pub sets: Vec<String>,
+    pub printings: Vec<MasterPrinting>,
+}
+
+/// One printing of a card: where it was printed and how it looks.
+#[derive(Debug, Clone, Serialize)]
+pub struct MasterPrinting {
+    pub print_id: String,
+    pub set_code: String,
+    /// The collector number, the digits ending the print id (e.g. "001").
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rarity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub foiling: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edition: Option<String>,
+    pub artists: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+}
+
+impl MasterPrinting {
+    fn new(printing: &Printing, file_set_code: &str) -> Self {
+        let set_code = printing.set_code.as_deref().unwrap_or(file_set_code).to_uppercase();
+        MasterPrinting {
+            print_id: printing.print_id.clone(),
+            set_code,
+            number: printing.collector_number().map(str::to_string),
+            rarity: printing.rarity.clone(),
+            foiling: printing.foiling.clone(),
+            edition: printing.edition.clone(),
+            artists: printing.artists.clone(),
+            image_url: printing.image_url.clone(),
+        }
+    }
+
+    /// What makes two listings the same printing: the same print id in the same finish.
+    fn key(&self) -> (&str, &str, Option<&str>, Option<&str>) {
+        (&self.set_code, &self.print_id, self.foiling.as_deref(), self.edition.as_deref())
+    }
+}
+
+/// Builds the master index of the given sets.
+///
+/// # Arguments
+/// * `sets` - The set codes and cards to index. A card's name, pitch and types are taken from
+///   the first set listing it; its printings are merged from all of them, each distinct print
+///   id, foiling and edition once.
+///
+/// # Returns
+/// The cards keyed by card id.
+pub fn build<'a>(
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+) -> BTreeMap<String, MasterCard> {
+    let mut cards: BTreeMap<String, MasterCard> = BTreeMap::new();
+    for (set_code, set) in sets {
+        for card in &set.results {
+            let entry = cards.entry(card.card_id.clone()).or_insert_with(|| MasterCard {
+                name: card.name.clone(),
+                pitch: card.pitch.clone(),
+                types: card.types.clone(),
+                sets: Vec::new(),
+                printings: Vec::new(),
+            });
+            for printing in &card.printings {
+                entry.printings.push(MasterPrinting::new(printing, set_code));
+            }
+        }
+    }
+    for card in cards.values_mut() {
+        card.printings.sort_by(|a, b| a.key().cmp(&b.key()));
+        card.printings.dedup_by(|a, b| a.key() == b.key());
+        let sets: BTreeSet<&str> =
+            card.printings.iter().map(|printing| printing.set_code.as_str()).collect();
+        card.sets = sets.into_iter().map(str::to_string).collect();
+    }
+    cards
+}
+
+/// Writes the master index of the given sets into the output directory.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `sets` - The set codes and cards to index.
+///
+/// # Returns
+/// A `Result` containing the number of cards and of printings indexed, or an error.
+pub fn write<'a>(
+    base_output_dir: &str,
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+) -> Result<(usize, usize), Box<dyn Error>> {
+    let cards = build(sets);
+    let printings = cards.values().map(|card| card.printings.len()).sum();
+    let path = Path::new(base_output_dir).join(MASTER_INDEX_FILENAME);
+    super::write_atomically(&path, serde_json::to_string_pretty(&cards)?.as_bytes())?;
+    Ok((cards.len(), printings))
+}
@@ -0,0 +1,808 @@
+//! Persistence of fetched card data in the output directory.
+//!
+//! Every set is written to `<out>/<format>/<SET>_cards.<format>` for each per-set format, and
+//! all sets together to a combined file per format, either of them possibly compressed (see
+//! [`compression`]). The manifest and run state recording
+//! what was fetched, the snapshot of what the latest run replaced, the card name, search and
+//! master indexes, and the lock keeping two runs from writing at once, live next to them.
+
+pub mod archive;
+pub mod backend;
+pub mod compression;
+pub mod http_cache;
+pub mod lock;
+pub mod manifest;
+pub mod master_index;
+pub mod name_index;
+pub mod run_state;
+pub mod s3;
+pub mod search_index;
+pub mod snapshot;
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::events::{Event, EventLog};
+use crate::export;
+use crate::model::SetResponse;
+use crate::template::CardTemplate;
+use compression::Compression;
+
+// Size assumed for a set that has no output from a previous run yet
+const DEFAULT_SET_SIZE_ESTIMATE: u64 = 2 * 1024 * 1024;
+
+// Size assumed for a card image while the manifest records none to average over
+const DEFAULT_IMAGE_SIZE_ESTIMATE: u64 = 200 * 1024;
+
+// Number of cards assumed for a set that has no output from a previous run yet
+const DEFAULT_SET_CARDS_ESTIMATE: u64 = 250;
+
+// Extra headroom required on top of the estimate, in percent
+const DISK_SPACE_MARGIN_PERCENT: u64 = 20;
+
+// Folder filtered fetches are written to, inside the output directory
+pub const FILTERED_DIR: &str = "filtered";
+
+// Folder the sets fetched before their release are written to, inside the output directory
+pub const PREVIEWS_DIR: &str = "previews";
+
+// Suffix of the temporary file a file is written to before being renamed into place
+pub const PARTIAL_SUFFIX: &str = ".part";
+
+// Name of the combined JSON file of a `--lang` fetch, keyed by language
+pub const ALL_LANGUAGES_FILENAME: &str = "all_languages_combined";
+
+/// A file format the fetched data can be written in.
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Json,
+    Txt,
+    /// One row per printing, for spreadsheets and dataframes
+    Csv,
+    /// One card per line (JSON Lines), for streaming into jq, DuckDB or BigQuery
+    Ndjson,
+    /// A SQLite database (`cards.db`) mirroring every set, updated in place on each run
+    Sqlite,
+    /// A Parquet file (`printings.parquet`) of every printing with typed columns, for DuckDB
+    /// and Spark
+    Parquet,
+}
+
+impl OutputFormat {
+    /// Name of the format's subdirectory and file extension.
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Txt => "txt",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Sqlite => "sqlite",
+            OutputFormat::Parquet => "parquet",
+        }
+    }
+
+    /// Whether files of this format hold the set's JSON data as fetched.
+    pub fn holds_json(self) -> bool {
+        matches!(self, OutputFormat::Json)
+    }
+
+    /// Whether the format writes one file per set, rather than a single file for all sets.
+    pub fn per_set_files(self) -> bool {
+        !matches!(self, OutputFormat::Sqlite | OutputFormat::Parquet)
+    }
+
+    /// Whether `--compress` applies to the format; binary formats are never compressed.
+    pub fn compressible(self) -> bool {
+        !matches!(self, OutputFormat::Sqlite | OutputFormat::Parquet)
+    }
+}
+
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Reads set codes from the specified file, one code per line.
+///
+/// # Arguments
+/// * `filename` - The path to the file containing set codes.
+///
+/// # Returns
+/// A `Result` containing a vector of set codes if successful, or an error.
+pub fn read_set_codes(filename: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    // Check if the file exists
+    if !Path::new(filename).exists() {
+        return Err(format!("Error: Input file '{}' not found. Please create it in the project root.", filename).into());
+    }
+
+    let file = File::open(filename)?;
+    let reader = BufReader::new(file);
+    let mut codes = Vec::new();
+
+    for line in reader.lines() {
+        let line_content = line?.trim().to_string();
+        if !line_content.is_empty() {
+            codes.push(line_content);
+        }
+    }
+    Ok(codes)
+}
+
+/// Writes set codes to a file, one code per line, replacing its previous contents.
+///
+/// # Arguments
+/// * `filename` - The path of the set codes file.
+/// * `codes` - The set codes to write.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+pub fn write_set_codes(filename: &str, codes: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut content = codes.join("\n");
+    content.push('\n');
+    save_data_to_file(filename, &content)
+}
+
+/// Saves the provided data string to a file.
+///
+/// # Arguments
+/// * `filename` - The name of the file to save the data to.
+/// * `data` - The string data to write to the file.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+pub fn save_data_to_file(filename: &str, data: &str) -> Result<(), Box<dyn Error>> {
+    write_atomically(Path::new(filename), data.as_bytes())
+}
+
+/// Writes a file through a temporary `<path>.part` file that is renamed into place, so a
+/// crash mid-write never leaves a truncated file behind. The partial file is removed when
+/// writing or renaming it fails.
+///
+/// # Arguments
+/// * `path` - The file to write.
+/// * `data` - The file contents.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+pub fn write_atomically(path: &Path, data: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut partial_path = path.as_os_str().to_os_string();
+    partial_path.push(PARTIAL_SUFFIX);
+    let mut file = File::create(&partial_path)?;
+    let written = file
+        .write_all(data)
+        .and_then(|_| file.sync_all())
+        .and_then(|_| fs::rename(&partial_path, path));
+    if let Err(e) = written {
+        drop(file);
+        let _ = fs::remove_file(&partial_path);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Estimates the disk space a run will need, in bytes.
+///
+/// Each set is sized from its JSON output of a previous run when one exists, falling back
+/// to `DEFAULT_SET_SIZE_ESTIMATE`. Every set is written once per output format and appears
+/// once more per format in the combined files, plus a safety margin.
+///
+/// # Arguments
+/// * `set_codes` - The set codes that will be fetched.
+/// * `base_output_dir` - The root output directory.
+/// * `formats` - The output formats of the run.
+///
+/// # Returns
+/// The estimated number of bytes required.
+pub fn estimate_required_space(
+    set_codes: &[String],
+    base_output_dir: &str,
+    formats: &[OutputFormat],
+) -> u64 {
+    let per_copy: u64 = set_codes
+        .iter()
+        .map(|set_code| {
+            let previous = set_filename(base_output_dir, OutputFormat::Json, set_code);
+            compression::find(&previous)
+                .and_then(|path| fs::metadata(path).ok())
+                .map(|metadata| metadata.len())
+                .unwrap_or(DEFAULT_SET_SIZE_ESTIMATE)
+        })
+        .sum();
+
+    let total = per_copy * 2 * formats.len() as u64;
+    total + total * DISK_SPACE_MARGIN_PERCENT / 100
+}
+
+/// Estimates the disk space the card images of a run will need, in bytes.
+///
+/// Only the images not downloaded yet are counted, sized from the average image the manifest
+/// records, or `DEFAULT_IMAGE_SIZE_ESTIMATE` while it records none. A set without a previous
+/// output is assumed to hold `DEFAULT_SET_CARDS_ESTIMATE` cards, plus a safety margin.
+///
+/// # Arguments
+/// * `set_codes` - The set codes that will be fetched.
+/// * `base_output_dir` - The root output directory.
+/// * `manifest` - The manifest of the output directory.
+///
+/// # Returns
+/// The estimated number of bytes required.
+pub fn estimate_image_space(
+    set_codes: &[String],
+    base_output_dir: &str,
+    manifest: &manifest::Manifest,
+) -> u64 {
+    let average_size = match manifest.images.len() as u64 {
+        0 => DEFAULT_IMAGE_SIZE_ESTIMATE,
+        count => manifest.images.values().map(|image| image.size).sum::<u64>() / count,
+    };
+    let missing: u64 = set_codes
+        .iter()
+        .map(|set_code| {
+            let previous = read_existing_set(base_output_dir, set_code)
+                .ok()
+                .and_then(|json| serde_json::from_str::<SetResponse>(&json).ok());
+            let Some(set) = previous else {
+                return DEFAULT_SET_CARDS_ESTIMATE;
+            };
+            let mut seen = std::collections::HashSet::new();
+            set.results
+                .iter()
+                .filter(|card| seen.insert(card.card_id.as_str()))
+                .filter(|card| {
+                    crate::images::image_on_disk(base_output_dir, set_code, card) == Some(false)
+                })
+                .count() as u64
+        })
+        .sum();
+
+    let total = missing * average_size;
+    total + total * DISK_SPACE_MARGIN_PERCENT / 100
+}
+
+/// Verifies that the filesystem holding `dir` has at least `required` bytes free.
+///
+/// # Arguments
+/// * `dir` - An existing directory on the target filesystem.
+/// * `required` - The number of bytes the run is expected to write.
+///
+/// # Returns
+/// A `Result` indicating enough space is available, or an error describing the shortfall.
+pub fn check_disk_space(dir: &str, required: u64) -> Result<(), Box<dyn Error>> {
+    let available = fs2::available_space(dir)?;
+    if available < required {
+        return Err(format!(
+            "Error: Not enough disk space in '{}': about {} MiB required, only {} MiB available.",
+            dir,
+            required.div_ceil(1024 * 1024),
+            available / (1024 * 1024)
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Checks that a written set file can be read back, decompressed if needed, holds exactly the
+/// data that was written and parses into the typed model.
+///
+/// # Arguments
+/// * `filename` - The path of the file to verify.
+/// * `expected_sha256` - The SHA-256 of the JSON data written to the file.
+///
+/// # Returns
+/// A `Result` indicating the file is valid, or what is wrong with it.
+pub fn verify_json_file(filename: &str, expected_sha256: &str) -> Result<(), Box<dyn Error>> {
+    let content = compression::read_to_string(filename)?;
+    let sha256 = manifest::sha256_hex(content.as_bytes());
+    if sha256 != expected_sha256 {
+        return Err(format!(
+            "the file does not hold the data written (SHA-256 {} instead of {})",
+            sha256, expected_sha256
+        )
+        .into());
+    }
+    serde_json::from_str::<SetResponse>(&content)?;
+    Ok(())
+}
+
+/// Renders a set's data in the given format.
+///
+/// # Arguments
+/// * `format` - The output format.
+/// * `set_code` - The set code (e.g., "WTR").
+/// * `json_content` - The set's JSON data, written as-is by the JSON format.
+/// * `set` - The set's typed data, used by the other formats.
+/// * `template` - The template of every card in the text listing.
+///
+/// # Returns
+/// A `Result` containing the file contents, or an error.
+pub fn render_set<'a>(
+    format: OutputFormat,
+    set_code: &str,
+    json_content: &'a str,
+    set: &SetResponse,
+    template: &CardTemplate,
+) -> Result<Cow<'a, str>, Box<dyn Error>> {
+    match format {
+        OutputFormat::Json => Ok(Cow::Borrowed(json_content)),
+        OutputFormat::Txt => Ok(Cow::Owned(template.render_set(set_code, set))),
+        OutputFormat::Csv => Ok(Cow::Owned(export::csv::render([(set_code, set)])?)),
+        OutputFormat::Ndjson => Ok(Cow::Owned(export::ndjson::render([(set_code, set)])?)),
+        OutputFormat::Sqlite => Err("SQLite output is only written for all sets at once".into()),
+        OutputFormat::Parquet => {
+            Err("Parquet output is only written for all sets at once".into())
+        }
+    }
+}
+
+/// Builds the path of a set's output file for the given format.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `format` - The output format, which also names the subdirectory.
+/// * `set_code` - The set code (e.g., "WTR").
+pub fn set_filename(base_output_dir: &str, format: OutputFormat, set_code: &str) -> String {
+    format!("{}/{}/{}_cards.{}", base_output_dir, format, set_code.trim(), format)
+}
+
+/// A per-set output file found in an output directory.
+pub struct SetFile {
+    pub set_code: String,
+    /// The language folder of a `--lang` fetch the file is in, if any.
+    pub language: Option<String>,
+    pub path: PathBuf,
+}
+
+/// Lists the per-set files of a format in an output directory, including those in the
+/// language folders of `--lang` fetches.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `format` - The format whose folder is listed.
+///
+/// # Returns
+/// A `Result` containing the files ordered by path, or an error if the folder cannot be read.
+/// A format without a folder has no files.
+pub fn list_set_files(
+    base_output_dir: &str,
+    format: OutputFormat,
+) -> Result<Vec<SetFile>, Box<dyn Error>> {
+    let format_dir = Path::new(base_output_dir).join(format.name());
+    let suffix = format!("_cards.{}", format);
+    let mut files = Vec::new();
+    if !format_dir.is_dir() {
+        return Ok(files);
+    }
+    let mut dirs = vec![(format_dir, None)];
+    while let Some((dir, language)) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if path.is_dir() {
+                // Language folders hold files of their own, but never further folders
+                if language.is_none() {
+                    dirs.push((path.clone(), Some(file_name.to_string())));
+                }
+            } else if let Some(set_code) =
+                compression::strip_extension(file_name).strip_suffix(&suffix)
+            {
+                let set_code = set_code.to_string();
+                files.push(SetFile { set_code, language: language.clone(), path });
+            }
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Builds the path of a set's output file in one language of a `--lang` fetch.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `format` - The output format, which also names the subdirectory.
+/// * `language` - The language code (e.g., "fr"), which names the folder inside it.
+/// * `set_code` - The set code (e.g., "WTR").
+pub fn localized_set_filename(
+    base_output_dir: &str,
+    format: OutputFormat,
+    language: &str,
+    set_code: &str,
+) -> String {
+    format!("{}/{}/{}/{}_cards.{}", base_output_dir, format, language, set_code.trim(), format)
+}
+
+/// Builds the path of the file holding every set in one language of a `--lang` fetch, or
+/// every language for the JSON-based formats.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `format` - The output format.
+/// * `language` - The language code (e.g., "fr"); ignored by the JSON-based formats.
+pub fn localized_combined_filename(
+    base_output_dir: &str,
+    format: OutputFormat,
+    language: &str,
+) -> String {
+    if format.holds_json() {
+        format!("{}/{}/{}.{}", base_output_dir, format, ALL_LANGUAGES_FILENAME, format)
+    } else {
+        let name = Path::new(&combined_filename(base_output_dir, format))
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        format!("{}/{}/{}/{}", base_output_dir, format, language, name)
+    }
+}
+
+/// Builds the path of a filtered fetch's output file for the given format.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `format` - The output format.
+/// * `name` - The name of the filtered fetch.
+pub fn filtered_filename(base_output_dir: &str, format: OutputFormat, name: &str) -> String {
+    format!("{}/{}/{}.{}", base_output_dir, FILTERED_DIR, name, format)
+}
+
+/// Builds the path of the file holding every set for the given format.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `format` - The output format.
+pub fn combined_filename(base_output_dir: &str, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Sqlite => format!("{}/{}", base_output_dir, export::DATABASE_FILENAME),
+        OutputFormat::Parquet => format!("{}/{}", base_output_dir, export::PARQUET_FILENAME),
+        OutputFormat::Ndjson => {
+            format!("{}/{}/{}", base_output_dir, format, export::ndjson::ALL_CARDS_FILENAME)
+        }
+        _ => format!("{}/{}/all_sets_combined.{}", base_output_dir, format, format),
+    }
+}
+
+/// Describes where the files of a format end up, for the metadata file and run summary.
+pub fn output_location(base_output_dir: &str, format: OutputFormat) -> String {
+    if format.per_set_files() {
+        format!("{} files: {}/{}/", format.name().to_uppercase(), base_output_dir, format)
+    } else {
+        let kind = if format == OutputFormat::Sqlite { "database" } else { "file" };
+        let filename = combined_filename(base_output_dir, format);
+        format!("{} {}: {}", format.name().to_uppercase(), kind, filename)
+    }
+}
+
+/// Creates the output directory and one subdirectory per requested per-set format.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `formats` - The output formats of the run.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+pub fn create_output_dirs(
+    base_output_dir: &str,
+    formats: &[OutputFormat],
+) -> Result<(), Box<dyn Error>> {
+    if !Path::new(base_output_dir).exists() {
+        fs::create_dir_all(base_output_dir)?;
+        debug!("Created base output directory: {}", base_output_dir);
+    }
+    for format in formats.iter().filter(|format| format.per_set_files()) {
+        let format_dir = format!("{}/{}", base_output_dir, format);
+        if !Path::new(&format_dir).exists() {
+            fs::create_dir(&format_dir)?;
+            debug!("Created {} output directory: {}", format, format_dir);
+        }
+    }
+    Ok(())
+}
+
+/// Writes the combined files holding every set, one per requested format.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `formats` - The output formats of the run.
+/// * `all_sets_data` - The JSON data of each set, keyed by set code.
+/// * `pretty` - Whether to indent the combined JSON file.
+/// * `compression` - How to compress the combined files; the database is never compressed.
+/// * `template` - The template of every card in the combined text listing.
+/// * `events` - Receives an event for every file written.
+///
+/// # Returns
+/// A description of every set left out of the combined files because its data is invalid, and
+/// of every combined file that could not be written.
+pub fn write_combined_files(
+    base_output_dir: &str,
+    formats: &[OutputFormat],
+    all_sets_data: &HashMap<String, String>,
+    pretty: bool,
+    compression: Option<Compression>,
+    template: &CardTemplate,
+    events: &EventLog,
+) -> Vec<String> {
+    info!("Creating combined files with all sets data...");
+
+    // A set that does not parse is left out rather than corrupting every combined file
+    let (sets, mut failures) = validate_sets(all_sets_data);
+    let sets = || sets.iter().map(|(set_code, set)| (*set_code, set));
+
+    for format in formats {
+        let combined_filename = combined_filename(base_output_dir, *format);
+        let save = |content: String| {
+            compression::write(&combined_filename, content.as_bytes(), compression)
+        };
+        let saved = match format {
+            OutputFormat::Json => render_combined_json(sets(), pretty).and_then(save),
+            OutputFormat::Txt => save(template.render_sets(sets())),
+            OutputFormat::Csv => export::csv::render(sets()).and_then(save),
+            OutputFormat::Ndjson => export::ndjson::render(sets()).and_then(save),
+            #[cfg(feature = "sqlite")]
+            OutputFormat::Sqlite => export::sqlite::write(&combined_filename, sets())
+                .map(|()| combined_filename.clone()),
+            #[cfg(not(feature = "sqlite"))]
+            OutputFormat::Sqlite => Err("SQLite output needs a build with the `sqlite` feature".into()),
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => export::parquet::write(&combined_filename, sets())
+                .map(|()| combined_filename.clone()),
+            #[cfg(not(feature = "parquet"))]
+            OutputFormat::Parquet => {
+                Err("Parquet output needs a build with the `parquet` feature".into())
+            }
+        };
+        match saved {
+            Err(e) => failures.push(format!(
+                "Could not save combined {} file {}: {}",
+                format, combined_filename, e
+            )),
+            Ok(written) => {
+                info!("Successfully saved combined {} file: {}", format, written);
+                events.record(Event::FileWritten { path: &written });
+            }
+        }
+    }
+    failures
+}
+
+/// Writes sets to a storage in the given formats, under the keys of the output directory
+/// layout (`json/WTR_cards.json`, `csv/all_sets_combined.csv`, `printings.parquet`, ...), so
+/// library users get the same files without writing anything to disk.
+///
+/// # Arguments
+/// * `storage` - Where the files are written.
+/// * `sets` - Pairs of set code and the set's cards.
+/// * `formats` - The formats to write; SQLite is not supported, as a database needs a file.
+/// * `template` - The template of every card in the text listings.
+/// * `pretty` - Whether to indent the JSON files.
+///
+/// # Returns
+/// A `Result` containing the keys written, or the first error.
+pub fn store_sets(
+    storage: &dyn backend::Storage,
+    sets: &[(&str, &SetResponse)],
+    formats: &[OutputFormat],
+    template: &CardTemplate,
+    pretty: bool,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    // The layout's paths, relative to the root of the storage
+    let key = |path: String| path.trim_start_matches('/').to_string();
+    let mut written = Vec::new();
+    let mut put = |key: String, data: &[u8]| -> Result<(), Box<dyn Error>> {
+        storage.put(&key, data)?;
+        written.push(key);
+        Ok(())
+    };
+    for format in formats {
+        if format.per_set_files() {
+            for (set_code, set) in sets {
+                let json = if pretty {
+                    serde_json::to_string_pretty(set)?
+                } else {
+                    serde_json::to_string(set)?
+                };
+                let content = render_set(*format, set_code, &json, set, template)?;
+                put(key(set_filename("", *format, set_code)), content.as_bytes())?;
+            }
+        }
+        let sets = || sets.iter().copied();
+        let combined = match format {
+            OutputFormat::Json => render_combined_json(sets(), pretty)?.into_bytes(),
+            OutputFormat::Txt => template.render_sets(sets()).into_bytes(),
+            OutputFormat::Csv => export::csv::render(sets())?.into_bytes(),
+            OutputFormat::Ndjson => export::ndjson::render(sets())?.into_bytes(),
+            OutputFormat::Sqlite => {
+                return Err("SQLite output needs a file and cannot be written to a storage".into());
+            }
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => export::parquet::render(sets())?,
+            #[cfg(not(feature = "parquet"))]
+            OutputFormat::Parquet => {
+                return Err("Parquet output needs a build with the `parquet` feature".into());
+            }
+        };
+        put(key(combined_filename("", *format)), &combined)?;
+    }
+    Ok(written)
+}
+
+/// Renders the combined JSON file: an object mapping every set code to the set's data.
+///
+/// # Arguments
+/// * `sets` - The set codes and typed data of every set; they are written ordered by code.
+/// * `pretty` - Whether to indent the output.
+///
+/// # Returns
+/// A `Result` containing the JSON text, or a serialization error.
+pub fn render_combined_json<'a>(
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+    pretty: bool,
+) -> Result<String, Box<dyn Error>> {
+    let combined: BTreeMap<&str, &SetResponse> = sets.into_iter().collect();
+    if pretty {
+        Ok(serde_json::to_string_pretty(&combined)?)
+    } else {
+        Ok(serde_json::to_string(&combined)?)
+    }
+}
+
+/// Parses the JSON data of every set, ordered by set code, leaving out the sets that do not
+/// parse.
+///
+/// # Arguments
+/// * `all_sets_data` - The JSON data of each set, keyed by set code.
+///
+/// # Returns
+/// The set codes and typed data of the valid sets, and a description of every invalid one.
+pub fn validate_sets(
+    all_sets_data: &HashMap<String, String>,
+) -> (Vec<(&str, SetResponse)>, Vec<String>) {
+    let mut sets = Vec::new();
+    let mut failures = Vec::new();
+    for (set_code, json_data) in all_sets_data {
+        match serde_json::from_str::<SetResponse>(json_data) {
+            Ok(set) => sets.push((set_code.as_str(), set)),
+            Err(e) => failures.push(format!(
+                "Leaving set {} out of the combined files: its data is invalid ({})",
+                set_code, e
+            )),
+        }
+    }
+    sets.sort_by_key(|(set_code, _)| *set_code);
+    failures.sort();
+    (sets, failures)
+}
+
+/// Parses the JSON data of every set, ordered by set code.
+///
+/// # Arguments
+/// * `all_sets_data` - The JSON data of each set, keyed by set code.
+///
+/// # Returns
+/// A `Result` containing the set codes and typed data, or an error if a set does not parse.
+pub fn parse_all_sets(
+    all_sets_data: &HashMap<String, String>,
+) -> Result<Vec<(&str, SetResponse)>, Box<dyn Error>> {
+    let mut sets = Vec::new();
+    for (set_code, json_data) in all_sets_data {
+        sets.push((set_code.as_str(), serde_json::from_str::<SetResponse>(json_data)?));
+    }
+    sets.sort_by_key(|(set_code, _)| *set_code);
+    Ok(sets)
+}
+
+/// Reads the JSON data of a set written by a previous run.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `set_code` - The set code (e.g., "WTR").
+///
+/// # Returns
+/// A `Result` containing the set's JSON data, or an error if no JSON output exists.
+pub fn read_existing_set(base_output_dir: &str, set_code: &str) -> Result<String, Box<dyn Error>> {
+    compression::read_to_string(&set_filename(base_output_dir, OutputFormat::Json, set_code))
+        .map_err(|_| format!("no JSON output of set {} found", set_code).into())
+}
+
+/// A set read back from the JSON output of an earlier run.
+pub struct LocalSet {
+    pub set_code: String,
+    pub json: String,
+    pub set: SetResponse,
+}
+
+/// Loads one set previously fetched into an output directory.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `set_code` - The set code (e.g., "WTR").
+///
+/// # Returns
+/// A `Result` containing the set, or an error if it has no JSON output or it does not parse.
+pub fn load_fetched_set(base_output_dir: &str, set_code: &str) -> Result<LocalSet, Box<dyn Error>> {
+    let json = read_existing_set(base_output_dir, set_code)?;
+    let set = serde_json::from_str(&json)
+        .map_err(|e| format!("Error: Invalid data of set {}: {}", set_code, e))?;
+    Ok(LocalSet { set_code: set_code.to_string(), json, set })
+}
+
+/// Loads every set previously fetched into an output directory, ordered by set code.
+///
+/// Files that no longer parse into the typed model are reported and skipped.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+///
+/// # Returns
+/// A `Result` containing the loaded sets, or an error if there are none.
+pub fn load_fetched_sets(base_output_dir: &str) -> Result<Vec<LocalSet>, Box<dyn Error>> {
+    let json_output_dir = format!("{}/{}", base_output_dir, OutputFormat::Json);
+    if !Path::new(&json_output_dir).exists() {
+        return Err(format!(
+            "Error: No fetched sets found in '{}'. Run 'fab-scrapper fetch' first.",
+            json_output_dir
+        )
+        .into());
+    }
+
+    let mut local_sets = Vec::new();
+    for entry in fs::read_dir(&json_output_dir)? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Some(set_code) = compression::strip_extension(file_name).strip_suffix("_cards.json")
+        else {
+            continue;
+        };
+
+        // Only use sets that still parse into the typed model
+        let json = compression::read_to_string(&path.to_string_lossy())?;
+        match serde_json::from_str::<SetResponse>(&json) {
+            Ok(set) => local_sets.push(LocalSet {
+                set_code: set_code.to_string(),
+                json,
+                set,
+            }),
+            Err(e) => warn!("Skipping {}: {}", path.display(), e),
+        }
+    }
+
+    if local_sets.is_empty() {
+        return Err(format!("Error: No set files found in '{}'.", json_output_dir).into());
+    }
+    local_sets.sort_by(|a, b| a.set_code.cmp(&b.set_code));
+    Ok(local_sets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_atomic_write_leaves_no_partial_file() {
+        let dir = std::env::temp_dir().join(format!("fab-scrapper-atomic-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        // A directory in place of the file makes the final rename fail
+        let path = dir.join("WTR_cards.json");
+        fs::create_dir_all(path.join("occupied")).unwrap();
+
+        assert!(write_atomically(&path, b"{}").is_err());
+        let mut partial_path = path.as_os_str().to_os_string();
+        partial_path.push(PARTIAL_SUFFIX);
+        assert!(!Path::new(&partial_path).exists());
+
+        write_atomically(&dir.join("ARC_cards.json"), b"{}").unwrap();
+        assert_eq!(fs::read_to_string(dir.join("ARC_cards.json")).unwrap(), "{}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
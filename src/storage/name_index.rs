@@ -0,0 +1,141 @@
+//! A compact index from card names to card ids, written next to the fetched data.
+//!
+//! `name_index.bin` is a finite state transducer set (the `fst` crate) with one key per card
+//! and set, `<lowercase name>\0<name>\0<card id>\0<set code>`, sorted by name. It is memory
+//! mapped when opened, so names resolve without reading or parsing the card data, and only
+//! the sets holding the resolved card need to be loaded afterwards.
+
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+use fst::{IntoStreamer, Set, Streamer};
+use memmap2::Mmap;
+
+use crate::model::SetResponse;
+
+// Name of the index file inside the output directory
+pub const NAME_INDEX_FILENAME: &str = "name_index.bin";
+
+// Separates the fields of an index key; it cannot appear in card names, ids or set codes
+const SEPARATOR: char = '\0';
+
+/// A card of the index, as printed in one set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub card_id: String,
+    pub set_code: String,
+}
+
+impl Entry {
+    /// Reads an entry back from its index key.
+    fn from_key(key: &[u8]) -> Option<Entry> {
+        let key = std::str::from_utf8(key).ok()?;
+        let mut fields = key.split(SEPARATOR).skip(1);
+        Some(Entry {
+            name: fields.next()?.to_string(),
+            card_id: fields.next()?.to_string(),
+            set_code: fields.next()?.to_string(),
+        })
+    }
+}
+
+/// Writes the name index of the given sets into the output directory.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `sets` - The set codes and cards to index.
+///
+/// # Returns
+/// A `Result` indicating success or an error.
+pub fn write<'a>(
+    base_output_dir: &str,
+    sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+) -> Result<(), Box<dyn Error>> {
+    let mut keys: Vec<String> = sets
+        .into_iter()
+        .flat_map(|(set_code, set)| {
+            set.results.iter().map(move |card| {
+                [card.name.to_lowercase().as_str(), &card.name, &card.card_id, set_code]
+                    .join(&SEPARATOR.to_string())
+            })
+        })
+        .collect();
+    keys.sort_unstable();
+    keys.dedup();
+    let index = Set::from_iter(keys)?;
+    let path = Path::new(base_output_dir).join(NAME_INDEX_FILENAME);
+    super::write_atomically(&path, index.as_fst().as_bytes())
+}
+
+/// The name index of an output directory, memory mapped.
+pub struct NameIndex {
+    set: Set<Mmap>,
+}
+
+impl NameIndex {
+    /// Opens the name index of an output directory.
+    ///
+    /// # Returns
+    /// A `Result` containing the index, `None` if the directory has none yet, or an error if
+    /// the file cannot be read or is not an index.
+    pub fn open(base_output_dir: &str) -> Result<Option<Self>, Box<dyn Error>> {
+        let path = Path::new(base_output_dir).join(NAME_INDEX_FILENAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(&path)?;
+        // SAFETY: the index is only ever replaced by renaming a new file over it, never
+        // modified in place, so the mapped contents cannot change while they are read.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let set = Set::new(mmap)
+            .map_err(|e| format!("Error: Invalid name index {}: {}", path.display(), e))?;
+        Ok(Some(NameIndex { set }))
+    }
+
+    /// Every entry of the index, ordered by name.
+    pub fn entries(&self) -> Vec<Entry> {
+        let mut stream = self.set.stream();
+        let mut entries = Vec::new();
+        while let Some(key) = stream.next() {
+            entries.extend(Entry::from_key(key));
+        }
+        entries
+    }
+
+    /// The entries of the cards named exactly `name`, ignoring case.
+    pub fn lookup(&self, name: &str) -> Vec<Entry> {
+        let start = format!("{}{}", name.trim().to_lowercase(), SEPARATOR);
+        let end = format!("{}\u{1}", name.trim().to_lowercase());
+        let mut stream = self.set.range().ge(&start).lt(&end).into_stream();
+        let mut entries = Vec::new();
+        while let Some(key) = stream.next() {
+            entries.extend(Entry::from_key(key));
+        }
+        entries
+    }
+
+    /// Resolves a card id or name the way `card show` does: an exact id first, then an exact
+    /// name, then names containing the query, all ignoring case.
+    ///
+    /// # Returns
+    /// The entries of every matching card, in every set it is printed in.
+    pub fn resolve(&self, query: &str) -> Vec<Entry> {
+        let query = query.trim().to_lowercase();
+        let entries = self.entries();
+        let by_id: Vec<Entry> =
+            entries.iter().filter(|entry| entry.card_id.to_lowercase() == query).cloned().collect();
+        if !by_id.is_empty() {
+            return by_id;
+        }
+        let by_name = self.lookup(&query);
+        if !by_name.is_empty() {
+            return by_name;
+        }
+        entries
+            .into_iter()
+            .filter(|entry| entry.name.to_lowercase().contains(&query))
+            .collect()
+    }
+}
@@ -0,0 +1,67 @@
+//! Progress of the current fetch run, kept on disk so an interrupted run can be resumed.
+//!
+//! The state file is written when a run starts and updated after every set. A run that
+//! finishes with every set saved removes it; otherwise `fetch --resume` picks up the sets
+//! that failed or were never reached.
+
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+// Name of the run state file inside the output directory
+pub const RUN_STATE_FILENAME: &str = "run_state.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunState {
+    /// When the interrupted run was first started.
+    pub started_at: String,
+    /// Every set code of the run, in order.
+    pub set_codes: Vec<String>,
+    /// The sets already saved (or found unchanged).
+    pub completed: BTreeSet<String>,
+}
+
+impl RunState {
+    pub fn new(started_at: String, set_codes: Vec<String>) -> Self {
+        RunState { started_at, set_codes, completed: BTreeSet::new() }
+    }
+
+    /// Loads the state of an interrupted run, or `None` if there is nothing to resume.
+    pub fn load(base_output_dir: &str) -> Result<Option<Self>, Box<dyn Error>> {
+        let path = Path::new(base_output_dir).join(RUN_STATE_FILENAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| format!("Error: Invalid run state {}: {}", path.display(), e).into())
+    }
+
+    /// Writes the state into the output directory.
+    pub fn save(&self, base_output_dir: &str) -> Result<(), Box<dyn Error>> {
+        let path = Path::new(base_output_dir).join(RUN_STATE_FILENAME);
+        super::write_atomically(&path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    /// Removes the state file once the run is complete.
+    pub fn remove(base_output_dir: &str) -> Result<(), Box<dyn Error>> {
+        let path = Path::new(base_output_dir).join(RUN_STATE_FILENAME);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// The sets still to fetch, in run order.
+    pub fn remaining(&self) -> Vec<String> {
+        self.set_codes
+            .iter()
+            .filter(|code| !self.completed.contains(*code))
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,251 @@
+//! A full-text search index over the card names and rules text of the fetched sets.
+//!
+//! The index is an inverted index from every word of a card's name, type line, keywords and
+//! text to the cards holding it, with one document per card and set. Building it means parsing
+//! every set, so it is cached in `search_index.json` and rebuilt only when a per-set JSON
+//! file was added, removed or rewritten since.
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use super::OutputFormat;
+use crate::model::SetResponse;
+
+// Name of the cached index inside the output directory
+pub const SEARCH_INDEX_FILENAME: &str = "search_index.json";
+
+// Version of the cached index layout; a cache of another version is rebuilt
+const SEARCH_INDEX_VERSION: u32 = 1;
+
+/// A card of the index, as printed in one set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    pub card_id: String,
+    pub name: String,
+    pub set_code: String,
+    pub rarities: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pitch: Option<String>,
+}
+
+/// A card matching a search, with how many of the query's words are in its name.
+pub struct Hit<'a> {
+    pub document: &'a Document,
+    pub name_matches: usize,
+}
+
+/// The size and modification time of a per-set file the index was built from.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Source {
+    size: u64,
+    modified_ms: u64,
+}
+
+/// The search index of an output directory.
+#[derive(Serialize, Deserialize)]
+pub struct SearchIndex {
+    version: u32,
+    sources: BTreeMap<String, Source>,
+    documents: Vec<Document>,
+    /// Words of card names, each with the documents holding it in ascending order.
+    name_terms: BTreeMap<String, Vec<u32>>,
+    /// Words of type lines, keywords and rules text.
+    text_terms: BTreeMap<String, Vec<u32>>,
+}
+
+impl SearchIndex {
+    /// Builds the index of the given sets.
+    ///
+    /// # Arguments
+    /// * `sets` - The set codes and cards to index.
+    pub fn build<'a>(sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>) -> Self {
+        let mut index = SearchIndex {
+            version: SEARCH_INDEX_VERSION,
+            sources: BTreeMap::new(),
+            documents: Vec::new(),
+            name_terms: BTreeMap::new(),
+            text_terms: BTreeMap::new(),
+        };
+        for (set_code, set) in sets {
+            for card in &set.results {
+                let id = index.documents.len() as u32;
+                let mut rarities: Vec<String> = card
+                    .printings
+                    .iter()
+                    .filter(|printing| {
+                        printing
+                            .set_code
+                            .as_deref()
+                            .is_none_or(|code| code.eq_ignore_ascii_case(set_code))
+                    })
+                    .filter_map(|printing| printing.rarity.clone())
+                    .collect();
+                rarities.sort_unstable();
+                rarities.dedup();
+                index.documents.push(Document {
+                    card_id: card.card_id.clone(),
+                    name: card.name.clone(),
+                    set_code: set_code.to_string(),
+                    rarities,
+                    pitch: card.pitch.clone(),
+                });
+
+                let text = [card.type_text.as_deref(), card.text.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .chain(card.keywords.iter().map(String::as_str));
+                add_terms(&mut index.name_terms, words(&card.name), id);
+                add_terms(&mut index.text_terms, text.flat_map(words), id);
+            }
+        }
+        index
+    }
+
+    /// Opens the cached index of an output directory, rebuilding it from the per-set JSON
+    /// files first when they changed since it was cached.
+    ///
+    /// # Arguments
+    /// * `base_output_dir` - The root output directory.
+    ///
+    /// # Returns
+    /// A `Result` containing the index, or an error if there are no fetched sets to build it
+    /// from.
+    pub fn open(base_output_dir: &str) -> Result<Self, Box<dyn Error>> {
+        let sources = sources(base_output_dir)?;
+        let path = Path::new(base_output_dir).join(SEARCH_INDEX_FILENAME);
+        if let Ok(content) = fs::read(&path)
+            && let Ok(index) = serde_json::from_slice::<SearchIndex>(&content)
+            && index.version == SEARCH_INDEX_VERSION
+            && index.sources == sources
+        {
+            debug!("Using the cached search index {}.", path.display());
+            return Ok(index);
+        }
+
+        debug!("Building the search index of {}.", base_output_dir);
+        let local_sets = super::load_fetched_sets(base_output_dir)?;
+        let sets = local_sets.iter().map(|local| (local.set_code.as_str(), &local.set));
+        let mut index = SearchIndex::build(sets);
+        index.sources = sources;
+        // Searching works without the cache, it is only slower next time
+        let saved = serde_json::to_vec(&index)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|content| super::write_atomically(&path, &content));
+        if let Err(e) = saved {
+            warn!("Could not cache the search index in {}: {}", path.display(), e);
+        }
+        Ok(index)
+    }
+
+    /// Finds the cards holding every word of a query, in their name or text. A word also
+    /// matches longer words it starts, so `dom` finds `dominate`.
+    ///
+    /// # Returns
+    /// The matching cards, those with more query words in their name first, then by name and
+    /// set code.
+    pub fn search(&self, query: &str) -> Vec<Hit<'_>> {
+        let query = words(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut matching: Option<HashSet<u32>> = None;
+        let mut name_matches = vec![0; self.documents.len()];
+        for word in &query {
+            let in_names = prefixed(&self.name_terms, word);
+            for id in &in_names {
+                name_matches[*id as usize] += 1;
+            }
+            let in_text = prefixed(&self.text_terms, word);
+            let found: HashSet<u32> = in_names.union(&in_text).copied().collect();
+            matching = Some(match matching {
+                Some(matching) => matching.intersection(&found).copied().collect(),
+                None => found,
+            });
+        }
+
+        let mut hits: Vec<Hit> = matching
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| Hit {
+                document: &self.documents[id as usize],
+                name_matches: name_matches[id as usize],
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.name_matches
+                .cmp(&a.name_matches)
+                .then_with(|| a.document.name.cmp(&b.document.name))
+                .then_with(|| a.document.set_code.cmp(&b.document.set_code))
+        });
+        hits
+    }
+}
+
+/// Splits text into lowercase words, dropping punctuation and markup.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Records that document `id` holds the given words.
+fn add_terms(
+    terms: &mut BTreeMap<String, Vec<u32>>,
+    words: impl IntoIterator<Item = String>,
+    id: u32,
+) {
+    for word in words {
+        let postings = terms.entry(word).or_default();
+        if postings.last() != Some(&id) {
+            postings.push(id);
+        }
+    }
+}
+
+/// The documents holding a word starting with `prefix`.
+fn prefixed(terms: &BTreeMap<String, Vec<u32>>, prefix: &str) -> HashSet<u32> {
+    terms
+        .range(prefix.to_string()..)
+        .take_while(|(term, _)| term.starts_with(prefix))
+        .flat_map(|(_, postings)| postings.iter().copied())
+        .collect()
+}
+
+/// The size and modification time of every per-set JSON file, by set code.
+fn sources(base_output_dir: &str) -> Result<BTreeMap<String, Source>, Box<dyn Error>> {
+    let json_output_dir = Path::new(base_output_dir).join(OutputFormat::Json.name());
+    if !json_output_dir.exists() {
+        return Err(format!(
+            "Error: No fetched sets found in '{}'. Run 'fab-scrapper fetch' first.",
+            json_output_dir.display()
+        )
+        .into());
+    }
+    let mut sources = BTreeMap::new();
+    for entry in fs::read_dir(&json_output_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(set_code) = file_name
+            .to_str()
+            .and_then(|name| super::compression::strip_extension(name).strip_suffix("_cards.json"))
+        else {
+            continue;
+        };
+        let metadata = entry.metadata()?;
+        let modified_ms = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis() as u64)
+            .unwrap_or_default();
+        sources.insert(set_code.to_string(), Source { size: metadata.len(), modified_ms });
+    }
+    Ok(sources)
+}
@@ -0,0 +1,81 @@
+//! The previous version of every set changed by the latest fetch, kept for `fab-scrapper diff`.
+//!
+//! A fetch empties the snapshot when it starts and copies a set's JSON output into it right
+//! before overwriting it with different data. A set fetched for the first time is recorded as
+//! an empty listing, so the snapshot always holds exactly the sets the latest run changed.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::model::SetResponse;
+
+// Folder the snapshot is kept in, inside the output directory
+pub const SNAPSHOT_DIR: &str = "previous";
+
+// What a set without any earlier output is recorded as
+const EMPTY_SET: &str = "{\"results\":[]}";
+
+fn snapshot_filename(base_output_dir: &str, set_code: &str) -> PathBuf {
+    Path::new(base_output_dir).join(SNAPSHOT_DIR).join(format!("{}_cards.json", set_code))
+}
+
+/// Empties the snapshot at the start of a run.
+pub fn clear(base_output_dir: &str) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(base_output_dir).join(SNAPSHOT_DIR);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    Ok(())
+}
+
+/// Records the version of a set that is about to be overwritten.
+///
+/// Only the first call for a set within a run is recorded, so re-fetching a set after a
+/// corrupt download keeps the version from before the run.
+///
+/// # Arguments
+/// * `base_output_dir` - The root output directory.
+/// * `set_code` - The set code (e.g., "WTR").
+/// * `previous_json` - The set's current JSON output, or `None` if it has none yet.
+pub fn record(
+    base_output_dir: &str,
+    set_code: &str,
+    previous_json: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let filename = snapshot_filename(base_output_dir, set_code);
+    if filename.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(Path::new(base_output_dir).join(SNAPSHOT_DIR))?;
+    super::write_atomically(&filename, previous_json.unwrap_or(EMPTY_SET).as_bytes())
+}
+
+/// Loads every set recorded in the snapshot, ordered by set code.
+///
+/// # Returns
+/// A `Result` containing pairs of set code and the set's previous cards; empty if the latest
+/// run changed nothing.
+pub fn load(base_output_dir: &str) -> Result<Vec<(String, SetResponse)>, Box<dyn Error>> {
+    let dir = Path::new(base_output_dir).join(SNAPSHOT_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut sets = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let Some(set_code) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix("_cards.json"))
+        else {
+            continue;
+        };
+        let set = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+        sets.push((set_code.to_string(), set));
+    }
+    sets.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(sets)
+}
@@ -0,0 +1,127 @@
+//! "Did you mean ...?" suggestions for set codes and card names that do not resolve.
+
+use std::collections::HashSet;
+
+// Most suggestions offered for one unknown value
+const MAX_SUGGESTIONS: usize = 3;
+
+/// The number of single-character insertions, deletions, substitutions and swaps of adjacent
+/// characters turning `a` into `b`, ignoring case.
+///
+/// This is the Levenshtein distance extended with transpositions, as swapped letters are the
+/// most common typo in short set codes (`MNO` for `MON`).
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+
+    // distances[i][j] is the distance between the first i characters of a and j of b
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    distances[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut distance =
+                substitution.min(distances[i - 1][j] + 1).min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+/// The candidates closest to `input`, best first.
+///
+/// Only candidates within a third of the input's length (at least one edit) are offered, so
+/// unrelated values are never suggested.
+///
+/// # Arguments
+/// * `input` - The value that did not resolve.
+/// * `candidates` - The valid values.
+pub fn closest<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    let input = input.trim();
+    let max_distance = (input.chars().count() / 3).max(1);
+    let mut scored: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(input, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    scored.sort();
+    // Candidates differing only in case are offered once, as the best of them; they need not
+    // sort next to each other
+    let mut offered = HashSet::new();
+    scored.retain(|(_, candidate)| offered.insert(candidate.to_lowercase()));
+    scored.into_iter().take(MAX_SUGGESTIONS).map(|(_, candidate)| candidate).collect()
+}
+
+/// A sentence suggesting the closest candidates, or an empty string if none is close.
+///
+/// # Returns
+/// E.g. ` Did you mean 'MON'?`, with a leading space so it can be appended to a message.
+pub fn did_you_mean<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> String {
+    let quoted: Vec<String> = closest(input, candidates)
+        .into_iter()
+        .map(|candidate| format!("'{}'", candidate))
+        .collect();
+    match quoted.as_slice() {
+        [] => String::new(),
+        [only] => format!(" Did you mean {}?", only),
+        [rest @ .., last] => format!(" Did you mean {} or {}?", rest.join(", "), last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_swapped_letters_as_one_edit_and_ignores_case() {
+        assert_eq!(edit_distance("MNO", "MON"), 1);
+        assert_eq!(edit_distance("mon", "MON"), 0);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "ARC"), 3);
+        assert_eq!(edit_distance("WTR", ""), 3);
+    }
+
+    #[test]
+    fn only_offers_candidates_within_a_third_of_the_input() {
+        // A three-letter code allows a single edit
+        assert_eq!(closest("MNO", ["MON", "MOX", "ELE"]), ["MON"]);
+        // Six letters allow two
+        assert_eq!(closest("abcdef", ["abcxyz", "abcdxy", "abcdef"]), ["abcdef", "abcdxy"]);
+        // Short inputs still allow one edit
+        assert_eq!(closest("a", ["b", "bc"]), ["b"]);
+        assert!(closest(" WTR ", ["WTR"]).contains(&"WTR"));
+    }
+
+    #[test]
+    fn offers_at_most_max_suggestions_best_first() {
+        // Four candidates are within one edit of CRU
+        let candidates = ["CRX", "ARC", "CRU", "CRA", "WTR", "ARU"];
+        let suggestions = closest("CRU", candidates);
+        assert_eq!(suggestions.len(), MAX_SUGGESTIONS);
+        assert_eq!(suggestions, ["CRU", "ARU", "CRA"]);
+    }
+
+    #[test]
+    fn case_variants_are_suggested_once() {
+        // "MOO" sorts between the two spellings of MON
+        assert_eq!(closest("MNO", ["MON", "MOO", "Mon"]), ["MON", "MOO"]);
+        assert_eq!(closest("mno", ["Mon", "mon"]), ["Mon"]);
+    }
+
+    #[test]
+    fn phrases_one_two_or_three_suggestions() {
+        assert_eq!(did_you_mean("XYZ", ["WTR"]), "");
+        assert_eq!(did_you_mean("MNO", ["MON"]), " Did you mean 'MON'?");
+        assert_eq!(did_you_mean("AR", ["ARC", "AR", "WTR"]), " Did you mean 'AR' or 'ARC'?");
+        assert_eq!(
+            did_you_mean("ELE", ["ELD", "ELE", "EVR", "HLE"]),
+            " Did you mean 'ELE', 'ELD' or 'HLE'?"
+        );
+    }
+}
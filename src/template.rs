@@ -0,0 +1,303 @@
+//! Templates turning cards into the human-readable listings of the `txt` output format.
+//!
+//! A template is plain text with `{{field}}` placeholders, rendered once per card:
+//!
+//! ```text
+//! {{print_id}} {{name}} ({{pitch|-}}) - {{type_text}}
+//! ```
+//!
+//! `{{field|fallback}}` renders the fallback when the card has no value for the field. Fields
+//! of the API that the model does not know are available as `{{extra.<name>}}`, e.g.
+//! `{{extra.flavor_text}}`. Unknown fields and unclosed placeholders are rejected when the
+//! template is parsed, before anything is fetched.
+
+use std::error::Error;
+
+use serde_json::Value;
+
+use crate::model::{Card, Printing, SetResponse};
+use crate::suggest;
+
+// Template of the `txt` files unless another one is configured
+pub const DEFAULT_TEMPLATE: &str = "{{print_id}} {{name}} (pitch {{pitch|-}}, cost {{cost|-}}) \
+                                    - {{type_text}}\n{{text}}";
+
+// Fields a placeholder may name, besides `extra.<name>`
+const FIELDS: [&str; 16] = [
+    "card_id",
+    "name",
+    "pitch",
+    "cost",
+    "power",
+    "defense",
+    "health",
+    "intelligence",
+    "type_text",
+    "types",
+    "keywords",
+    "text",
+    "set_code",
+    "print_id",
+    "rarity",
+    "artists",
+];
+
+// Prefix of the placeholders naming a field outside the model
+const EXTRA_PREFIX: &str = "extra.";
+
+/// A piece of a parsed template.
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Field { name: String, fallback: String },
+}
+
+/// A parsed card template.
+#[derive(Debug, Clone)]
+pub struct CardTemplate {
+    segments: Vec<Segment>,
+}
+
+impl Default for CardTemplate {
+    fn default() -> Self {
+        CardTemplate::parse(DEFAULT_TEMPLATE).expect("the default template parses")
+    }
+}
+
+impl CardTemplate {
+    /// Parses a template.
+    ///
+    /// # Arguments
+    /// * `source` - The template text.
+    ///
+    /// # Returns
+    /// A `Result` containing the template, or an error naming the unclosed placeholder or the
+    /// unknown field.
+    pub fn parse(source: &str) -> Result<Self, Box<dyn Error>> {
+        let mut segments = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let after = &rest[start + 2..];
+            let end = after.find("}}").ok_or_else(|| {
+                format!("Error: Unclosed placeholder in template at '{}'", &rest[start..])
+            })?;
+            let placeholder = &after[..end];
+            let (name, fallback) = placeholder.split_once('|').unwrap_or((placeholder, ""));
+            let name = name.trim();
+            let extra = name.strip_prefix(EXTRA_PREFIX).is_some_and(|extra| !extra.is_empty());
+            if !FIELDS.contains(&name) && !extra {
+                return Err(format!(
+                    "Error: Unknown field '{}' in template.{} Fields of the API outside the model \
+                     are named like {{{{extra.flavor_text}}}}.",
+                    name,
+                    suggest::did_you_mean(name, FIELDS)
+                )
+                .into());
+            }
+            let fallback = fallback.to_string();
+            segments.push(Segment::Field { name: name.to_string(), fallback });
+            rest = &after[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        Ok(CardTemplate { segments })
+    }
+
+    /// Renders the template for one card.
+    ///
+    /// # Arguments
+    /// * `card` - The card.
+    /// * `set_code` - The set being listed; `print_id`, `rarity` and `artists` are taken from
+    ///   the card's first printing in it.
+    pub fn render_card(&self, card: &Card, set_code: &str) -> String {
+        let printing = card
+            .printings
+            .iter()
+            .find(|printing| {
+                printing.set_code.as_deref().is_none_or(|code| code.eq_ignore_ascii_case(set_code))
+            })
+            .or(card.printings.first());
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Field { name, fallback } => {
+                    let value = field(card, printing, set_code, name);
+                    match value.as_deref().map(str::trim) {
+                        Some(value) if !value.is_empty() => rendered.push_str(value),
+                        _ => rendered.push_str(fallback),
+                    }
+                }
+            }
+        }
+        rendered
+    }
+
+    /// Renders the listing of a set: a heading, then every card. Cards rendering to a single
+    /// line are listed one per line, others are separated by a blank line.
+    ///
+    /// # Arguments
+    /// * `set_code` - The set code (e.g., "WTR").
+    /// * `set` - The set's cards.
+    pub fn render_set(&self, set_code: &str, set: &SetResponse) -> String {
+        let cards: Vec<String> =
+            set.results.iter().map(|card| self.render_card(card, set_code)).collect();
+        let separator = if cards.iter().any(|card| card.contains('\n')) { "\n\n" } else { "\n" };
+        let mut listing = format!("{}: {} card(s)\n\n", set_code, set.results.len());
+        listing.push_str(&cards.join(separator));
+        listing.push('\n');
+        listing
+    }
+
+    /// Renders the listings of several sets one after the other, ordered by set code.
+    pub fn render_sets<'a>(
+        &self,
+        sets: impl IntoIterator<Item = (&'a str, &'a SetResponse)>,
+    ) -> String {
+        let mut sets: Vec<(&str, &SetResponse)> = sets.into_iter().collect();
+        sets.sort_by_key(|(set_code, _)| *set_code);
+        let listings: Vec<String> =
+            sets.into_iter().map(|(set_code, set)| self.render_set(set_code, set)).collect();
+        listings.join("\n")
+    }
+}
+
+/// The value of a field of a card, if it has one.
+fn field(card: &Card, printing: Option<&Printing>, set_code: &str, name: &str) -> Option<String> {
+    let join = |values: &[String]| Some(values.join(", "));
+    match name {
+        "card_id" => Some(card.card_id.clone()),
+        "name" => Some(card.name.clone()),
+        "pitch" => card.pitch.clone(),
+        "cost" => card.cost.clone(),
+        "power" => card.power.clone(),
+        "defense" => card.defense.clone(),
+        "health" => card.health.clone(),
+        "intelligence" => card.intelligence.clone(),
+        "type_text" => card.type_text.clone(),
+        "types" => join(&card.types),
+        "keywords" => join(&card.keywords),
+        "text" => card.text.clone(),
+        "set_code" => Some(set_code.to_string()),
+        "print_id" => printing.map(|printing| printing.print_id.clone()),
+        "rarity" => printing.and_then(|printing| printing.rarity.clone()),
+        "artists" => printing.and_then(|printing| join(&printing.artists)),
+        _ => {
+            let value = card.extra.get(name.strip_prefix(EXTRA_PREFIX)?)?;
+            match value {
+                Value::Null => None,
+                Value::String(text) => Some(text.clone()),
+                Value::Array(values) => Some(
+                    values
+                        .iter()
+                        .map(|value| match value {
+                            Value::String(text) => text.clone(),
+                            other => other.to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+                other => Some(other.to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn card(value: Value) -> Card {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn render(source: &str, card: &Card, set_code: &str) -> String {
+        CardTemplate::parse(source).unwrap().render_card(card, set_code)
+    }
+
+    fn snatch() -> Card {
+        card(json!({
+            "card_id": "snatch-red",
+            "name": "Snatch",
+            "pitch": "1",
+            "cost": "0",
+            "type_text": "Generic Action - Attack",
+            "types": ["Generic", "Action", "Attack"],
+            "text": "If Snatch hits, draw a card.",
+            "flavor_text": "Mine!",
+            "watermarks": ["Gold", 3],
+            "errata": null,
+            "printings": [
+                {
+                    "print_id": "WTR163",
+                    "set_code": "WTR",
+                    "rarity": "C",
+                    "artists": ["Kaja Foglio"]
+                },
+                {"print_id": "U-WTR163", "set_code": "U-WTR", "rarity": "C"},
+                {"print_id": "1HP358", "set_code": "1HP", "rarity": "P", "artists": ["Jessada"]}
+            ]
+        }))
+    }
+
+    #[test]
+    fn rejects_unknown_fields_with_a_suggestion() {
+        let error = CardTemplate::parse("{{print_id}} {{nmae}}").unwrap_err().to_string();
+        assert!(error.contains("Unknown field 'nmae'"), "{}", error);
+        assert!(error.contains("Did you mean 'name'?"), "{}", error);
+        assert!(CardTemplate::parse("{{extra.}}").is_err());
+    }
+
+    #[test]
+    fn rejects_unclosed_placeholders() {
+        let error = CardTemplate::parse("{{name}} {{pitch").unwrap_err().to_string();
+        assert!(error.contains("Unclosed placeholder"), "{}", error);
+        assert!(error.contains("'{{pitch'"), "{}", error);
+    }
+
+    #[test]
+    fn renders_the_fallback_of_empty_and_missing_values() {
+        let snatch = snatch();
+        assert_eq!(render("{{cost|-}}/{{power|-}}", &snatch, "WTR"), "0/-");
+        assert_eq!(render("{{power}}.", &snatch, "WTR"), ".");
+        let blank = card(json!({"card_id": "a", "name": "A", "text": "  ", "printings": []}));
+        let template = "{{text|no text}}, {{rarity|no rarity}}, {{keywords|none}}";
+        assert_eq!(render(template, &blank, "WTR"), "no text, no rarity, none");
+    }
+
+    #[test]
+    fn renders_extra_fields() {
+        let snatch = snatch();
+        assert_eq!(render("{{extra.flavor_text}}", &snatch, "WTR"), "Mine!");
+        assert_eq!(render("{{extra.watermarks}}", &snatch, "WTR"), "Gold, 3");
+        assert_eq!(render("{{extra.errata|none}}", &snatch, "WTR"), "none");
+        assert_eq!(render("{{extra.missing|?}}", &snatch, "WTR"), "?");
+    }
+
+    #[test]
+    fn renders_the_printing_of_the_listed_set() {
+        let snatch = snatch();
+        let template = "{{set_code}} {{print_id}} {{rarity}} {{artists|unknown}}";
+        assert_eq!(render(template, &snatch, "WTR"), "WTR WTR163 C Kaja Foglio");
+        assert_eq!(render(template, &snatch, "u-wtr"), "u-wtr U-WTR163 C unknown");
+        assert_eq!(render(template, &snatch, "1HP"), "1HP 1HP358 P Jessada");
+        // A set the card has no printing in falls back to its first printing
+        assert_eq!(render(template, &snatch, "ARC"), "ARC WTR163 C Kaja Foglio");
+    }
+
+    #[test]
+    fn default_template_renders_a_card() {
+        let rendered = CardTemplate::default().render_card(&snatch(), "WTR");
+        let expected = "WTR163 Snatch (pitch 1, cost 0) - Generic Action - Attack\n\
+                        If Snatch hits, draw a card.";
+        assert_eq!(rendered, expected);
+        let set: SetResponse = serde_json::from_value(json!({"results": [snatch()]})).unwrap();
+        let listing = CardTemplate::default().render_set("WTR", &set);
+        assert!(listing.starts_with("WTR: 1 card(s)\n\nWTR163 Snatch"), "{}", listing);
+    }
+}
@@ -0,0 +1,412 @@
+//! Validation of fetched card data: the invariants the API is expected to keep, checked before
+//! anything is archived.
+//!
+//! The API occasionally ships malformed entries — a card without a name, a pitch of 4, a
+//! negative cost, two cards claiming the same collector number of a set, an image URL that is
+//! not a URL. Every fetch checks the sets it ends up with, and `fab-scrapper validate` checks
+//! the sets of an output directory; either way, the anomalies found are written to
+//! `validation_report.json` rather than archived silently.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::model::{Card, Printing, SetResponse};
+
+// Report of the latest validation, inside the output directory
+pub const VALIDATION_REPORT_FILENAME: &str = "validation_report.json";
+
+/// An invariant of the card data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    /// A card id, name, print id or the printings of a card are missing
+    MissingField,
+    /// The pitch is not 1, 2 or 3
+    Pitch,
+    /// The cost is negative, or neither a number nor an X cost
+    Cost,
+    /// Two cards of a set share a collector number
+    DuplicateNumber,
+    /// An image URL is not a well-formed http(s) URL
+    ImageUrl,
+}
+
+impl Check {
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::MissingField => "missing_field",
+            Check::Pitch => "pitch",
+            Check::Cost => "cost",
+            Check::DuplicateNumber => "duplicate_number",
+            Check::ImageUrl => "image_url",
+        }
+    }
+}
+
+impl std::fmt::Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A card or printing breaking one of the checks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Anomaly {
+    pub set_code: String,
+    pub card_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub print_id: Option<String>,
+    pub check: Check,
+    pub message: String,
+}
+
+/// The anomalies of a set of fetched sets, as written to `validation_report.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub generated_at: String,
+    pub sets: usize,
+    pub cards: usize,
+    /// The number of anomalies of each check, leaving out the checks every card passed.
+    pub counts: BTreeMap<Check, usize>,
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl ValidationReport {
+    /// Validates sets in parallel and collects their anomalies, ordered by set code.
+    ///
+    /// # Arguments
+    /// * `generated_at` - When the report is made, as an RFC 3339 timestamp.
+    /// * `sets` - The set codes and cards to validate.
+    pub fn new(generated_at: String, sets: &[(&str, &SetResponse)]) -> Self {
+        let mut sets: Vec<(&str, &SetResponse)> = sets.to_vec();
+        sets.sort_by_key(|(set_code, _)| *set_code);
+        let anomalies: Vec<Anomaly> = sets
+            .par_iter()
+            .flat_map_iter(|(set_code, set)| validate_set(set_code, set))
+            .collect();
+        let mut counts = BTreeMap::new();
+        for anomaly in &anomalies {
+            *counts.entry(anomaly.check).or_insert(0) += 1;
+        }
+        ValidationReport {
+            generated_at,
+            sets: sets.len(),
+            cards: sets.iter().map(|(_, set)| set.results.len()).sum(),
+            counts,
+            anomalies,
+        }
+    }
+
+    /// The number of anomalies of each set that has any, ordered by set code.
+    pub fn by_set(&self) -> BTreeMap<&str, usize> {
+        let mut by_set = BTreeMap::new();
+        for anomaly in &self.anomalies {
+            *by_set.entry(anomaly.set_code.as_str()).or_insert(0) += 1;
+        }
+        by_set
+    }
+
+    /// Writes the report to the output directory, replacing the previous one.
+    ///
+    /// # Arguments
+    /// * `base_output_dir` - The root output directory.
+    ///
+    /// # Returns
+    /// A `Result` indicating success or an error.
+    pub fn write(&self, base_output_dir: &str) -> Result<(), Box<dyn Error>> {
+        let path = Path::new(base_output_dir).join(VALIDATION_REPORT_FILENAME);
+        crate::storage::write_atomically(&path, serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Checks every card of a set.
+///
+/// # Arguments
+/// * `set_code` - The code of the set, which collector numbers are checked within.
+/// * `set` - The cards of the set.
+///
+/// # Returns
+/// The anomalies found, in the order of the cards.
+pub fn validate_set(set_code: &str, set: &SetResponse) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    // Card ids of each collector number of the set, in the order they were listed
+    let mut numbers: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+    for card in &set.results {
+        validate_card(set_code, card, &mut anomalies);
+        for printing in card.printings.iter().filter(|printing| is_of_set(printing, set_code)) {
+            if let Some(number) = printing.collector_number() {
+                let cards = numbers.entry(number).or_default();
+                if !cards.iter().any(|(card_id, _)| *card_id == card.card_id) {
+                    cards.push((&card.card_id, &printing.print_id));
+                }
+            }
+        }
+    }
+    for (number, cards) in numbers {
+        let Some(((first, _), others)) = cards.split_first() else {
+            continue;
+        };
+        for (card_id, print_id) in others {
+            anomalies.push(Anomaly {
+                set_code: set_code.to_string(),
+                card_id: card_id.to_string(),
+                print_id: Some(print_id.to_string()),
+                check: Check::DuplicateNumber,
+                message: format!("collector number {} is also used by {}", number, first),
+            });
+        }
+    }
+    anomalies
+}
+
+/// Checks the fields of a card and of its printings.
+fn validate_card(set_code: &str, card: &Card, anomalies: &mut Vec<Anomaly>) {
+    let mut report = |print_id: Option<&str>, check: Check, message: String| {
+        anomalies.push(Anomaly {
+            set_code: set_code.to_string(),
+            card_id: card.card_id.clone(),
+            print_id: print_id.map(str::to_string),
+            check,
+            message,
+        });
+    };
+
+    for (field, value) in [("card_id", &card.card_id), ("name", &card.name)] {
+        if value.trim().is_empty() {
+            report(None, Check::MissingField, format!("{} is empty", field));
+        }
+    }
+    if card.printings.is_empty() {
+        report(None, Check::MissingField, "the card has no printings".to_string());
+    }
+    if let Some(pitch) = &card.pitch
+        && !matches!(pitch.trim(), "" | "1" | "2" | "3")
+    {
+        report(None, Check::Pitch, format!("pitch '{}' is not 1, 2 or 3", pitch));
+    }
+    if let Some(cost) = &card.cost
+        && let Some(problem) = cost_problem(cost)
+    {
+        report(None, Check::Cost, format!("cost '{}' {}", cost, problem));
+    }
+
+    for printing in &card.printings {
+        if printing.print_id.trim().is_empty() {
+            report(None, Check::MissingField, "a printing has an empty print_id".to_string());
+            continue;
+        }
+        if let Some(url) = &printing.image_url
+            && let Some(problem) = image_url_problem(url)
+        {
+            let message = format!("image URL '{}' {}", url, problem);
+            report(Some(&printing.print_id), Check::ImageUrl, message);
+        }
+    }
+}
+
+/// Whether a printing belongs to a set, taking printings without a set code as the set's own.
+fn is_of_set(printing: &Printing, set_code: &str) -> bool {
+    printing.set_code.as_deref().is_none_or(|code| code.eq_ignore_ascii_case(set_code))
+}
+
+/// What is wrong with a cost, if anything. Costs like "X" or "XX" are fine.
+fn cost_problem(cost: &str) -> Option<&'static str> {
+    let cost = cost.trim();
+    if cost.is_empty() || cost.chars().all(|c| c.eq_ignore_ascii_case(&'x') || c == '*') {
+        return None;
+    }
+    match cost.parse::<i64>() {
+        Ok(value) if value < 0 => Some("is negative"),
+        Ok(_) => None,
+        Err(_) => Some("is not a number"),
+    }
+}
+
+/// What is wrong with an image URL, if anything.
+fn image_url_problem(url: &str) -> Option<&'static str> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if !matches!(parsed.scheme(), "http" | "https") => Some("is not http(s)"),
+        Ok(parsed) if parsed.host_str().is_none_or(str::is_empty) => Some("has no host"),
+        Ok(_) => None,
+        Err(_) => Some("does not parse"),
+    }
+}
+
+/// Renders a report as text: the anomalies found, then their number by check and by set.
+///
+/// # Arguments
+/// * `report` - The report to render.
+/// * `limit` - The most anomalies to list, or `None` for all of them.
+pub fn render_text(report: &ValidationReport, limit: Option<usize>) -> String {
+    let mut text = String::new();
+    let shown = limit.unwrap_or(report.anomalies.len()).min(report.anomalies.len());
+    for anomaly in &report.anomalies[..shown] {
+        text.push_str(&format!(
+            "{:<6} {:<40} {:<10} {:<16} {}\n",
+            anomaly.set_code,
+            anomaly.card_id,
+            anomaly.print_id.as_deref().unwrap_or("-"),
+            anomaly.check.name(),
+            anomaly.message
+        ));
+    }
+    if shown < report.anomalies.len() {
+        text.push_str(&format!("... and {} more\n", report.anomalies.len() - shown));
+    }
+    if !report.anomalies.is_empty() {
+        text.push('\n');
+    }
+
+    text.push_str(&format!(
+        "{} anomal{} in {} card(s) of {} set(s)\n",
+        report.anomalies.len(),
+        if report.anomalies.len() == 1 { "y" } else { "ies" },
+        report.cards,
+        report.sets
+    ));
+    for (check, count) in &report.counts {
+        text.push_str(&format!("  {:<16} {}\n", check.name(), count));
+    }
+    let by_set = report.by_set();
+    if !by_set.is_empty() {
+        let sets: Vec<String> =
+            by_set.iter().map(|(set_code, count)| format!("{} ({})", set_code, count)).collect();
+        text.push_str(&format!("Sets with anomalies: {}\n", sets.join(", ")));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn set(cards: Value) -> SetResponse {
+        serde_json::from_value(json!({ "results": cards })).unwrap()
+    }
+
+    /// A card passing every check, with some of its fields replaced.
+    fn card(card_id: &str, fields: Value) -> Value {
+        let mut card = json!({
+            "card_id": card_id,
+            "name": "Snatch",
+            "pitch": 1,
+            "cost": 0,
+            "printings": [{
+                "print_id": "WTR163",
+                "set_code": "WTR",
+                "image_url": "https://storage.googleapis.com/fabmaster/media/images/WTR163.png"
+            }]
+        });
+        for (field, value) in fields.as_object().unwrap() {
+            card[field] = value.clone();
+        }
+        card
+    }
+
+    /// The card id, print id and check of every anomaly of a set of WTR.
+    fn anomalies(cards: Value) -> Vec<(String, Option<String>, Check)> {
+        validate_set("WTR", &set(cards))
+            .into_iter()
+            .map(|anomaly| (anomaly.card_id, anomaly.print_id, anomaly.check))
+            .collect()
+    }
+
+    fn checks(card: Value) -> Vec<Check> {
+        anomalies(json!([card])).into_iter().map(|(_, _, check)| check).collect()
+    }
+
+    #[test]
+    fn a_valid_card_has_no_anomalies() {
+        assert!(checks(card("snatch-red", json!({}))).is_empty());
+    }
+
+    #[test]
+    fn reports_missing_fields() {
+        assert_eq!(checks(card("snatch-red", json!({"name": " "}))), [Check::MissingField]);
+        assert_eq!(checks(card("", json!({}))), [Check::MissingField]);
+        assert_eq!(checks(card("snatch-red", json!({"printings": []}))), [Check::MissingField]);
+        let printings = json!({"printings": [{"print_id": ""}, {"print_id": "WTR163"}]});
+        assert_eq!(checks(card("snatch-red", printings)), [Check::MissingField]);
+    }
+
+    #[test]
+    fn reports_pitches_other_than_1_2_or_3() {
+        for pitch in [json!(1), json!("2"), json!(3), json!(""), Value::Null] {
+            assert!(checks(card("snatch", json!({ "pitch": pitch }))).is_empty());
+        }
+        for pitch in [json!(0), json!(4), json!("red")] {
+            assert_eq!(checks(card("snatch", json!({ "pitch": pitch }))), [Check::Pitch]);
+        }
+    }
+
+    #[test]
+    fn reports_negative_and_non_numeric_costs() {
+        for cost in [json!(0), json!(3), json!("X"), json!("xx"), json!("*"), Value::Null] {
+            assert!(checks(card("snatch", json!({ "cost": cost }))).is_empty());
+        }
+        for cost in [json!(-1), json!("three"), json!("1X")] {
+            assert_eq!(checks(card("snatch", json!({ "cost": cost }))), [Check::Cost]);
+        }
+    }
+
+    #[test]
+    fn reports_collector_numbers_shared_by_two_cards() {
+        // Two printings of one card, e.g. a foil, and a printing of another set are fine
+        let cards = json!([
+            card("snatch-red", json!({
+                "printings": [
+                    {"print_id": "WTR163"},
+                    {"print_id": "WTR163", "foiling": "R"},
+                    {"print_id": "1HP001", "set_code": "1HP"}
+                ]
+            })),
+            card("energy-potion", json!({"printings": [{"print_id": "WTR001"}]}))
+        ]);
+        assert!(anomalies(cards).is_empty());
+
+        let cards = json!([
+            card("snatch-red", json!({"printings": [{"print_id": "WTR163"}]})),
+            card("snatch-yellow", json!({"printings": [{"print_id": "WTR164"}]})),
+            card("snatch-blue", json!({"printings": [{"print_id": "WTR163", "set_code": "wtr"}]}))
+        ]);
+        let expected =
+            (String::from("snatch-blue"), Some(String::from("WTR163")), Check::DuplicateNumber);
+        assert_eq!(anomalies(cards), [expected]);
+    }
+
+    #[test]
+    fn reports_image_urls_that_are_not_http() {
+        for url in ["http://example.com/WTR163.png", "https://example.com/a.png"] {
+            let printings = json!({"printings": [{"print_id": "WTR163", "image_url": url}]});
+            assert!(checks(card("snatch", printings)).is_empty());
+        }
+        for url in ["ftp://example.com/WTR163.png", "example.com/WTR163.png", "https://"] {
+            let printings = json!({"printings": [{"print_id": "WTR163", "image_url": url}]});
+            assert_eq!(checks(card("snatch", printings)), [Check::ImageUrl], "{}", url);
+        }
+    }
+
+    #[test]
+    fn report_counts_anomalies_by_check_and_set() {
+        let wtr = set(json!([card("snatch", json!({"pitch": 4, "cost": -1}))]));
+        let arc = set(json!([card("", json!({}))]));
+        let clean = set(json!([card("snatch", json!({}))]));
+        let report =
+            ValidationReport::new(String::new(), &[("WTR", &wtr), ("ARC", &arc), ("CRU", &clean)]);
+        assert_eq!((report.sets, report.cards), (3, 3));
+        let counts: Vec<(Check, usize)> = report.counts.clone().into_iter().collect();
+        assert_eq!(counts, [(Check::MissingField, 1), (Check::Pitch, 1), (Check::Cost, 1)]);
+        assert_eq!(report.by_set().into_iter().collect::<Vec<_>>(), [("ARC", 1), ("WTR", 2)]);
+
+        let text = render_text(&report, Some(1));
+        assert!(text.contains("... and 2 more\n"), "{}", text);
+        assert!(text.contains("3 anomalies in 3 card(s) of 3 set(s)\n"), "{}", text);
+        assert!(text.contains("Sets with anomalies: ARC (1), WTR (2)\n"), "{}", text);
+    }
+}
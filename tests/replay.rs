@@ -0,0 +1,112 @@
+//! Runs the command line tool against the recorded responses in `tests/fixtures/api`: a set
+//! paginated over three pages, and a set answered with a 429 and `Retry-After` before its page
+//! and with 304 Not Modified once its ETag is known.
+
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use fab_scrapper::model::SetResponse;
+use serde_json::Value;
+
+// Recorded responses of the WTR and ARC sets and of the sets listing
+const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/api");
+
+/// An empty output directory, removed when the test ends.
+struct OutputDir(PathBuf);
+
+impl OutputDir {
+    fn new(name: &str) -> Self {
+        let dir = format!("fab-scrapper-{}-{}", name, std::process::id());
+        let dir = std::env::temp_dir().join(dir);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        OutputDir(dir)
+    }
+
+    fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+
+    fn read_json(&self, relative: &str) -> Value {
+        let content = fs::read_to_string(self.0.join(relative)).unwrap();
+        serde_json::from_str(&content).unwrap()
+    }
+}
+
+impl Drop for OutputDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs the tool on an output directory, failing the test if it does not succeed.
+fn run(out: &OutputDir, args: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_fab-scrapper"))
+        .args(args)
+        .args(["--out", out.path()])
+        .current_dir(&out.0)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "fab-scrapper {} failed:\n{}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// The report of a set in `run_report.json`.
+fn set_report(out: &OutputDir, set_code: &str) -> Value {
+    let report = out.read_json("run_report.json");
+    let sets = report["sets"].as_array().unwrap();
+    sets.iter().find(|set| set["set_code"] == set_code).unwrap().clone()
+}
+
+fn card_count(path: &Path) -> usize {
+    let set: SetResponse = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+    set.results.len()
+}
+
+#[test]
+fn fetch_writes_and_combines_replayed_sets() {
+    let out = OutputDir::new("replay");
+    let fetch = ["fetch", "--sets", "WTR,ARC", "--fixtures", FIXTURES_DIR, "--retry-delay-ms", "1"];
+    let started = Instant::now();
+    run(&out, &fetch);
+
+    // Every page of WTR is followed, and ARC is fetched once the 429 has been retried after
+    // the second its Retry-After asked for
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert_eq!(card_count(&out.0.join("json/WTR_cards.json")), 7);
+    assert_eq!(card_count(&out.0.join("json/ARC_cards.json")), 3);
+    let wtr = set_report(&out, "WTR");
+    assert_eq!((wtr["status"].as_str(), wtr["pages"].as_u64()), (Some("saved"), Some(3)));
+    let arc = set_report(&out, "ARC");
+    assert_eq!((arc["status"].as_str(), arc["retries"].as_u64()), (Some("saved"), Some(1)));
+    assert!(fs::read_to_string(out.0.join("txt/WTR_cards.txt")).unwrap().contains("WTR Card 1"));
+
+    // The manifest keeps the recorded ETags, which the next incremental run sends
+    let manifest = out.read_json("manifest.json");
+    assert_eq!(manifest["sets"]["WTR"]["etag"], "\"WTR-v1\"");
+    run(&out, &[&fetch[..], &["--incremental"]].concat());
+    for set_code in ["WTR", "ARC"] {
+        // A 304 Not Modified answer is no page at all
+        let report = set_report(&out, set_code);
+        assert_eq!(report["status"], "unchanged");
+        assert_eq!(report["pages"], 0, "{} was not answered with 304 Not Modified", set_code);
+    }
+
+    // The combined file is rebuilt from the per-set files
+    let combined = out.0.join("json/all_sets_combined.json");
+    fs::remove_file(&combined).unwrap();
+    run(&out, &["combine"]);
+    let combined = out.read_json("json/all_sets_combined.json");
+    let content = serde_json::to_string(&combined).unwrap();
+    for card_id in ["wtr-card-1", "arc-card-1"] {
+        assert!(content.contains(card_id), "{} is missing from the combined file", card_id);
+    }
+}